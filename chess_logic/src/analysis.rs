@@ -0,0 +1,236 @@
+use crate::engine::piece_value;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+pub(crate) const ROOK_DIRECTIONS: [(i32, i32); 4] = [(0, -1), (0, 1), (-1, 0), (1, 0)];              // (file, rank) steps, rank 0 is the 8th rank
+pub(crate) const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+const KNIGHT_JUMPS: [(i32, i32); 8] = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+
+// Questions about a position for frontends and teaching tools. Attacks are the tiles a piece could
+// capture on, pins and checks of the pieces left on the board don't matter for them.
+impl GAME {
+    // The enemy pieces giving check to the side to move.
+    pub fn checkers(&self) -> Vec<usize> {
+        match king_tile(&self.board, self.turn) {
+            Some(king_tile) => attackers(&self.board, king_tile, opposite(self.turn)),
+            None => Vec::new(),
+        }
+    }
+
+    // Pieces of color that can't leave the line between their king and an enemy rook, bishop or queen.
+    pub fn pinned_pieces(&self, color: u8) -> Vec<usize> {
+        let king_tile = match king_tile(&self.board, color) {
+            Some(king_tile) => king_tile,
+            None => return Vec::new(),
+        };
+        let mut pinned = Vec::new();
+        for (directions, slider) in [(ROOK_DIRECTIONS, TYPES::ROOK), (BISHOP_DIRECTIONS, TYPES::BISHOP)] {
+            for direction in directions {
+                let mut ray = ray_tiles(king_tile, direction).filter(|tile| self.board[*tile] != TYPES::NONE);
+                let first = match ray.next() {
+                    Some(tile) if self.board[tile] & color > 0 => tile,
+                    _ => continue,
+                };
+                if let Some(second) = ray.next() {
+                    let piece = self.board[second];
+                    if piece & opposite(color) > 0 && piece & (slider | TYPES::QUEEN) > 0 {
+                        pinned.push(first);
+                    }
+                }
+            }
+        }
+        pinned.sort_unstable();
+        pinned
+    }
+
+    // The pieces of color attacking the tile, whatever stands on it.
+    pub fn attackers_of(&self, tile: usize, color: u8) -> Vec<usize> {
+        attackers(&self.board, tile, color)
+    }
+
+    // The pieces protecting the piece on the tile, empty for an empty tile.
+    pub fn defenders_of(&self, tile: usize) -> Vec<usize> {
+        let color = self.board[tile] & (COLORS::WHITE | COLORS::BLACK);
+        if color == 0 {
+            return Vec::new();
+        }
+        attackers(&self.board, tile, color)
+    }
+
+    pub fn is_square_attacked(&self, tile: usize, by: u8) -> bool {
+        is_attacked(&self.board, tile, by)
+    }
+
+    // Pieces of color, kings left out, that are attacked and either not defended or attacked by something cheaper.
+    pub fn hanging_pieces(&self, color: u8) -> Vec<usize> {
+        (0..64).filter(|tile| {
+            let piece = self.board[*tile];
+            if piece & color == 0 || piece & TYPES::KING > 0 {
+                return false;
+            }
+            let attacking = self.attackers_of(*tile, opposite(color));
+            if attacking.is_empty() {
+                return false;
+            }
+            let cheapest_attacker = attacking.iter().map(|attacker| piece_value(self.board[*attacker])).min().unwrap_or(0);
+            self.defenders_of(*tile).is_empty() || cheapest_attacker < piece_value(piece)
+        }).collect()
+    }
+}
+
+pub(crate) fn is_attacked(board: &[u8; 64], tile: usize, by: u8) -> bool {
+    !attackers(board, tile, by).is_empty()
+}
+
+// Looks outwards from the tile for the pieces that reach it, sliders up to the first piece in the way.
+pub(crate) fn attackers(board: &[u8; 64], tile: usize, color: u8) -> Vec<usize> {
+    let mut found = Vec::new();
+    for (directions, slider) in [(ROOK_DIRECTIONS, TYPES::ROOK), (BISHOP_DIRECTIONS, TYPES::BISHOP)] {
+        for direction in directions {
+            for (distance, other_tile) in ray_tiles(tile, direction).enumerate() {
+                let piece = board[other_tile];
+                if piece == TYPES::NONE {
+                    continue;
+                }
+                if piece & color > 0 && (piece & (slider | TYPES::QUEEN) > 0 || (distance == 0 && piece & TYPES::KING > 0)) {
+                    found.push(other_tile);
+                }
+                break;
+            }
+        }
+    }
+    for jump in KNIGHT_JUMPS {
+        if let Some(other_tile) = offset_tile(tile, jump) {
+            if board[other_tile] == TYPES::KNIGHT + color {
+                found.push(other_tile);
+            }
+        }
+    }
+    // white pawns attack towards the 8th rank, so they stand one rank further down
+    let pawn_rank_step = if color == COLORS::WHITE { 1 } else { -1 };
+    for file_step in [-1, 1] {
+        if let Some(other_tile) = offset_tile(tile, (file_step, pawn_rank_step)) {
+            if board[other_tile] == TYPES::PAWN + color {
+                found.push(other_tile);
+            }
+        }
+    }
+    found.sort_unstable();
+    found
+}
+
+pub(crate) fn king_tile(board: &[u8; 64], color: u8) -> Option<usize> {
+    board.iter().position(|piece| piece & TYPES::KING > 0 && piece & color > 0)
+}
+
+pub(crate) fn opposite(color: u8) -> u8 {
+    if color == COLORS::WHITE { COLORS::BLACK } else { COLORS::WHITE }
+}
+
+fn offset_tile(tile: usize, (file_step, rank_step): (i32, i32)) -> Option<usize> {
+    let file = (tile % 8) as i32 + file_step;
+    let rank = (tile / 8) as i32 + rank_step;
+    if (0..8).contains(&file) && (0..8).contains(&rank) {
+        Some((rank * 8 + file) as usize)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn ray_tiles(tile: usize, direction: (i32, i32)) -> impl Iterator<Item = usize> {
+    let mut current = tile;
+    std::iter::from_fn(move || {
+        current = offset_tile(current, direction)?;
+        Some(current)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, init_game, init_game_from_fen};
+
+    fn tiles(names: &[&str]) -> Vec<usize> {
+        names.iter().map(|name| algebraic_notation_to_memory_location(name)).collect()
+    }
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    #[test]
+    fn checkers() {
+        assert!(init_game().checkers().is_empty());
+        let game = init_game_from_fen("4k3/8/8/8/1b6/8/8/4K2r w - - 0 1");
+        assert_eq!(game.checkers(), tiles(&["b4", "h1"]));
+        let game = init_game_from_fen("4k3/8/8/8/8/3N4/8/4K3 b - - 0 1");
+        assert!(game.checkers().is_empty());
+        let game = init_game_from_fen("4k3/8/3N4/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(game.checkers(), tiles(&["d6"]));
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        let game = init_game_from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert_eq!(game.pinned_pieces(COLORS::WHITE), tiles(&["e2"]));
+        assert!(game.pinned_pieces(COLORS::BLACK).is_empty());
+        // two pieces in the way, nothing is pinned
+        let game = init_game_from_fen("4k3/4r3/8/8/8/4B3/4N3/4K3 w - - 0 1");
+        assert!(game.pinned_pieces(COLORS::WHITE).is_empty());
+        // a rook doesn't pin along a diagonal, a bishop or queen does
+        let game = init_game_from_fen("4k3/8/8/8/1r6/8/3N4/4K3 w - - 0 1");
+        assert!(game.pinned_pieces(COLORS::WHITE).is_empty());
+        let game = init_game_from_fen("4k3/8/8/8/1q6/8/3N4/4K3 w - - 0 1");
+        assert_eq!(game.pinned_pieces(COLORS::WHITE), tiles(&["d2"]));
+        // an enemy piece in between isn't pinned by its own side
+        let game = init_game_from_fen("4k3/4r3/8/8/8/8/4n3/4K3 w - - 0 1");
+        assert!(game.pinned_pieces(COLORS::WHITE).is_empty());
+    }
+
+    #[test]
+    fn attackers_of() {
+        // only the front rook of the two reaches a8, attacks don't x-ray
+        let game = init_game_from_fen("4k3/8/8/8/8/8/R7/R3K3 w - - 0 1");
+        assert_eq!(game.attackers_of(tile("a8"), COLORS::WHITE), tiles(&["a2"]));
+        assert_eq!(game.attackers_of(tile("a3"), COLORS::WHITE), tiles(&["a2"]));
+        let game = init_game_from_fen("4k3/8/8/8/8/2B5/1Q6/4K3 w - - 0 1");
+        assert_eq!(game.attackers_of(tile("f6"), COLORS::WHITE), tiles(&["c3"]));
+
+        // every kind of piece, pawns only diagonally forwards
+        let game = init_game_from_fen("4k3/8/8/2NP4/1R2P3/5K2/3Q4/8 w - - 0 1");
+        assert_eq!(game.attackers_of(tile("e4"), COLORS::WHITE), tiles(&["c5", "b4", "f3"]));
+        assert_eq!(game.attackers_of(tile("f4"), COLORS::WHITE), tiles(&["f3", "d2"]));
+        assert_eq!(game.attackers_of(tile("d6"), COLORS::WHITE), Vec::<usize>::new());
+        assert_eq!(game.attackers_of(tile("e6"), COLORS::WHITE), tiles(&["c5", "d5"]));
+        assert!(game.is_square_attacked(tile("e6"), COLORS::WHITE));
+        assert!(!game.is_square_attacked(tile("e6"), COLORS::BLACK));
+    }
+
+    #[test]
+    fn defenders_of() {
+        let game = init_game_from_fen("4k3/8/8/8/8/5N2/6P1/4K3 w - - 0 1");
+        assert_eq!(game.defenders_of(tile("f3")), tiles(&["g2"]));
+        assert_eq!(game.defenders_of(tile("g2")), Vec::<usize>::new());
+        assert!(game.defenders_of(tile("a1")).is_empty());                            // empty tile
+        // a pinned piece still defends
+        let game = init_game_from_fen("4k3/4r3/8/8/8/r1B5/4N3/4K3 w - - 0 1");
+        assert_eq!(game.defenders_of(tile("c3")), tiles(&["e2"]));
+    }
+
+    #[test]
+    fn hanging_pieces() {
+        // defended by the pinned knight and attacked by a dearer rook
+        let game = init_game_from_fen("4k3/4r3/8/8/8/r1B5/4N3/4K3 w - - 0 1");
+        assert!(game.hanging_pieces(COLORS::WHITE).is_empty());
+        let game = init_game_from_fen("4k3/4r3/8/8/8/r1B5/8/4K3 w - - 0 1");
+        assert_eq!(game.hanging_pieces(COLORS::WHITE), tiles(&["c3"]));
+        // a pawn takes a defended knight with profit
+        let game = init_game_from_fen("4k3/8/8/8/4p3/5N2/6P1/4K3 w - - 0 1");
+        assert_eq!(game.hanging_pieces(COLORS::WHITE), tiles(&["f3"]));
+        assert!(game.hanging_pieces(COLORS::BLACK).is_empty());
+        // kings are left out
+        let game = init_game_from_fen("4k3/8/8/8/8/8/8/r3K3 w - - 0 1");
+        assert!(game.hanging_pieces(COLORS::WHITE).is_empty());
+    }
+}
@@ -0,0 +1,401 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+
+use rand::Rng;
+
+use crate::make_move;
+use crate::pgn::{san_to_move, PgnGame};
+use crate::variant::Variant;
+use crate::zobrist::{polyglot_square, polyglot_tile};
+use crate::Move;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+pub const DEFAULT_BOOK_PATH: &str = "book.bin";
+pub const BOOK_PATH_VARIABLE: &str = "CHESS_BOOK";
+
+const ENTRY_SIZE: u64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub raw_move: u16,
+    pub weight: u16,
+    pub learn: u32,                                             // read but never used
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BookSelection {
+    WeightedRandom,
+    BestWeight,
+}
+
+pub struct Book {
+    file: File,
+    entry_count: u64,
+}
+
+// The book path can be set with the CHESS_BOOK environment variable.
+pub fn book_path() -> String {
+    env::var(BOOK_PATH_VARIABLE).unwrap_or_else(|_| DEFAULT_BOOK_PATH.to_string())
+}
+
+impl Book {
+    pub fn open(path: &str) -> io::Result<Book> {
+        let file = File::open(path)?;
+        let entry_count = file.metadata()?.len() / ENTRY_SIZE;
+        Ok(Book { file, entry_count })
+    }
+
+    pub fn open_default() -> io::Result<Book> {
+        Book::open(&book_path())
+    }
+
+    fn read_entry(&self, index: u64) -> io::Result<BookEntry> {
+        let mut file = &self.file;
+        let mut buffer = [0u8; ENTRY_SIZE as usize];
+        file.seek(SeekFrom::Start(index * ENTRY_SIZE))?;
+        file.read_exact(&mut buffer)?;
+
+        let mut key = [0u8; 8];
+        key.copy_from_slice(&buffer[0..8]);
+        Ok(BookEntry {
+            key: u64::from_be_bytes(key),
+            raw_move: u16::from_be_bytes([buffer[8], buffer[9]]),
+            weight: u16::from_be_bytes([buffer[10], buffer[11]]),
+            learn: u32::from_be_bytes([buffer[12], buffer[13], buffer[14], buffer[15]]),
+        })
+    }
+
+    // Entries are sorted by key, so find the first one with a matching key and read on from there.
+    pub fn probe(&self, key: u64) -> io::Result<Vec<BookEntry>> {
+        let mut low = 0;
+        let mut high = self.entry_count;
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.read_entry(middle)?.key < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut index = low;
+        while index < self.entry_count {
+            let entry = self.read_entry(index)?;
+            if entry.key != key {
+                break;
+            }
+            entries.push(entry);
+            index += 1;
+        }
+        Ok(entries)
+    }
+
+    // Legal book moves for the position together with their weights. Polyglot books are for
+    // standard chess, the variants and chess960 get no book moves.
+    pub fn get_book_moves(&self, game: &GAME) -> Vec<(Move, u16)> {
+        if game.get_variant() != Variant::Standard || game.is_chess960() {
+            return Vec::new();
+        }
+        let entries = self.probe(game.get_zobrist_key()).unwrap_or_default();
+        let legal_moves = game.generate_legal_moves();
+
+        let mut book_moves = Vec::new();
+        for entry in entries {
+            let book_move = decode_move(entry.raw_move, game);
+            if legal_moves.contains(&book_move) {
+                book_moves.push((book_move, entry.weight));
+            }
+        }
+        book_moves
+    }
+
+    pub fn choose_move(&self, game: &GAME, selection: BookSelection) -> Option<Move> {
+        let book_moves = self.get_book_moves(game);
+        if book_moves.is_empty() {
+            return None;
+        }
+
+        match selection {
+            BookSelection::BestWeight => {
+                book_moves.iter().max_by_key(|(_, weight)| *weight).map(|(book_move, _)| *book_move)
+            }
+            BookSelection::WeightedRandom => {
+                let total_weight: u32 = book_moves.iter().map(|(_, weight)| *weight as u32).sum();
+                if total_weight == 0 {
+                    return Some(book_moves[0].0);
+                }
+                let mut pick = rand::thread_rng().gen_range(0..total_weight);
+                for (book_move, weight) in &book_moves {
+                    if pick < *weight as u32 {
+                        return Some(*book_move);
+                    }
+                    pick -= *weight as u32;
+                }
+                None
+            }
+        }
+    }
+}
+
+// Polyglot moves are to-file, to-row, from-file, from-row and promotion packed in three bits each.
+// Castling is stored as the king capturing its own rook.
+pub fn decode_move(raw_move: u16, game: &GAME) -> Move {
+    let to_square = (raw_move & 0x3f) as usize;
+    let from_square = ((raw_move >> 6) & 0x3f) as usize;
+    let promotion = match (raw_move >> 12) & 0x7 {
+        1 => TYPES::KNIGHT,
+        2 => TYPES::BISHOP,
+        3 => TYPES::ROOK,
+        4 => TYPES::QUEEN,
+        _ => TYPES::NONE,
+    };
+
+    let from_tile = polyglot_tile(from_square);
+    let mut to_tile = polyglot_tile(to_square);
+
+    if game.board[from_tile] & TYPES::KING > 0 && !game.is_chess960() {
+        if (from_tile == 60 && to_tile == 63) || (from_tile == 4 && to_tile == 7) {
+            to_tile = from_tile + 2;
+        } else if (from_tile == 60 && to_tile == 56) || (from_tile == 4 && to_tile == 0) {
+            to_tile = from_tile - 2;
+        }
+    }
+    Move::new(from_tile, to_tile, promotion)
+}
+
+pub fn encode_move(chess_move: &Move, game: &GAME) -> u16 {
+    let mut to_tile = chess_move.to;
+    if game.is_castling(chess_move) && !game.is_chess960() {
+        if (chess_move.from == 60 || chess_move.from == 4) && to_tile == chess_move.from + 2 {
+            to_tile = chess_move.from + 3;
+        } else if (chess_move.from == 60 || chess_move.from == 4) && to_tile + 2 == chess_move.from {
+            to_tile = chess_move.from - 4;
+        }
+    }
+    let promotion = match chess_move.promotion {
+        TYPES::KNIGHT => 1,
+        TYPES::BISHOP => 2,
+        TYPES::ROOK => 3,
+        TYPES::QUEEN => 4,
+        _ => 0,
+    };
+    (promotion << 12) | ((polyglot_square(chess_move.from) as u16) << 6) | polyglot_square(to_tile) as u16
+}
+
+// Entries are written sorted by key for the binary search in Book::probe, a copy is sorted when they aren't.
+pub fn write_book(path: &str, entries: &[BookEntry]) -> io::Result<()> {
+    let mut sorted_entries = Vec::new();
+    let entries = if entries.windows(2).all(|pair| entry_order(&pair[0], &pair[1]) != Ordering::Greater) {
+        entries
+    } else {
+        sorted_entries.extend_from_slice(entries);
+        sorted_entries.sort_by(entry_order);
+        &sorted_entries
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    for entry in entries {
+        writer.write_all(&entry.key.to_be_bytes())?;
+        writer.write_all(&entry.raw_move.to_be_bytes())?;
+        writer.write_all(&entry.weight.to_be_bytes())?;
+        writer.write_all(&entry.learn.to_be_bytes())?;
+    }
+    writer.flush()
+}
+
+// Polyglot books are sorted by key, the heaviest move of a position first.
+fn entry_order(a: &BookEntry, b: &BookEntry) -> Ordering {
+    a.key.cmp(&b.key).then(b.weight.cmp(&a.weight))
+}
+
+#[derive(Clone, Copy, Default)]
+struct MoveStatistics {
+    occurrences: u32,
+    score: u32,
+}
+
+// Collects the moves of the first max_ply plies of every game. A move scores win_weight,
+// draw_weight or loss_weight points depending on how the game went for the side that played it.
+pub struct BookBuilder {
+    pub max_ply: usize,
+    pub min_occurrences: u32,
+    pub win_weight: u32,
+    pub draw_weight: u32,
+    pub loss_weight: u32,
+    statistics: HashMap<(u64, u16), MoveStatistics>,
+}
+
+impl Default for BookBuilder {
+    fn default() -> Self {
+        BookBuilder::new()
+    }
+}
+
+impl BookBuilder {
+    pub fn new() -> BookBuilder {
+        BookBuilder {
+            max_ply: 20,
+            min_occurrences: 1,
+            win_weight: 2,
+            draw_weight: 1,
+            loss_weight: 0,
+            statistics: HashMap::new(),
+        }
+    }
+
+    // Returns false when the game has a move that can't be played, the moves before it are still counted.
+    // Games starting from a custom position (FEN tag) or of another variant are skipped.
+    pub fn add_game(&mut self, pgn_game: &PgnGame) -> bool {
+        if pgn_game.tag("FEN").is_some() || pgn_game.get_variant() != Some(Variant::Standard) {
+            return false;
+        }
+        let result = pgn_game.get_result().to_string();
+        let mut game = crate::init_game();
+
+        for san in pgn_game.moves.iter().take(self.max_ply) {
+            let chess_move = match san_to_move(san, &game) {
+                Some(chess_move) => chess_move,
+                None => return false,
+            };
+
+            let white_moved = game.turn == COLORS::WHITE;
+            let points = match result.as_str() {
+                "1-0" => if white_moved { self.win_weight } else { self.loss_weight },
+                "0-1" => if white_moved { self.loss_weight } else { self.win_weight },
+                "1/2-1/2" => self.draw_weight,
+                _ => 0,
+            };
+
+            let key = (game.get_zobrist_key(), encode_move(&chess_move, &game));
+            let statistics = self.statistics.entry(key).or_default();
+            statistics.occurrences += 1;
+            statistics.score += points;
+
+            make_move(&chess_move, &mut game);
+        }
+        true
+    }
+
+    // Weights are the scores, scaled down together for positions whose best move scored more than
+    // fits in a weight so the moves keep their shares.
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let counted = |statistics: &MoveStatistics| statistics.occurrences >= self.min_occurrences && statistics.score > 0;
+        let mut best_scores: HashMap<u64, u32> = HashMap::new();
+        for ((key, _), statistics) in self.statistics.iter().filter(|(_, statistics)| counted(statistics)) {
+            let best_score = best_scores.entry(*key).or_insert(0);
+            *best_score = (*best_score).max(statistics.score);
+        }
+
+        let mut entries = Vec::new();
+        for ((key, raw_move), statistics) in self.statistics.iter().filter(|(_, statistics)| counted(statistics)) {
+            let best_score = best_scores[key] as u64;
+            let weight = if best_score > u16::MAX as u64 {
+                (statistics.score as u64 * u16::MAX as u64 / best_score).max(1)
+            } else {
+                statistics.score as u64
+            };
+            entries.push(BookEntry {
+                key: *key,
+                raw_move: *raw_move,
+                weight: weight as u16,
+                learn: 0,
+            });
+        }
+        entries.sort_by(entry_order);
+        entries
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<usize> {
+        let entries = self.entries();
+        write_book(path, &entries)?;
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algebraic_notation_to_memory_location;
+    use crate::init_game;
+    use crate::init_variant_game;
+    use crate::pgn::parse_pgn;
+    use std::fs;
+    use std::process;
+
+    fn book_move(from: &str, to: &str) -> Move {
+        Move::new(algebraic_notation_to_memory_location(from), algebraic_notation_to_memory_location(to), TYPES::NONE)
+    }
+
+    fn write_test_book(name: &str) -> String {
+        let game = init_game();
+        let entries = [(book_move("d2", "d4"), 5), (book_move("e2", "e4"), 9), (book_move("e2", "e5"), 20)]
+            .iter()
+            .map(|(chess_move, weight)| BookEntry { key: game.get_zobrist_key(), raw_move: encode_move(chess_move, &game), weight: *weight, learn: 0 })
+            .collect::<Vec<_>>();
+        let path = env::temp_dir().join(format!("{}_{}.bin", name, process::id())).to_string_lossy().to_string();
+        write_book(&path, &entries).unwrap();
+        path
+    }
+
+    #[test]
+    fn book_moves_of_the_start_position() {
+        let path = write_test_book("chess_logic_book_moves");
+        let book = Book::open(&path).unwrap();
+        let game = init_game();
+        // e2e5 isn't legal so it is left out even though it weighs the most
+        assert_eq!(book.get_book_moves(&game), vec![(book_move("e2", "e4"), 9), (book_move("d2", "d4"), 5)]);
+        assert_eq!(book.choose_move(&game, BookSelection::BestWeight), Some(book_move("e2", "e4")));
+        assert!(book.choose_move(&game, BookSelection::WeightedRandom).is_some());
+        assert!(book.get_book_moves(&init_variant_game(Variant::Atomic)).is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    fn builder_moves(builder: &BookBuilder) -> Vec<(String, u16)> {
+        let game = init_game();
+        let mut moves: Vec<(String, u16)> = builder.entries().iter()
+            .filter(|entry| entry.key == game.get_zobrist_key())
+            .map(|entry| (decode_move(entry.raw_move, &game).to_uci(), entry.weight))
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn book_builder_counts_the_first_plies() {
+        let games = parse_pgn("[Round \"1\"]\n1. e4 e5 1-0\n[Round \"2\"]\n1. e4 c5 0-1\n[Round \"3\"]\n1. d4 d5 1/2-1/2\n[FEN \"4k3/8/8/8/8/8/8/4K3 w - - 0 1\"]\n\n1. Kd2 *\n");
+        let mut builder = BookBuilder { max_ply: 1, ..BookBuilder::new() };
+        let added: Vec<bool> = games.iter().map(|pgn_game| builder.add_game(pgn_game)).collect();
+        assert_eq!(added, [true, true, true, false]);
+        // e4 won once and lost once, d4 drew
+        assert_eq!(builder_moves(&builder), [("d2d4".to_string(), 1), ("e2e4".to_string(), 2)]);
+        assert_eq!(builder.entries().len(), 2);
+
+        builder.min_occurrences = 2;
+        assert_eq!(builder_moves(&builder), [("e2e4".to_string(), 2)]);
+
+        let mut builder = BookBuilder { max_ply: 2, ..BookBuilder::new() };
+        for pgn_game in &games {
+            builder.add_game(pgn_game);
+        }
+        // the replies are in the book too, except e5 which lost and scored nothing
+        assert_eq!(builder.entries().len(), 4);
+    }
+
+    #[test]
+    fn castling_is_stored_as_the_king_taking_its_rook() {
+        let game = crate::init_game_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let castling = book_move("e1", "g1");
+        let raw_move = encode_move(&castling, &game);
+        assert_eq!(raw_move, ((polyglot_square(60) as u16) << 6) | polyglot_square(63) as u16);
+        assert_eq!(decode_move(raw_move, &game), castling);
+        let long_castling = book_move("e1", "c1");
+        assert_eq!(decode_move(encode_move(&long_castling, &game), &game), long_castling);
+    }
+}
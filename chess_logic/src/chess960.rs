@@ -0,0 +1,106 @@
+use rand::Rng;
+
+pub const START_POSITION_COUNT: usize = 960;
+
+// Knight placements on the five tiles left after bishops and queen, in the standard numbering.
+const KNIGHT_TILES: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+// The back rank from the a-file, like "RNBQKBNR" for index 518.
+pub fn back_rank(index: usize) -> Option<[char; 8]> {
+    if index >= START_POSITION_COUNT {
+        return None;
+    }
+    let mut rank = [' '; 8];
+    let mut rest = index;
+
+    rank[(rest % 4) * 2 + 1] = 'B';                             // light squared bishop on b, d, f or h
+    rest /= 4;
+    rank[(rest % 4) * 2] = 'B';                                 // dark squared bishop on a, c, e or g
+    rest /= 4;
+
+    let queen = rest % 6;
+    rest /= 6;
+    let free_files: Vec<usize> = (0..8).filter(|file| rank[*file] == ' ').collect();
+    rank[free_files[queen]] = 'Q';
+
+    let (first_knight, second_knight) = KNIGHT_TILES[rest];
+    let free_files: Vec<usize> = (0..8).filter(|file| rank[*file] == ' ').collect();
+    rank[free_files[first_knight]] = 'N';
+    rank[free_files[second_knight]] = 'N';
+
+    // the king goes between the rooks on the three files left
+    let free_files: Vec<usize> = (0..8).filter(|file| rank[*file] == ' ').collect();
+    rank[free_files[0]] = 'R';
+    rank[free_files[1]] = 'K';
+    rank[free_files[2]] = 'R';
+    Some(rank)
+}
+
+pub fn start_position_fen(index: usize) -> Option<String> {
+    let white: String = back_rank(index)?.iter().collect();
+    let black = white.to_lowercase();
+    Some(format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1", black, white))
+}
+
+pub fn random_start_position_index() -> usize {
+    rand::thread_rng().gen_range(0..START_POSITION_COUNT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_chess960_game, init_game_from_fen};
+
+    fn back_rank_of(index: usize) -> String {
+        back_rank(index).unwrap().iter().collect()
+    }
+
+    #[test]
+    fn scharnagl_numbers() {
+        assert_eq!(back_rank_of(518), "RNBQKBNR");
+        assert_eq!(back_rank_of(0), "BBQNNRKR");
+        assert_eq!(back_rank_of(959), "RKRNNQBB");
+        assert_eq!(back_rank(START_POSITION_COUNT), None);
+        assert_eq!(start_position_fen(518).unwrap(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+    }
+
+    #[test]
+    fn every_start_position_is_different_and_legal() {
+        let mut back_ranks: Vec<String> = (0..START_POSITION_COUNT).map(back_rank_of).collect();
+        for rank in &back_ranks {
+            let bishops: Vec<usize> = rank.match_indices('B').map(|(file, _)| file).collect();
+            let king = rank.find('K').unwrap();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}", rank);
+            assert!(rank.find('R').unwrap() < king && rank.rfind('R').unwrap() > king, "{}", rank);
+        }
+        back_ranks.sort();
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), START_POSITION_COUNT);
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        let game = init_chess960_game(0).unwrap();
+        assert_eq!(game.get_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(game.get_shredder_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+
+        // with the king and rooks on their usual tiles a FEN can't tell chess960 apart, those castle the same
+        for index in 0..START_POSITION_COUNT {
+            let game = init_chess960_game(index).unwrap();
+            let rank = back_rank_of(index);
+            let usual_tiles = rank.starts_with('R') && rank.ends_with('R') && rank.find('K') == Some(4);
+            for fen in [game.get_fen(), game.get_shredder_fen()] {
+                let loaded = init_game_from_fen(&fen);
+                assert_eq!(loaded.is_chess960(), !usual_tiles, "{}", fen);
+                assert_eq!(loaded.get_fen(), game.get_fen());
+                assert_eq!(loaded.get_shredder_fen(), game.get_shredder_fen());
+            }
+        }
+
+        // X-FEN names the rook by its file when another rook stands further out on that side
+        let game = init_game_from_fen("4k3/8/8/8/8/8/8/1K2R2R w E - 0 1");
+        assert!(game.is_chess960());
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/1K2R2R w E - 0 1");
+        assert_eq!(init_game_from_fen("4k3/8/8/8/8/8/8/1K2R2R w K - 0 1").get_shredder_fen(), "4k3/8/8/8/8/8/8/1K2R2R w H - 0 1");
+    }
+}
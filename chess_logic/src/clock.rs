@@ -0,0 +1,438 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::COLORS;
+
+// Where the clock reads the time from. WallClock is the real one, ManualTime is moved by hand
+// so games with a clock can be played through without waiting.
+pub trait TimeSource: Send {
+    fn now(&self) -> Duration;                                  // time since some fixed point
+}
+
+pub struct WallClock {
+    start: Instant,
+}
+
+impl WallClock {
+    pub fn new() -> WallClock {
+        WallClock { start: Instant::now() }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock::new()
+    }
+}
+
+impl TimeSource for WallClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// Clones share the time, keep one to advance it after handing the other to a clock.
+#[derive(Clone, Debug, Default)]
+pub struct ManualTime {
+    millis: Arc<AtomicU64>,
+}
+
+impl ManualTime {
+    pub fn new() -> ManualTime {
+        ManualTime::default()
+    }
+
+    pub fn advance(&self, time: Duration) {
+        self.millis.fetch_add(time.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, time: Duration) {
+        self.millis.store(time.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Duration {
+        Duration::from_millis(self.millis.load(Ordering::SeqCst))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delay {
+    None,
+    Fischer(Duration),                                          // added after every move
+    Bronstein(Duration),                                        // the time used is given back, up to this much
+    Simple(Duration),                                           // the clock waits this long before it runs
+}
+
+// A stage gives time for a number of moves, the last stage lasts for the rest of the game. A last
+// stage with a number of moves is repeated, like 40/7200 for two hours every 40 moves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stage {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub delay: Delay,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    pub stages: Vec<Stage>,
+}
+
+impl TimeControl {
+    pub fn sudden_death(time: Duration) -> TimeControl {
+        TimeControl { stages: vec![Stage { moves: None, time, delay: Delay::None }] }
+    }
+
+    pub fn fischer(time: Duration, increment: Duration) -> TimeControl {
+        TimeControl { stages: vec![Stage { moves: None, time, delay: Delay::Fischer(increment) }] }
+    }
+
+    pub fn bronstein(time: Duration, delay: Duration) -> TimeControl {
+        TimeControl { stages: vec![Stage { moves: None, time, delay: Delay::Bronstein(delay) }] }
+    }
+
+    pub fn simple_delay(time: Duration, delay: Duration) -> TimeControl {
+        TimeControl { stages: vec![Stage { moves: None, time, delay: Delay::Simple(delay) }] }
+    }
+
+    // The PGN TimeControl tag: stages split by colons, each "moves/seconds" or "seconds" with an optional
+    // "+increment", like 40/5400+30:1800+30. Delays are written "300d5" (simple) and "300b5" (Bronstein).
+    // Unknown (?), untimed (-) and sandglass (*180) controls give None.
+    pub fn from_pgn(text: &str) -> Option<TimeControl> {
+        parse_stages(text, 1.0)
+    }
+
+    // The way players write them, minutes and seconds of increment or delay, like 5+3, 90+30 or 40/90+30:30+30.
+    pub fn from_minutes(text: &str) -> Option<TimeControl> {
+        parse_stages(text, 60.0)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let fields: Vec<String> = self.stages.iter().map(|stage| {
+            let moves = stage.moves.map_or(String::new(), |moves| format!("{}/", moves));
+            let delay = match stage.delay {
+                Delay::None => String::new(),
+                Delay::Fischer(time) => format!("+{}", seconds_text(time)),
+                Delay::Bronstein(time) => format!("b{}", seconds_text(time)),
+                Delay::Simple(time) => format!("d{}", seconds_text(time)),
+            };
+            format!("{}{}{}", moves, seconds_text(stage.time), delay)
+        }).collect();
+        fields.join(":")
+    }
+
+    // The stage a side plays in after leaving stage index, the last one repeats.
+    fn next_stage(&self, index: usize) -> usize {
+        (index + 1).min(self.stages.len() - 1)
+    }
+}
+
+fn parse_stages(text: &str, time_unit: f64) -> Option<TimeControl> {
+    let mut stages = Vec::new();
+    for field in text.trim().split(':') {
+        let (moves, rest) = match field.split_once('/') {
+            Some((moves, rest)) => (Some(moves.parse::<u32>().ok().filter(|moves| *moves > 0)?), rest),
+            None => (None, field),
+        };
+        let split = rest.find(['+', 'd', 'b']).unwrap_or(rest.len());
+        let time = parse_seconds(&rest[..split], time_unit)?;
+        let delay = match rest.get(split..split + 1) {
+            None => Delay::None,
+            Some(kind) => {
+                let delay = parse_seconds(&rest[split + 1..], 1.0)?;
+                match kind {
+                    "+" => Delay::Fischer(delay),
+                    "d" => Delay::Simple(delay),
+                    _ => Delay::Bronstein(delay),
+                }
+            }
+        };
+        stages.push(Stage { moves, time, delay });
+    }
+    // only the last stage may go on for the rest of the game
+    if stages.iter().rev().skip(1).any(|stage| stage.moves.is_none()) {
+        return None;
+    }
+    Some(TimeControl { stages })
+}
+
+fn parse_seconds(text: &str, unit: f64) -> Option<Duration> {
+    let number: f64 = text.parse().ok()?;
+    // negative, infinite and too long times don't make a Duration
+    Duration::try_from_secs_f64(number * unit).ok()
+}
+
+fn seconds_text(time: Duration) -> String {
+    if time.subsec_millis() == 0 {
+        time.as_secs().to_string()
+    } else {
+        format!("{}", time.as_secs_f64())
+    }
+}
+
+// "1:30:00", "4:59" or "0:09.4", tenths are shown in the last ten seconds.
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
+    }
+}
+
+// A chess clock for both sides. Start it for the side to move, press it after every move and
+// the other side's time runs. A side whose time runs out has flagged and the clock stops.
+pub struct Clock {
+    control: TimeControl,
+    source: Box<dyn TimeSource>,
+    remaining: [Duration; 2],                                   // white and black, without the running turn
+    stage: [usize; 2],
+    stage_moves: [u32; 2],                                      // moves played in the current stage
+    running: Option<(usize, Duration)>,                         // the side whose time runs and when its turn began
+    flagged: Option<u8>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        let time = control.stages.first().map_or(Duration::ZERO, |stage| stage.time);
+        Clock {
+            control,
+            source: Box::new(WallClock::new()),
+            remaining: [time; 2],
+            stage: [0; 2],
+            stage_moves: [0; 2],
+            running: None,
+            flagged: None,
+        }
+    }
+
+    pub fn with_time_source(mut self, source: impl TimeSource + 'static) -> Clock {
+        self.source = Box::new(source);
+        self
+    }
+
+    pub fn get_control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    // Starts (or moves) the running time to color, what the other side used so far is kept.
+    pub fn start(&mut self, color: u8) {
+        self.stop();
+        if self.flagged.is_none() {
+            self.running = Some((side_index(color), self.source.now()));
+        }
+    }
+
+    // Stops the clock without ending the turn, no increment is given.
+    pub fn stop(&mut self) {
+        if let Some((side, _)) = self.running {
+            let used = self.used(side);
+            self.spend(side, used);
+            self.running = None;
+        }
+    }
+
+    // Ends the turn of the side whose time runs: its delay or increment is applied, it moves on to its
+    // next stage after the stage's last move and the other side's time starts. False when it had flagged.
+    pub fn press(&mut self) -> bool {
+        let (side, started) = match self.running {
+            Some(running) => running,
+            None => return self.flagged.is_none(),
+        };
+        let now = self.source.now();
+        let elapsed = now.saturating_sub(started);
+        let stage = self.control.stages[self.stage[side]];
+        if !self.spend(side, self.used(side)) {
+            self.running = None;
+            return false;
+        }
+        match stage.delay {
+            Delay::Fischer(increment) => self.remaining[side] = self.remaining[side].saturating_add(increment),
+            Delay::Bronstein(delay) => self.remaining[side] = self.remaining[side].saturating_add(elapsed.min(delay)),
+            Delay::None | Delay::Simple(_) => {}
+        }
+        self.stage_moves[side] += 1;
+        if stage.moves == Some(self.stage_moves[side]) {
+            self.stage[side] = self.control.next_stage(self.stage[side]);
+            self.stage_moves[side] = 0;
+            self.remaining[side] = self.remaining[side].saturating_add(self.control.stages[self.stage[side]].time);
+        }
+        self.running = Some((1 - side, now));
+        true
+    }
+
+    // Back to the start of the first stage for both sides, stopped.
+    pub fn reset(&mut self) {
+        let source = std::mem::replace(&mut self.source, Box::new(WallClock::new()));
+        *self = Clock { source, ..Clock::new(self.control.clone()) };
+    }
+
+    // The time left of color right now, counting the running turn.
+    pub fn remaining(&self, color: u8) -> Duration {
+        let side = side_index(color);
+        match self.running {
+            Some((running_side, _)) if running_side == side => self.remaining[side].saturating_sub(self.used(side)),
+            _ => self.remaining[side],
+        }
+    }
+
+    // The colour whose time is running, None while stopped.
+    pub fn running(&self) -> Option<u8> {
+        self.running.map(|(side, _)| side_color(side))
+    }
+
+    // The moves color still has to play before its next time control, None in a stage for the rest of the game.
+    pub fn moves_to_go(&self, color: u8) -> Option<u32> {
+        let side = side_index(color);
+        self.control.stages[self.stage[side]].moves.map(|moves| moves - self.stage_moves[side])
+    }
+
+    // The colour that ran out of time, also while its flag falls and nobody pressed the clock.
+    pub fn flagged(&self) -> Option<u8> {
+        if self.flagged.is_some() {
+            return self.flagged;
+        }
+        match self.running {
+            Some((side, _)) if self.remaining(side_color(side)) == Duration::ZERO => Some(side_color(side)),
+            _ => None,
+        }
+    }
+
+    // The time the running turn of side counts for, a simple delay passes before the clock runs.
+    fn used(&self, side: usize) -> Duration {
+        let started = match self.running {
+            Some((running_side, started)) if running_side == side => started,
+            _ => return Duration::ZERO,
+        };
+        let elapsed = self.source.now().saturating_sub(started);
+        match self.control.stages[self.stage[side]].delay {
+            Delay::Simple(delay) => elapsed.saturating_sub(delay),
+            _ => elapsed,
+        }
+    }
+
+    fn spend(&mut self, side: usize, used: Duration) -> bool {
+        if used >= self.remaining[side] {
+            self.remaining[side] = Duration::ZERO;
+            self.flagged = Some(side_color(side));
+            return false;
+        }
+        self.remaining[side] -= used;
+        true
+    }
+}
+
+fn side_index(color: u8) -> usize {
+    if color == COLORS::WHITE { 0 } else { 1 }
+}
+
+fn side_color(side: usize) -> u8 {
+    if side == 0 { COLORS::WHITE } else { COLORS::BLACK }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    // A clock running for white with the time kept by hand.
+    fn started(control: TimeControl) -> (Clock, ManualTime) {
+        let time = ManualTime::new();
+        let mut clock = Clock::new(control).with_time_source(time.clone());
+        clock.start(COLORS::WHITE);
+        (clock, time)
+    }
+
+    // White thinks for think seconds and presses, black answers right away.
+    fn white_moves(clock: &mut Clock, time: &ManualTime, think: u64) {
+        time.advance(seconds(think));
+        assert!(clock.press());
+        assert!(clock.press());
+    }
+
+    #[test]
+    fn fischer_adds_the_increment() {
+        let (mut clock, time) = started(TimeControl::fischer(seconds(60), seconds(2)));
+        white_moves(&mut clock, &time, 5);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(57));
+        assert_eq!(clock.remaining(COLORS::BLACK), seconds(62));
+    }
+
+    #[test]
+    fn bronstein_gives_back_the_time_used_up_to_the_delay() {
+        let (mut clock, time) = started(TimeControl::bronstein(seconds(60), seconds(5)));
+        white_moves(&mut clock, &time, 3);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(60));
+        white_moves(&mut clock, &time, 8);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(57));
+    }
+
+    #[test]
+    fn simple_delay_waits_before_running() {
+        let (mut clock, time) = started(TimeControl::simple_delay(seconds(60), seconds(5)));
+        time.advance(seconds(3));
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(60));
+        assert!(clock.press());
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(60));
+        assert!(clock.press());
+        white_moves(&mut clock, &time, 8);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(57));
+    }
+
+    #[test]
+    fn stages_add_their_time_after_the_last_move() {
+        let control = TimeControl::from_pgn("2/60:30+1").expect("a time control");
+        let (mut clock, time) = started(control);
+        white_moves(&mut clock, &time, 10);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(50));
+        assert_eq!(clock.moves_to_go(COLORS::WHITE), Some(1));
+        white_moves(&mut clock, &time, 10);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(70));
+        assert_eq!(clock.moves_to_go(COLORS::WHITE), None);
+        white_moves(&mut clock, &time, 5);
+        assert_eq!(clock.remaining(COLORS::WHITE), seconds(66));
+        assert_eq!(clock.moves_to_go(COLORS::BLACK), None);
+    }
+
+    #[test]
+    fn flag_falls_when_the_time_is_gone() {
+        let (mut clock, time) = started(TimeControl::sudden_death(seconds(10)));
+        time.advance(seconds(9));
+        assert_eq!(clock.flagged(), None);
+        time.advance(seconds(2));
+        assert_eq!(clock.flagged(), Some(COLORS::WHITE));
+        assert!(!clock.press());
+        assert_eq!(clock.remaining(COLORS::WHITE), Duration::ZERO);
+        assert_eq!(clock.running(), None);
+        clock.start(COLORS::BLACK);
+        assert_eq!(clock.running(), None);
+        assert_eq!(clock.flagged(), Some(COLORS::WHITE));
+    }
+
+    #[test]
+    fn pgn_time_controls_round_trip() {
+        for text in ["300", "180+2", "40/5400+30:1800+30", "300d5", "300b5", "2/60:30+1", "0.5"] {
+            let control = TimeControl::from_pgn(text).expect(text);
+            assert_eq!(control.to_pgn(), text);
+            assert_eq!(TimeControl::from_pgn(&control.to_pgn()), Some(control));
+        }
+        assert_eq!(TimeControl::from_minutes("5+3").map(|control| control.to_pgn()), Some("300+3".to_string()));
+        assert_eq!(TimeControl::from_minutes("40/90+30:30+30").map(|control| control.to_pgn()), Some("40/5400+30:1800+30".to_string()));
+    }
+
+    #[test]
+    fn unknown_untimed_and_broken_controls_are_rejected() {
+        for text in ["?", "-", "*180", "", "60:40/30", "0/60", "-5", "5+", "inf", "1e30", "5+1e30"] {
+            assert_eq!(TimeControl::from_pgn(text), None, "{}", text);
+        }
+        assert_eq!(TimeControl::from_minutes("1e30"), None);
+        assert_eq!(TimeControl::from_minutes("1e18"), None);
+    }
+}
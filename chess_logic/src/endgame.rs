@@ -0,0 +1,410 @@
+use std::sync::OnceLock;
+
+use crate::engine::piece_value;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+// Knowledge about endings the search alone plays badly. Scores are in centipawns from the
+// side to move like engine::evaluate, squares are numbered from a1 like in the bitbase.
+
+pub const KNOWN_WIN: i32 = 10_000;
+pub const SCALE_FACTOR_NORMAL: i32 = 64;
+
+// KPK positions with white having the pawn, the pawn on the a-d files:
+// white king (6 bits), black king (6 bits), side to move (1 bit), pawn file (2 bits), 7 - pawn rank (3 bits)
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+static KPK_BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+fn rank_of(square: usize) -> usize {
+    square / 8
+}
+
+fn file_of(square: usize) -> usize {
+    square % 8
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    let rank_distance = (rank_of(a) as i32 - rank_of(b) as i32).unsigned_abs();
+    let file_distance = (file_of(a) as i32 - file_of(b) as i32).unsigned_abs();
+    rank_distance.max(file_distance) as usize
+}
+
+fn edge_distance(coordinate: usize) -> usize {
+    coordinate.min(7 - coordinate)
+}
+
+fn is_dark_square(square: usize) -> bool {
+    (rank_of(square) + file_of(square)) % 2 == 0
+}
+
+fn king_moves(square: usize) -> Vec<usize> {
+    let mut moves = Vec::with_capacity(8);
+    for rank in rank_of(square).saturating_sub(1)..=(rank_of(square) + 1).min(7) {
+        for file in file_of(square).saturating_sub(1)..=(file_of(square) + 1).min(7) {
+            if rank * 8 + file != square {
+                moves.push(rank * 8 + file);
+            }
+        }
+    }
+    moves
+}
+
+fn white_pawn_attacks(pawn: usize, square: usize) -> bool {
+    rank_of(square) == rank_of(pawn) + 1 && (file_of(square) as i32 - file_of(pawn) as i32).abs() == 1
+}
+
+fn kpk_index(white_to_move: bool, black_king: usize, white_king: usize, pawn: usize) -> usize {
+    white_king | (black_king << 6) | ((!white_to_move as usize) << 12) | (file_of(pawn) << 13) | ((6 - rank_of(pawn)) << 15)
+}
+
+// Positions that are decided without looking at the moves.
+fn kpk_initial_result(index: usize) -> u8 {
+    let white_king = index & 0x3f;
+    let black_king = (index >> 6) & 0x3f;
+    let white_to_move = (index >> 12) & 1 == 0;
+    let pawn = (6 - ((index >> 15) & 7)) * 8 + ((index >> 13) & 3);
+
+    if distance(white_king, black_king) <= 1 || white_king == pawn || black_king == pawn ||
+        (white_to_move && white_pawn_attacks(pawn, black_king)) {
+        return INVALID;
+    }
+
+    // The pawn promotes without being taken
+    if white_to_move && rank_of(pawn) == 6 && white_king != pawn + 8 &&
+        (distance(black_king, pawn + 8) > 1 || distance(white_king, pawn + 8) == 1) {
+        return WIN;
+    }
+
+    // Stalemate, or the black king can take the undefended pawn
+    if !white_to_move {
+        let white_king_moves = king_moves(white_king);
+        let black_king_moves = king_moves(black_king);
+        let stalemate = black_king_moves.iter().all(|square| white_king_moves.contains(square) || white_pawn_attacks(pawn, *square));
+        let takes_pawn = black_king_moves.contains(&pawn) && !white_king_moves.contains(&pawn);
+        if stalemate || takes_pawn {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+// A position is won if white has a move to a won position or every black move leads to one.
+fn kpk_classify(index: usize, results: &[u8]) -> u8 {
+    let white_king = index & 0x3f;
+    let black_king = (index >> 6) & 0x3f;
+    let white_to_move = (index >> 12) & 1 == 0;
+    let pawn = (6 - ((index >> 15) & 7)) * 8 + ((index >> 13) & 3);
+
+    let (good, bad) = if white_to_move { (WIN, DRAW) } else { (DRAW, WIN) };
+    let mut reachable = INVALID;
+
+    if white_to_move {
+        for square in king_moves(white_king) {
+            reachable |= results[kpk_index(false, black_king, square, pawn)];
+        }
+        if rank_of(pawn) < 6 {
+            reachable |= results[kpk_index(false, black_king, white_king, pawn + 8)];
+        }
+        if rank_of(pawn) == 1 && pawn + 8 != white_king && pawn + 8 != black_king {
+            reachable |= results[kpk_index(false, black_king, white_king, pawn + 16)];
+        }
+    } else {
+        for square in king_moves(black_king) {
+            reachable |= results[kpk_index(true, square, white_king, pawn)];
+        }
+    }
+
+    if reachable & good > 0 {
+        good
+    } else if reachable & UNKNOWN > 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
+
+fn generate_kpk_bitbase() -> Vec<u64> {
+    let mut results: Vec<u8> = (0..KPK_SIZE).map(kpk_initial_result).collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..KPK_SIZE {
+            if results[index] == UNKNOWN {
+                let result = kpk_classify(index, &results);
+                if result != UNKNOWN {
+                    results[index] = result;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut bitbase = vec![0u64; KPK_SIZE / 64];
+    for (index, result) in results.iter().enumerate() {
+        if *result == WIN {
+            bitbase[index / 64] |= 1 << (index % 64);
+        }
+    }
+    bitbase
+}
+
+// True if white wins, the pawn has to be on the a-d files. The bitbase is built on first use.
+pub fn probe_kpk(white_king: usize, pawn: usize, black_king: usize, white_to_move: bool) -> bool {
+    let bitbase = KPK_BITBASE.get_or_init(generate_kpk_bitbase);
+    let index = kpk_index(white_to_move, black_king, white_king, pawn);
+    bitbase[index / 64] & (1 << (index % 64)) > 0
+}
+
+#[derive(Default)]
+struct Material {
+    pawns: Vec<usize>,
+    knights: Vec<usize>,
+    bishops: Vec<usize>,
+    rooks: usize,
+    queens: usize,
+    king: usize,
+}
+
+impl Material {
+    fn of(game: &GAME, color: u8) -> Material {
+        let mut material = Material::default();
+        for (tile, piece) in game.board.iter().enumerate() {
+            if piece & color == 0 {
+                continue;
+            }
+            let square = (7 - tile / 8) * 8 + tile % 8;
+            if piece & TYPES::PAWN > 0 {
+                material.pawns.push(square);
+            } else if piece & TYPES::KNIGHT > 0 {
+                material.knights.push(square);
+            } else if piece & TYPES::BISHOP > 0 {
+                material.bishops.push(square);
+            } else if piece & TYPES::ROOK > 0 {
+                material.rooks += 1;
+            } else if piece & TYPES::QUEEN > 0 {
+                material.queens += 1;
+            } else {
+                material.king = square;
+            }
+        }
+        material
+    }
+
+    fn is_lone_king(&self) -> bool {
+        self.non_pawn_material() == 0 && self.pawns.is_empty()
+    }
+
+    fn non_pawn_material(&self) -> i32 {
+        self.knights.len() as i32 * piece_value(TYPES::KNIGHT) + self.bishops.len() as i32 * piece_value(TYPES::BISHOP) +
+            self.rooks as i32 * piece_value(TYPES::ROOK) + self.queens as i32 * piece_value(TYPES::QUEEN)
+    }
+
+    fn has_bishops_on_both_colors(&self) -> bool {
+        self.bishops.iter().any(|square| is_dark_square(*square)) && self.bishops.iter().any(|square| !is_dark_square(*square))
+    }
+}
+
+fn push_to_edge(square: usize) -> i32 {
+    let rank_distance = edge_distance(rank_of(square)) as i32;
+    let file_distance = edge_distance(file_of(square)) as i32;
+    90 - (7 * file_distance * file_distance / 2 + 7 * rank_distance * rank_distance / 2)
+}
+
+fn push_close(a: usize, b: usize) -> i32 {
+    140 - 20 * distance(a, b) as i32
+}
+
+// Large near a1 and h8, the corners of a dark squared bishop.
+fn push_to_dark_corner(square: usize) -> i32 {
+    (7 - rank_of(square) as i32 - file_of(square) as i32).abs()
+}
+
+// Mating material against a lone king: drive the king to the edge and bring the own king closer.
+fn evaluate_kxk(game: &GAME, strong: &Material, weak: &Material, strong_is_white: bool) -> i32 {
+    let weak_to_move = game.is_whites_turn() != strong_is_white;
+    if weak_to_move && !game.check && game.generate_legal_moves().is_empty() {
+        return 0;
+    }
+
+    let mut score = strong.non_pawn_material() + strong.pawns.len() as i32 * piece_value(TYPES::PAWN) +
+        push_to_edge(weak.king) + push_close(strong.king, weak.king);
+    if strong.queens > 0 || strong.rooks > 0 || (!strong.bishops.is_empty() && !strong.knights.is_empty()) ||
+        strong.has_bishops_on_both_colors() {
+        score += KNOWN_WIN;
+    }
+    score
+}
+
+// Bishop and knight mate: the king has to go to a corner of the bishop's colour.
+fn evaluate_kbnk(strong: &Material, weak: &Material) -> i32 {
+    let weak_king = if is_dark_square(strong.bishops[0]) { weak.king } else { weak.king ^ 7 };
+    KNOWN_WIN + 3520 + push_close(strong.king, weak.king) + 420 * push_to_dark_corner(weak_king)
+}
+
+fn evaluate_kpk(game: &GAME, strong: &Material, weak: &Material, strong_is_white: bool) -> i32 {
+    // Seen from the side with the pawn, with the pawn on the a-d files
+    let normalize = |square: usize| {
+        let square = if strong_is_white { square } else { square ^ 56 };
+        if file_of(strong.pawns[0]) >= 4 { square ^ 7 } else { square }
+    };
+    let pawn = normalize(strong.pawns[0]);
+    let strong_to_move = game.is_whites_turn() == strong_is_white;
+
+    if !probe_kpk(normalize(strong.king), pawn, normalize(weak.king), strong_to_move) {
+        return 0;
+    }
+    KNOWN_WIN + piece_value(TYPES::PAWN) + rank_of(pawn) as i32
+}
+
+// Pawns on a rook file that promote on a square the bishop can't control are a draw when the
+// defending king gets to the corner.
+fn is_wrong_rook_pawn_draw(strong: &Material, weak: &Material, strong_is_white: bool) -> bool {
+    if strong.pawns.is_empty() || !strong.knights.is_empty() || strong.rooks > 0 || strong.queens > 0 || !weak.is_lone_king() {
+        return false;
+    }
+    let file = file_of(strong.pawns[0]);
+    if (file != 0 && file != 7) || strong.pawns.iter().any(|pawn| file_of(*pawn) != file) {
+        return false;
+    }
+    let queening_square = if strong_is_white { 56 + file } else { file };
+    let bishop_controls_queening_square = strong.bishops.iter().any(|bishop| is_dark_square(*bishop) == is_dark_square(queening_square));
+    !bishop_controls_queening_square && distance(weak.king, queening_square) <= 1
+}
+
+// Two knights or bishops all on one colour can't force mate either.
+fn can_force_mate(material: &Material) -> bool {
+    if !material.pawns.is_empty() || material.rooks + material.queens > 0 {
+        return true;
+    }
+    if material.bishops.is_empty() {
+        return material.knights.len() > 2;
+    }
+    !material.knights.is_empty() || material.has_bishops_on_both_colors()
+}
+
+// Exact evaluations of known endings, None if the position isn't one of them.
+pub fn evaluate_endgame(game: &GAME) -> Option<i32> {
+    let white = Material::of(game, COLORS::WHITE);
+    let black = Material::of(game, COLORS::BLACK);
+
+    let (strong, weak, strong_is_white) = if black.is_lone_king() {
+        (&white, &black, true)
+    } else if white.is_lone_king() {
+        (&black, &white, false)
+    } else {
+        return None;
+    };
+
+    let score = if strong.is_lone_king() || is_wrong_rook_pawn_draw(strong, weak, strong_is_white) {
+        0
+    } else if strong.pawns.len() == 1 && strong.non_pawn_material() == 0 {
+        evaluate_kpk(game, strong, weak, strong_is_white)
+    } else if strong.pawns.is_empty() && strong.bishops.len() == 1 && strong.knights.len() == 1 && strong.rooks + strong.queens == 0 {
+        evaluate_kbnk(strong, weak)
+    } else if !can_force_mate(strong) {
+        0
+    } else if strong.non_pawn_material() >= piece_value(TYPES::ROOK) {
+        evaluate_kxk(game, strong, weak, strong_is_white)
+    } else {
+        return None;
+    };
+
+    Some(if game.is_whites_turn() == strong_is_white { score } else { -score })
+}
+
+// Out of SCALE_FACTOR_NORMAL. Endings with only bishops of opposite colours and pawns are
+// very drawish, the stronger side can rarely both stop the other bishop and promote.
+pub fn scale_factor(game: &GAME) -> i32 {
+    let white = Material::of(game, COLORS::WHITE);
+    let black = Material::of(game, COLORS::BLACK);
+
+    let only_bishops = |material: &Material| material.bishops.len() == 1 && material.non_pawn_material() == piece_value(TYPES::BISHOP);
+    if only_bishops(&white) && only_bishops(&black) && is_dark_square(white.bishops[0]) != is_dark_square(black.bishops[0]) {
+        let pawn_difference = (white.pawns.len() as i32 - black.pawns.len() as i32).abs();
+        return if pawn_difference <= 2 { SCALE_FACTOR_NORMAL / 4 } else { SCALE_FACTOR_NORMAL / 2 };
+    }
+    SCALE_FACTOR_NORMAL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_game_from_fen;
+
+    // Squares are numbered from a1 here, like in the bitbase.
+    fn square(name: &str) -> usize {
+        let bytes = name.as_bytes();
+        (bytes[1] - b'1') as usize * 8 + (bytes[0] - b'a') as usize
+    }
+
+    fn evaluate(fen: &str) -> i32 {
+        evaluate_endgame(&init_game_from_fen(fen)).expect("a known ending")
+    }
+
+    #[test]
+    fn kpk_king_in_front_on_the_sixth_rank_wins() {
+        assert!(probe_kpk(square("d6"), square("d5"), square("d8"), true));
+        assert!(probe_kpk(square("d6"), square("d5"), square("d8"), false));
+    }
+
+    #[test]
+    fn kpk_opposition_decides() {
+        assert!(!probe_kpk(square("d5"), square("d4"), square("d7"), true));
+        assert!(probe_kpk(square("d5"), square("d4"), square("d7"), false));
+    }
+
+    #[test]
+    fn kpk_pawn_outruns_the_king() {
+        assert!(probe_kpk(square("h1"), square("a6"), square("h8"), true));
+        assert!(probe_kpk(square("h1"), square("a7"), square("h8"), false));
+    }
+
+    #[test]
+    fn kpk_draws() {
+        // the rook pawn can't get the king out of the corner
+        assert!(!probe_kpk(square("c5"), square("a5"), square("a8"), true));
+        // the pawn is taken
+        assert!(!probe_kpk(square("h1"), square("d4"), square("c4"), false));
+    }
+
+    #[test]
+    fn kpk_evaluation_follows_the_bitbase() {
+        assert!(evaluate("3k4/8/3K4/3P4/8/8/8/8 b - - 0 1") <= -KNOWN_WIN);
+        assert_eq!(evaluate("k7/8/8/P1K5/8/8/8/8 w - - 0 1"), 0);
+        // black pawns and pawns on the e-h files are mirrored first
+        assert!(evaluate("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1") >= KNOWN_WIN);
+    }
+
+    #[test]
+    fn kbnk_drives_the_king_to_the_bishops_corner() {
+        // the bishop on c1 is on the dark squares like a1
+        let dark_corner = evaluate("8/8/8/8/8/2K5/8/k1BN4 w - - 0 1");
+        let light_corner = evaluate("8/8/8/8/8/5K2/8/2BN3k w - - 0 1");
+        assert!(dark_corner >= KNOWN_WIN && light_corner >= KNOWN_WIN);
+        assert!(dark_corner > light_corner);
+    }
+
+    #[test]
+    fn kxk_pushes_the_king_to_the_edge() {
+        let edge = evaluate("k7/8/2K5/8/8/8/8/7Q w - - 0 1");
+        let centre = evaluate("8/8/2K5/8/4k3/8/8/7Q w - - 0 1");
+        assert!(edge > centre && centre >= KNOWN_WIN);
+        assert!(evaluate("k7/8/2K5/8/8/8/8/7Q b - - 0 1") <= -KNOWN_WIN);
+    }
+
+    #[test]
+    fn drawn_material_scores_zero() {
+        assert_eq!(evaluate("k7/8/1Q6/8/8/8/8/7K b - - 0 1"), 0);               // stalemate
+        assert_eq!(evaluate("k7/8/8/8/8/8/8/5NNK w - - 0 1"), 0);
+        assert_eq!(evaluate("k7/8/8/8/8/8/8/7K w - - 0 1"), 0);
+        assert_eq!(evaluate("8/1k6/8/8/8/8/P7/K1B5 w - - 0 1"), 0);              // wrong rook pawn
+    }
+}
@@ -0,0 +1,444 @@
+use std::time::{Duration, Instant};
+
+use crate::book::{Book, BookSelection};
+use crate::endgame::{self, SCALE_FACTOR_NORMAL};
+use crate::make_move;
+use crate::syzygy::{Tablebase, Wdl};
+use crate::variant::{Variant, CHECKS_TO_WIN, HILL_TILES, POCKET_PIECES};
+use crate::Move;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+pub const MATE_SCORE: i32 = 100_000;
+pub const TABLEBASE_WIN_SCORE: i32 = 50_000;
+const INFINITY: i32 = 1_000_000;
+const ENDGAME_PIECES: usize = 8;                                // endgame knowledge is only looked up with this few pieces
+
+// Piece-square tables seen from white, tile 0 is a8 like the board.
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+const KING_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+pub struct Engine {
+    pub depth: u8,                                              // the maximum depth when searching with a movetime
+    pub movetime: Option<Duration>,
+    pub book: Option<Book>,
+    pub book_selection: BookSelection,
+    pub tablebase: Option<Tablebase>,
+}
+
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    pub score: i32,                                             // from the side to move, in centipawns
+    pub nodes: u64,
+    pub depth: u8,                                              // of the last finished iteration
+    pub from_book: bool,
+}
+
+struct SearchState {
+    nodes: u64,
+    deadline: Option<Instant>,
+    stopped: bool,
+}
+
+impl SearchState {
+    fn out_of_time(&mut self) -> bool {
+        if let Some(deadline) = self.deadline {
+            if !self.stopped && Instant::now() >= deadline {
+                self.stopped = true;
+            }
+        }
+        self.stopped
+    }
+}
+
+impl Engine {
+    pub fn new(depth: u8) -> Engine {
+        Engine {
+            depth,
+            movetime: None,
+            book: None,
+            book_selection: BookSelection::WeightedRandom,
+            tablebase: None,
+        }
+    }
+
+    pub fn with_book(mut self, book: Book, book_selection: BookSelection) -> Engine {
+        self.book = Some(book);
+        self.book_selection = book_selection;
+        self
+    }
+
+    // Searches deeper and deeper until the time is up, keeping the move of the last finished depth.
+    pub fn with_movetime(mut self, movetime: Duration) -> Engine {
+        self.movetime = Some(movetime);
+        self
+    }
+
+    pub fn with_tablebase(mut self, tablebase: Tablebase) -> Engine {
+        self.tablebase = Some(tablebase);
+        self
+    }
+
+    pub fn best_move(&self, game: &GAME) -> Option<Move> {
+        self.search(game).best_move
+    }
+
+    pub fn search(&self, game: &GAME) -> SearchResult {
+        if let Some(book) = &self.book {
+            if let Some(book_move) = book.choose_move(game, self.book_selection) {
+                return SearchResult { best_move: Some(book_move), score: 0, nodes: 0, depth: 0, from_book: true };
+            }
+        }
+
+        let mut state = SearchState {
+            nodes: 0,
+            deadline: self.movetime.map(|movetime| Instant::now() + movetime),
+            stopped: false,
+        };
+        let mut root_moves = self.root_moves(game);
+        if root_moves.is_empty() {
+            let score = match game.get_variant_winner() {
+                Some(winner) if winner == game.turn => MATE_SCORE,
+                Some(_) => -MATE_SCORE,
+                None if game.check => -MATE_SCORE,
+                None => 0,
+            };
+            return SearchResult { best_move: None, score, nodes: 0, depth: 0, from_book: false };
+        }
+
+        let max_depth = self.depth.max(1);
+        let first_depth = if state.deadline.is_some() { 1 } else { max_depth };
+        let mut result = SearchResult { best_move: None, score: -INFINITY, nodes: 0, depth: 0, from_book: false };
+
+        for depth in first_depth..=max_depth {
+            let (best_move, score) = self.search_root(game, &root_moves, depth, &mut state);
+            if state.stopped && result.best_move.is_some() {
+                break;
+            }
+            result.best_move = best_move;
+            result.score = score;
+            result.depth = depth;
+            if state.stopped || score.abs() >= MATE_SCORE - depth as i32 {
+                break;
+            }
+
+            // the best move so far is searched first on the next depth
+            if let Some(index) = root_moves.iter().position(|chess_move| Some(*chess_move) == best_move) {
+                let best = root_moves.remove(index);
+                root_moves.insert(0, best);
+            }
+        }
+        result.nodes = state.nodes;
+        result
+    }
+
+    // Every legal move with its exact score at the engine's depth, best first. Slower than search
+    // since nothing is cut at the root, for tools that compare the moves of a position.
+    pub fn score_moves(&self, game: &GAME) -> Vec<(Move, i32)> {
+        let mut state = SearchState { nodes: 0, deadline: None, stopped: false };
+        let depth = self.depth.max(1);
+        let mut scored_moves: Vec<(Move, i32)> = self.root_moves(game).into_iter().map(|chess_move| {
+            let mut virtual_game = game.clone();
+            make_move(&chess_move, &mut virtual_game);
+            (chess_move, -self.negamax(&virtual_game, depth - 1, -INFINITY, INFINITY, 1, &mut state))
+        }).collect();
+        scored_moves.sort_by_key(|(_, score)| -score);
+        scored_moves
+    }
+
+    fn search_root(&self, game: &GAME, root_moves: &[Move], depth: u8, state: &mut SearchState) -> (Option<Move>, i32) {
+        let mut best_move = None;
+        let mut alpha = -INFINITY;
+        let beta = INFINITY;
+
+        for chess_move in root_moves {
+            let mut virtual_game = game.clone();
+            make_move(chess_move, &mut virtual_game);
+            let score = -self.negamax(&virtual_game, depth - 1, -beta, -alpha, 1, state);
+            if state.stopped && best_move.is_some() {
+                break;
+            }
+            if score > alpha || best_move.is_none() {
+                alpha = score;
+                best_move = Some(*chess_move);
+            }
+        }
+        (best_move, alpha)
+    }
+
+    // With a tablebase only the moves keeping the best result are searched, and when winning
+    // only those that reach the next capture or pawn move fastest.
+    fn root_moves(&self, game: &GAME) -> Vec<Move> {
+        let moves = ordered_moves(game);
+        let tablebase = match &self.tablebase {
+            Some(tablebase) if tablebase.can_probe(game) => tablebase,
+            _ => return moves,
+        };
+
+        let mut ranked_moves = Vec::new();
+        for chess_move in &moves {
+            match tablebase.probe_dtz_after_move(game, chess_move) {
+                Some(dtz) => ranked_moves.push((*chess_move, dtz)),
+                None => return moves,
+            }
+        }
+
+        let wdl_rank = |dtz: i32| if dtz > 100 { 1 } else if dtz > 0 { 2 } else if dtz == 0 { 0 } else if dtz >= -100 { -2 } else { -1 };
+        let best_rank = ranked_moves.iter().map(|(_, dtz)| wdl_rank(*dtz)).max().unwrap_or(0);
+        ranked_moves.retain(|(_, dtz)| wdl_rank(*dtz) == best_rank);
+        if best_rank > 0 {
+            let fastest = ranked_moves.iter().map(|(_, dtz)| *dtz).min().unwrap_or(0);
+            ranked_moves.retain(|(_, dtz)| *dtz == fastest);
+        }
+        ranked_moves.into_iter().map(|(chess_move, _)| chess_move).collect()
+    }
+
+    fn negamax(&self, game: &GAME, depth: u8, mut alpha: i32, beta: i32, ply: i32, state: &mut SearchState) -> i32 {
+        state.nodes += 1;
+        if state.out_of_time() {
+            return 0;
+        }
+        if let Some(winner) = game.get_variant_winner() {
+            return if winner == game.turn { MATE_SCORE - ply } else { -MATE_SCORE + ply };
+        }
+        if game.is_variant_draw() {
+            return 0;
+        }
+        if let Some(score) = self.probe_tablebase(game, ply) {
+            return score;
+        }
+        if depth == 0 {
+            return quiescence(game, alpha, beta, &mut state.nodes);
+        }
+
+        let moves = ordered_moves(game);
+        if moves.is_empty() {
+            return if game.check { -MATE_SCORE + ply } else { 0 };
+        }
+
+        for chess_move in moves {
+            let mut virtual_game = game.clone();
+            make_move(&chess_move, &mut virtual_game);
+            let score = -self.negamax(&virtual_game, depth - 1, -beta, -alpha, ply + 1, state);
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+        alpha
+    }
+
+    // Wins found in the tablebase score below mates but above anything the evaluation gives.
+    fn probe_tablebase(&self, game: &GAME, ply: i32) -> Option<i32> {
+        let tablebase = self.tablebase.as_ref()?;
+        if !tablebase.can_probe(game) {
+            return None;
+        }
+        let score = match tablebase.probe_wdl(game)? {
+            Wdl::Win => TABLEBASE_WIN_SCORE - ply,
+            Wdl::CursedWin => 1,
+            Wdl::Draw => 0,
+            Wdl::BlessedLoss => -1,
+            Wdl::Loss => -TABLEBASE_WIN_SCORE + ply,
+        };
+        Some(score)
+    }
+}
+
+fn quiescence(game: &GAME, mut alpha: i32, beta: i32, nodes: &mut u64) -> i32 {
+    *nodes += 1;
+    let stand_pat = evaluate(game);
+    if stand_pat >= beta {
+        return beta;
+    }
+    if stand_pat > alpha {
+        alpha = stand_pat;
+    }
+
+    for chess_move in ordered_moves(game) {
+        if !game.is_capture(&chess_move) {
+            continue;
+        }
+        let mut virtual_game = game.clone();
+        make_move(&chess_move, &mut virtual_game);
+        let score = -quiescence(&virtual_game, -beta, -alpha, nodes);
+        if score >= beta {
+            return beta;
+        }
+        if score > alpha {
+            alpha = score;
+        }
+    }
+    alpha
+}
+
+// Captures first, most valuable victim by least valuable attacker, then promotions.
+fn ordered_moves(game: &GAME) -> Vec<Move> {
+    let mut moves = game.generate_legal_moves();
+    moves.sort_by_key(|chess_move| {
+        let mut order = 0;
+        if game.is_capture(chess_move) {
+            order -= 10 * piece_value(game.board[chess_move.to]) - piece_value(game.board[chess_move.from]) / 100;
+        }
+        order -= piece_value(chess_move.promotion);
+        order
+    });
+    moves
+}
+
+pub fn piece_value(piece: u8) -> i32 {
+    if piece & TYPES::PAWN > 0 {
+        100
+    } else if piece & TYPES::KNIGHT > 0 {
+        320
+    } else if piece & TYPES::BISHOP > 0 {
+        330
+    } else if piece & TYPES::ROOK > 0 {
+        500
+    } else if piece & TYPES::QUEEN > 0 {
+        900
+    } else {
+        0
+    }
+}
+
+fn piece_square_value(piece: u8, tile: usize) -> i32 {
+    let tile = if piece & COLORS::WHITE > 0 { tile } else { tile ^ 56 };
+    if piece & TYPES::PAWN > 0 {
+        PAWN_TABLE[tile]
+    } else if piece & TYPES::KNIGHT > 0 {
+        KNIGHT_TABLE[tile]
+    } else if piece & TYPES::BISHOP > 0 {
+        BISHOP_TABLE[tile]
+    } else if piece & TYPES::KING > 0 {
+        KING_TABLE[tile]
+    } else {
+        0
+    }
+}
+
+// Material and piece placement, positive when the side to move is better.
+pub fn evaluate(game: &GAME) -> i32 {
+    if let Some(winner) = game.get_variant_winner() {
+        return if winner == game.turn { MATE_SCORE } else { -MATE_SCORE };
+    }
+    if game.get_variant() == Variant::Antichess {
+        return antichess_evaluation(game);
+    }
+    // the endgame rules of standard chess don't hold in the variants
+    let few_pieces = game.get_variant() == Variant::Standard &&
+        game.board.iter().filter(|piece| **piece != TYPES::NONE).count() <= ENDGAME_PIECES;
+    if few_pieces {
+        if let Some(score) = endgame::evaluate_endgame(game) {
+            return score;
+        }
+    }
+
+    let mut score = 0;
+    for (tile, piece) in game.board.iter().enumerate() {
+        if *piece == TYPES::NONE {
+            continue;
+        }
+        let value = piece_value(*piece) + piece_square_value(*piece, tile) + variant_square_value(game, *piece, tile);
+        if piece & COLORS::WHITE > 0 {
+            score += value;
+        } else {
+            score -= value;
+        }
+    }
+    if few_pieces {
+        score = score * endgame::scale_factor(game) / SCALE_FACTOR_NORMAL;
+    }
+    if game.get_variant() == Variant::Crazyhouse {
+        // pieces in hand count like the ones on the board
+        for (index, piece_type) in POCKET_PIECES.iter().enumerate() {
+            let in_hand = game.get_pocket(COLORS::WHITE)[index] as i32 - game.get_pocket(COLORS::BLACK)[index] as i32;
+            score += in_hand * piece_value(*piece_type);
+        }
+    }
+    if game.get_variant() == Variant::ThreeCheck {
+        let checks_given = game.get_checks_given();
+        score += three_check_value(checks_given[0]) - three_check_value(checks_given[1]);
+    }
+    if game.turn == COLORS::WHITE { score } else { -score }
+}
+
+// In king of the hill the king is pulled towards the centre, more so the closer it gets,
+// in racing kings both kings towards the 8th rank.
+fn variant_square_value(game: &GAME, piece: u8, tile: usize) -> i32 {
+    if piece & TYPES::KING == 0 {
+        return 0;
+    }
+    if game.get_variant() == Variant::RacingKings {
+        return (7 - tile / 8) as i32 * 60;
+    }
+    if game.get_variant() != Variant::KingOfTheHill {
+        return 0;
+    }
+    let distance = HILL_TILES.iter().map(|hill_tile| {
+        let file_distance = (tile % 8) as i32 - (hill_tile % 8) as i32;
+        let rank_distance = (tile / 8) as i32 - (hill_tile / 8) as i32;
+        file_distance.abs().max(rank_distance.abs())
+    }).min().unwrap_or(0);
+    [0, 300, 120, 50, 20, 0, 0, 0][distance as usize]
+}
+
+// Every check given is worth more than the one before in three-check.
+fn three_check_value(checks_given: u8) -> i32 {
+    match checks_given.min(CHECKS_TO_WIN) {
+        0 => 0,
+        1 => 150,
+        _ => 500,
+    }
+}
+
+// Fewer pieces is better in antichess.
+fn antichess_evaluation(game: &GAME) -> i32 {
+    let own_pieces = game.board.iter().filter(|piece| **piece & game.turn > 0).count() as i32;
+    let enemy_pieces = game.board.iter().filter(|piece| **piece != TYPES::NONE && **piece & game.turn == 0).count() as i32;
+    (enemy_pieces - own_pieces) * 100
+}
@@ -0,0 +1,206 @@
+use std::fs;
+use std::io;
+
+use crate::init_game_from_fen;
+use crate::pgn::san_to_move;
+use crate::validation::game_from_fen;
+use crate::variant::Variant;
+use crate::Move;
+use crate::GAME;
+
+// An EPD record is the first four FEN fields followed by operations like
+// bm Qg6; id "WAC.001"; each an opcode with zero or more operands ended by a semicolon.
+#[derive(Clone, Debug, Default)]
+pub struct EpdRecord {
+    pub fen: String,                                            // with the move counters of hmvc and fmvn, or "0 1"
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdRecord {
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(name, _)| name == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.operation("id").and_then(|operands| operands.first()).map(|id| id.as_str())
+    }
+
+    pub fn get_comment(&self) -> Option<&str> {
+        self.operation("c0").and_then(|operands| operands.first()).map(|comment| comment.as_str())
+    }
+
+    // acd, ce, dm and the other operations with a single number
+    pub fn get_integer(&self, opcode: &str) -> Option<i64> {
+        self.operation(opcode)?.first()?.parse().ok()
+    }
+
+    pub fn get_game(&self) -> GAME {
+        init_game_from_fen(&self.fen)
+    }
+
+    // The bm moves as legal moves of the position, the first one that doesn't fit the position is an error.
+    pub fn get_best_moves(&self) -> Result<Vec<Move>, String> {
+        self.moves_of_operation("bm")
+    }
+
+    pub fn get_avoid_moves(&self) -> Result<Vec<Move>, String> {
+        self.moves_of_operation("am")
+    }
+
+    fn moves_of_operation(&self, opcode: &str) -> Result<Vec<Move>, String> {
+        let game = self.get_game();
+        let operands = self.operation(opcode).unwrap_or(&[]);
+        operands.iter().map(|san| {
+            san_to_move(san, &game)
+                .or_else(|| game.generate_legal_moves().into_iter().find(|chess_move| chess_move.to_uci() == *san))
+                .ok_or_else(|| format!("{} {} is not a legal move", opcode, san))
+        }).collect()
+    }
+}
+
+// The records with their line numbers, counted from 1, lines that can't be read keep why.
+pub fn read_epd_file(path: &str) -> io::Result<Vec<(usize, Result<EpdRecord, String>)>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.lines().enumerate().filter_map(|(number, line)| parse_epd_line(line).transpose().map(|record| (number + 1, record))).collect())
+}
+
+// None for empty lines and comments starting with #, an error for lines without a legal position.
+pub fn parse_epd_line(line: &str) -> Result<Option<EpdRecord>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut rest = line;
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if fields[3].is_empty() {
+        return Err("an EPD line starts with the pieces, side to move, castling and en passant fields".to_string());
+    }
+
+    let mut record = EpdRecord {
+        fen: String::new(),
+        operations: Vec::new(),
+    };
+
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_string = false;
+    for character in rest.chars() {
+        if in_string {
+            if character == '"' {
+                in_string = false;
+                words.push(word.clone());
+                word.clear();
+            } else {
+                word.push(character);
+            }
+            continue;
+        }
+        match character {
+            '"' => in_string = true,
+            ';' => {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    record.operations.push((opcode, words.clone()));
+                    words.clear();
+                }
+            }
+            _ if character.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+            }
+            _ => word.push(character),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        record.operations.push((opcode, words));               // last operation without its semicolon
+    }
+    let halfmove_clock = record.get_integer("hmvc").filter(|halfmoves| *halfmoves >= 0).unwrap_or(0);
+    let fullmove_number = record.get_integer("fmvn").filter(|fullmoves| *fullmoves >= 1).unwrap_or(1);
+    record.fen = format!("{} {} {}", fields.join(" "), halfmove_clock, fullmove_number);
+    game_from_fen(&record.fen, Variant::Standard)?;
+    Ok(Some(record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn parse(line: &str) -> EpdRecord {
+        parse_epd_line(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn operations() {
+        let record = parse(r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id "WAC.001"; c0 "mate; in three";"#);
+        assert_eq!(record.fen, "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1");
+        assert_eq!(record.get_id(), Some("WAC.001"));
+        assert_eq!(record.get_comment(), Some("mate; in three"));
+        let best_moves: Vec<String> = record.get_best_moves().unwrap().iter().map(|chess_move| chess_move.to_uci()).collect();
+        assert_eq!(best_moves, ["g3g6"]);
+        assert!(record.get_avoid_moves().unwrap().is_empty());
+    }
+
+    #[test]
+    fn several_moves_and_avoid_moves() {
+        let record = parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 d2d4; am f3 g4; acd 12");
+        let best_moves: Vec<String> = record.get_best_moves().unwrap().iter().map(|chess_move| chess_move.to_uci()).collect();
+        let avoid_moves: Vec<String> = record.get_avoid_moves().unwrap().iter().map(|chess_move| chess_move.to_uci()).collect();
+        assert_eq!(best_moves, ["e2e4", "d2d4"]);
+        assert_eq!(avoid_moves, ["f2f3", "g2g4"]);
+        assert_eq!(record.get_integer("acd"), Some(12));
+        assert_eq!(record.get_id(), None);
+
+        let record = parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e5;");
+        assert_eq!(record.get_best_moves().unwrap_err(), "bm e5 is not a legal move");
+    }
+
+    #[test]
+    fn move_counters() {
+        let record = parse("4k3/8/8/8/8/8/8/4K2R w K - hmvc 12; fmvn 40;");
+        assert_eq!(record.fen, "4k3/8/8/8/8/8/8/4K2R w K - 12 40");
+        assert_eq!(record.get_game().get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 12 40");
+        let record = parse("4k3/8/8/8/8/8/8/4K2R w K - hmvc -3; fmvn 0;");
+        assert_eq!(record.fen, "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+    }
+
+    #[test]
+    fn lines_that_are_not_records() {
+        assert!(parse_epd_line("").unwrap().is_none());
+        assert!(parse_epd_line("  # WAC").unwrap().is_none());
+        assert!(parse_epd_line("8/8/8/8/8/8/8/8 w").is_err());
+        assert!(parse_epd_line("8/8/8/8/8/8/8/8 w - - bm Ka1;").is_err());   // no kings
+    }
+
+    #[test]
+    fn line_numbers() {
+        let path = env::temp_dir().join(format!("chess_logic_epd_{}.epd", process::id()));
+        fs::write(&path, "# a suite\n\n4k3/8/8/8/8/8/8/4K2R w K - bm Rh8; id \"one\";\n4k3/8/8/8 w - - bm Kd2;\n4k3/8/8/8/8/8/8/4K2R b K - bm Kd7;\n").unwrap();
+        let records = read_epd_file(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let numbers: Vec<usize> = records.iter().map(|(number, _)| *number).collect();
+        assert_eq!(numbers, [3, 4, 5]);
+        assert_eq!(records[0].1.as_ref().unwrap().get_id(), Some("one"));
+        assert!(records[1].1.is_err());
+        assert_eq!(records[2].1.as_ref().unwrap().fen, "4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+    }
+}
@@ -1,13 +1,29 @@
 use std::{collections::HashMap, convert::TryInto};
 use std::cmp;
 
+mod analysis;
 mod movement;
+mod zobrist;
+pub mod book;
+pub mod chess960;
+pub mod clock;
+pub mod endgame;
+pub mod engine;
+pub mod epd;
+pub mod outcome;
+pub mod pgn;
+pub mod puzzle;
+pub mod setup;
+pub mod syzygy;
+pub mod tactics;
+pub mod validation;
+pub mod variant;
 
 use movement::available_moves_for_piece;
-use movement::get_all_attacked_squares;
+use variant::Variant;
 
 #[non_exhaustive]
-struct TYPES;
+pub struct TYPES;
 
 impl TYPES {
     pub const NONE: u8 = 0;
@@ -20,7 +36,7 @@ impl TYPES {
 }
 
 #[non_exhaustive]
-struct COLORS;
+pub struct COLORS;
 
 impl COLORS {
     pub const WHITE: u8 = 64;
@@ -36,12 +52,81 @@ pub struct GAME {
     tile_available_to_un_passant: u8,
     potential_tile_to_un_passant: u8,
     chastling_ability: [bool; 4],                               // KQkq
+    chastling_rooks: [usize; 4],                                // the tile of the rook for every castling right
+    chess960: bool,
+    variant: Variant,
+    checks_given: [u8; 2],                                      // by white and black, counted in three-check only
+    pockets: [[u8; 5]; 2],                                      // crazyhouse pieces in hand of white and black, PNBRQ
+    promoted: [bool; 64],                                       // pieces that were pawns, they go back to pawns when captured
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    position_keys: Vec<u64>,                                    // the positions since the last capture or pawn move, with pockets and checks
     check: bool,
-    draw: bool,
-    check_mate: bool,
     promoting: u8,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub promotion: u8,                                          // TYPES value, TYPES::NONE if not promoting
+    pub drop: u8,                                               // TYPES value of a piece put down from the pocket, from is then the same as to
+}
+
+impl Move {
+    pub fn new(from: usize, to: usize, promotion: u8) -> Move {
+        Move { from, to, promotion, drop: TYPES::NONE }
+    }
+
+    // A crazyhouse drop like N@f3.
+    pub fn new_drop(piece_type: u8, to: usize) -> Move {
+        Move { from: to, to, promotion: TYPES::NONE, drop: piece_type }
+    }
+
+    pub fn is_drop(&self) -> bool {
+        self.drop != TYPES::NONE
+    }
+
+    pub fn to_uci(&self) -> String {
+        if self.is_drop() {
+            return format!("{}@{}", piece_symbol(self.drop | COLORS::WHITE), memory_location_to_algebraic_notation(self.to));
+        }
+        let mut uci = memory_location_to_algebraic_notation(self.from);
+        uci.push_str(&memory_location_to_algebraic_notation(self.to));
+        if self.promotion != TYPES::NONE {
+            uci.push_str(promotion_symbol(self.promotion));
+        }
+        uci
+    }
+}
+
+fn piece_symbol(piece: u8) -> char {
+    let symbol = if piece & TYPES::PAWN > 0 {
+        'p'
+    } else if piece & TYPES::KNIGHT > 0 {
+        'n'
+    } else if piece & TYPES::BISHOP > 0 {
+        'b'
+    } else if piece & TYPES::ROOK > 0 {
+        'r'
+    } else if piece & TYPES::QUEEN > 0 {
+        'q'
+    } else {
+        'k'
+    };
+    if piece & COLORS::WHITE > 0 { symbol.to_ascii_uppercase() } else { symbol }
+}
+
+fn promotion_symbol(piece_type: u8) -> &'static str {
+    match piece_type {
+        TYPES::QUEEN => "q",
+        TYPES::ROOK => "r",
+        TYPES::BISHOP => "b",
+        TYPES::KING => "k",
+        _ => "n",
+    }
+}
+
 impl GAME {
     fn tiles_to_the_edge() -> [[u8; 8]; 64] {
         let mut precomputed_distances = [[0u8; 8]; 64];
@@ -86,11 +171,12 @@ impl GAME {
     }
 
     pub fn is_check_mate(&self) -> bool {
-        self.check_mate
+        self.check && self.generate_legal_moves().is_empty()
     }
 
+    // Drawn by the rules, like stalemate, repetition or insufficient material.
     pub fn is_draw(&self) -> bool {
-        self.draw
+        self.get_outcome().is_some_and(|outcome| outcome.result == outcome::GameResult::Draw)
     }
 
     pub fn is_whites_turn(&self) -> bool {
@@ -101,8 +187,202 @@ impl GAME {
         }
     }
 
-    pub fn get_game_status(&self) -> (bool, bool, bool, bool) {
-        (self.is_whites_turn(), self.is_check(), self.is_draw(), self.is_check_mate())
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    // In chess960 castling is played as the king taking its own rook.
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+    }
+
+    pub fn get_variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.check = side_to_move_is_checked(self);
+    }
+
+    // Checks given by white and black so far.
+    pub fn get_checks_given(&self) -> [u8; 2] {
+        self.checks_given
+    }
+
+    // The color that won by a rule of the variant, like reaching the hill or giving the third check.
+    pub fn get_variant_winner(&self) -> Option<u8> {
+        variant::variant_winner(self)
+    }
+
+    // Draws of the variant rules besides stalemate, like both kings reaching the last rank in racing kings.
+    pub fn is_variant_draw(&self) -> bool {
+        variant::is_variant_draw(self)
+    }
+
+    // The crazyhouse pieces in hand of a color, counted as pawns, knights, bishops, rooks and queens.
+    pub fn get_pocket(&self, color: u8) -> [u8; 5] {
+        self.pockets[if color == COLORS::WHITE { 0 } else { 1 }]
+    }
+
+    pub fn is_promoted(&self, tile: usize) -> bool {
+        self.promoted[tile] && self.board[tile] != TYPES::NONE
+    }
+
+    pub fn get_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn get_fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn get_zobrist_key(&self) -> u64 {
+        zobrist::polyglot_key(self)
+    }
+
+    pub fn generate_legal_moves(&self) -> Vec<Move> {
+        let mut legal_moves = Vec::new();
+        let mut game = self.clone();
+        let board = self.board;
+
+        for (from_tile, piece) in board.iter().enumerate() {
+            if piece & self.turn == 0 {
+                continue;
+            }
+            let possible_moves_for_piece = available_moves_for_piece(*piece, from_tile, &mut game);
+            for (to_tile, possible) in possible_moves_for_piece.iter().enumerate() {
+                if !possible {
+                    continue;
+                }
+                let mut virtual_game = self.clone();
+                if move_piece_from_tile_to_tile(from_tile, to_tile, &mut virtual_game) {
+                    if virtual_game.promoting <= 63 {
+                        for promotion in [TYPES::QUEEN, TYPES::ROOK, TYPES::BISHOP, TYPES::KNIGHT, TYPES::KING] {
+                            if promotion != TYPES::KING || self.variant == Variant::Antichess {
+                                legal_moves.push(Move::new(from_tile, to_tile, promotion));
+                            }
+                        }
+                    } else {
+                        legal_moves.push(Move::new(from_tile, to_tile, TYPES::NONE));
+                    }
+                }
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            for (index, count) in self.get_pocket(self.turn).iter().enumerate() {
+                if *count == 0 {
+                    continue;
+                }
+                for to_tile in 0..64 {
+                    let mut virtual_game = self.clone();
+                    if drop_piece(variant::POCKET_PIECES[index], to_tile, &mut virtual_game) {
+                        legal_moves.push(Move::new_drop(variant::POCKET_PIECES[index], to_tile));
+                    }
+                }
+            }
+        }
+        legal_moves
+    }
+
+    // The piece types a pawn going from from_tile to to_tile may become, empty when that isn't a promotion.
+    // For frontends to ask which one before playing the move, the game never waits half way through a move.
+    pub fn pending_promotion(&self, from_tile: usize, to_tile: usize) -> Vec<u8> {
+        self.generate_legal_moves().iter()
+            .filter(|chess_move| chess_move.from == from_tile && chess_move.to == to_tile && chess_move.promotion != TYPES::NONE)
+            .map(|chess_move| chess_move.promotion)
+            .collect()
+    }
+
+    pub fn is_capture(&self, chess_move: &Move) -> bool {
+        (self.board[chess_move.to] != TYPES::NONE && self.board[chess_move.to] & self.turn == 0) ||
+            (self.board[chess_move.from] & TYPES::PAWN > 0 && chess_move.to == self.tile_available_to_un_passant as usize)
+    }
+
+    pub fn is_castling(&self, chess_move: &Move) -> bool {
+        chastling_right_of_move(self, self.board[chess_move.from], chess_move.from, chess_move.to).is_some()
+    }
+
+    // X-FEN: castling is written KQkq unless the rook isn't the outermost one, then its file is used.
+    pub fn get_fen(&self) -> String {
+        self.fen_with_castling(false)
+    }
+
+    // Shredder-FEN: castling is always written with the files of the rooks, like HAha.
+    pub fn get_shredder_fen(&self) -> String {
+        self.fen_with_castling(true)
+    }
+
+    fn fen_with_castling(&self, shredder: bool) -> String {
+        let mut fen = String::new();
+        for rank in 0..8 {
+            let mut empty_tiles = 0;
+            for file in 0..8 {
+                let piece = self.board[rank * 8 + file];
+                if piece == TYPES::NONE {
+                    empty_tiles += 1;
+                    continue;
+                }
+                if empty_tiles > 0 {
+                    fen.push_str(&empty_tiles.to_string());
+                    empty_tiles = 0;
+                }
+                fen.push(piece_symbol(piece));
+                if self.variant == Variant::Crazyhouse && self.promoted[rank * 8 + file] {
+                    fen.push('~');
+                }
+            }
+            if empty_tiles > 0 {
+                fen.push_str(&empty_tiles.to_string());
+            }
+            if rank < 7 {
+                fen.push('/');
+            }
+        }
+
+        if self.variant == Variant::Crazyhouse {
+            fen.push_str(&variant::pocket_field(self));
+        }
+        fen.push_str(if self.turn == COLORS::WHITE { " w " } else { " b " });
+
+        let mut castling = String::new();
+        for right in 0..4 {
+            if !self.chastling_ability[right] {
+                continue;
+            }
+            let rook_tile = self.chastling_rooks[right];
+            let white = right < 2;
+            let king_side = right % 2 == 0;
+            let outermost = self.board.iter().enumerate().filter(|(tile, piece)| {
+                tile / 8 == rook_tile / 8 && **piece == self.board[rook_tile]
+            }).all(|(tile, _)| if king_side { tile <= rook_tile } else { tile >= rook_tile });
+
+            let symbol = if shredder || !outermost {
+                (b'a' + (rook_tile % 8) as u8) as char
+            } else if king_side {
+                'k'
+            } else {
+                'q'
+            };
+            castling.push(if white { symbol.to_ascii_uppercase() } else { symbol });
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+
+        if self.tile_available_to_un_passant <= 63 {
+            fen.push(' ');
+            fen.push_str(&memory_location_to_algebraic_notation(self.tile_available_to_un_passant as usize));
+        } else {
+            fen.push_str(" -");
+        }
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+        if self.variant == Variant::ThreeCheck {
+            fen.push(' ');
+            fen.push_str(&variant::checks_field(self));
+        }
+        fen
     }
 
     // pub fn generate_all_possible_moves(&mut self, color_playing: u8) {               NOT WORKING
@@ -128,12 +408,109 @@ impl GAME {
     // }
 }
 
+// A pawn reaching the last rank is refused, promotions are played with make_move and the piece in the Move.
 pub fn move_piece_from_to(from_tile: &str, to_tile: &str, game: &mut GAME) -> bool {
     let from_tile = algebraic_notation_to_memory_location(from_tile);
     let to_tile = algebraic_notation_to_memory_location(to_tile);
+    make_move(&Move::new(from_tile, to_tile, TYPES::NONE), game)
+}
+
+// Plays a move including its promotion, the game is left untouched if the move is illegal.
+pub fn make_move(chess_move: &Move, game: &mut GAME) -> bool {
+    if chess_move.is_drop() {
+        let dropped = drop_piece(chess_move.drop, chess_move.to, game);
+        if dropped {
+            record_position(game);
+        }
+        return dropped;
+    }
+    let mut virtual_game = game.clone();
+    if !move_piece_from_tile_to_tile(chess_move.from, chess_move.to, &mut virtual_game) {
+        return false;
+    }
+    if virtual_game.promoting > 63 {
+        record_position(&mut virtual_game);
+    }
+    if virtual_game.promoting <= 63 {
+        if chess_move.promotion == TYPES::NONE {
+            return false;
+        }
+        if !promote_pawn_to(chess_move.promotion, &mut virtual_game) {
+            return false;
+        }
+        virtual_game.check = side_to_move_is_checked(&virtual_game);
+        if !variant::allows_position(&virtual_game) {
+            return false;
+        }
+        variant::after_move(&mut virtual_game, game.turn);
+        record_position(&mut virtual_game);
+    } else if chess_move.promotion != TYPES::NONE {
+        return false;
+    }
+    *game = virtual_game;
+    true
+}
+
+// Remembers the position just reached, a capture or pawn move makes the earlier ones unreachable.
+fn record_position(game: &mut GAME) {
+    if game.halfmove_clock == 0 {
+        game.position_keys.clear();
+    }
+    let key = zobrist::repetition_key(game);
+    game.position_keys.push(key);
+}
+
+// Puts a crazyhouse piece from the pocket of the side to move on an empty tile, pawns can't go on the first or last rank.
+pub fn drop_piece(piece_type: u8, to_tile: usize, game: &mut GAME) -> bool {
+    let pocket_index = match variant::POCKET_PIECES.iter().position(|pocket_piece| *pocket_piece == piece_type) {
+        Some(pocket_index) => pocket_index,
+        None => return false,
+    };
+    let side = if game.turn == COLORS::WHITE { 0 } else { 1 };
+    if game.variant != Variant::Crazyhouse || game.promoting <= 63 || to_tile > 63 || game.board[to_tile] != TYPES::NONE || game.pockets[side][pocket_index] == 0 {
+        return false;
+    }
+    if piece_type == TYPES::PAWN && (to_tile <= 7 || to_tile >= 56) {
+        return false;
+    }
+
+    let mut game_clone = game.clone();
+    game_clone.board[to_tile] = piece_type + game.turn;
+    game_clone.promoted[to_tile] = false;
+    game_clone.pockets[side][pocket_index] -= 1;
+    if side_to_move_is_checked(&game_clone) {
+        return false;
+    }
+
+    game_clone.tile_available_to_un_passant = 100;
+    game_clone.halfmove_clock += 1;
+    if game_clone.turn == COLORS::BLACK {
+        game_clone.fullmove_number += 1;
+    }
+    swap_turn(&mut game_clone);
+    game_clone.check = side_to_move_is_checked(&game_clone);
+    variant::after_move(&mut game_clone, game.turn);
+    *game = game_clone;
+    true
+}
+
+fn move_piece_from_tile_to_tile(from_tile: usize, to_tile: usize, game: &mut GAME) -> bool {
     let piece_to_move = game.board[from_tile];
     let mut if_valid_move = false;
 
+    // the king taking its own rook is castling in normal games too
+    let to_tile = match king_takes_own_rook(game, piece_to_move, from_tile, to_tile) {
+        Some(right) if !game.chess960 => chastling_squares(right).0,
+        _ => to_tile,
+    };
+    let is_capture = game.is_capture(&Move::new(from_tile, to_tile, TYPES::NONE));
+    let captured_tile = if game.board[to_tile] == TYPES::NONE && piece_to_move & TYPES::PAWN > 0 {
+        if piece_to_move & COLORS::WHITE > 0 { to_tile + 8 } else { to_tile.wrapping_sub(8) }       // en passant
+    } else {
+        to_tile
+    };
+    let resets_halfmove_clock = piece_to_move & TYPES::PAWN > 0 || is_capture;
+
     if game.promoting == 100 {
         if_valid_move = true;
     }
@@ -142,58 +519,109 @@ pub fn move_piece_from_to(from_tile: &str, to_tile: &str, game: &mut GAME) -> bo
     
     if_valid_move = is_legal_move_for_piece(game, piece_to_move, from_tile, to_tile, if_valid_move);
 
+    if_valid_move = if_valid_move && variant::allows_move(game, from_tile, to_tile);
+
     if if_valid_move {
-        handle_un_passant_logic(game, piece_to_move, from_tile, to_tile);
+        let was_check = game.check;
+        let mut game_clone = game.clone();
 
-        handle_promote_logic(game, piece_to_move, to_tile);
+        handle_un_passant_logic(&mut game_clone, piece_to_move, from_tile, to_tile);
+
+        handle_promote_logic(&mut game_clone, piece_to_move, to_tile);
 
-        let mut game_clone = game.clone();
         move_the_piece(&mut game_clone, piece_to_move, from_tile, to_tile);
-        
-        check_if_allied_king_is_checked(&mut game_clone, game);
+
+        if is_capture {
+            variant::after_capture(&mut game_clone, to_tile, game.board[captured_tile], game.promoted[captured_tile]);
+        }
+
+        if variant::ignores_check(&game_clone) {
+            game.check = variant::royal_king_lost(&game_clone, game.turn);
+        } else {
+            game.check = king_is_attacked(&game_clone, game.turn);
+        }
 
         if game.check {
+            game.check = was_check;
             if_valid_move = false;
         } else {
             if_valid_move = true;
         }
 
         if if_valid_move {
-        
-            move_the_piece(game, piece_to_move, from_tile, to_tile);
+            update_chastling_ability(&mut game_clone, piece_to_move, from_tile, to_tile);
 
-            update_chastling_ability(game, from_tile, to_tile);
+            game_clone.halfmove_clock = if resets_halfmove_clock { 0 } else { game_clone.halfmove_clock + 1 };
+            if game_clone.turn == COLORS::BLACK {
+                game_clone.fullmove_number += 1;
+            }
 
-            check_if_enemy_king_is_checked(game);
+            if variant::ignores_check(&game_clone) {
+                game_clone.check = false;
+            } else {
+                game_clone.check = king_is_attacked(&game_clone, if game_clone.turn == COLORS::WHITE { COLORS::BLACK } else { COLORS::WHITE });
+            }
+            if game_clone.promoting == 100 {
+                variant::after_move(&mut game_clone, game.turn);        // promotions are counted once the piece is chosen
+            }
 
-            swap_turn(game);
-            if_valid_move = true;
+            if variant::allows_position(&game_clone) {
+                swap_turn(&mut game_clone);
+                *game = game_clone;
+            } else {
+                if_valid_move = false;
+            }
         }
     }
     if_valid_move
 }
 
-fn update_chastling_ability(game: &mut GAME, from_tile: usize, to_tile: usize) {
-    if from_tile == 63 || to_tile == 63 {
-        game.chastling_ability[0] = false;
-    } 
-    if from_tile == 56 || to_tile == 56 {
-        game.chastling_ability[1] = false;
-    } 
-    if from_tile == 60 || to_tile == 60 {
-        game.chastling_ability[0] = false;
-        game.chastling_ability[1] = false;
-    } 
-    if from_tile == 7 || to_tile == 7 {
-        game.chastling_ability[2] = false;
+fn update_chastling_ability(game: &mut GAME, piece_to_move: u8, from_tile: usize, to_tile: usize) {
+    if piece_to_move & TYPES::KING > 0 {
+        let first_right = if piece_to_move & COLORS::WHITE > 0 { 0 } else { 2 };
+        game.chastling_ability[first_right] = false;
+        game.chastling_ability[first_right + 1] = false;
     }
-    if from_tile == 0 || to_tile == 0 {
-        game.chastling_ability[3] = false;
+    for right in 0..4 {
+        if from_tile == game.chastling_rooks[right] || to_tile == game.chastling_rooks[right] {
+            game.chastling_ability[right] = false;
+        }
+    }
+}
+
+// Where king and rook end up for each castling right (KQkq), the same in chess960.
+pub(crate) fn chastling_squares(right: usize) -> (usize, usize) {
+    match right {
+        0 => (62, 61),
+        1 => (58, 59),
+        2 => (6, 5),
+        _ => (2, 3),
+    }
+}
+
+fn king_takes_own_rook(game: &GAME, piece_to_move: u8, from_tile: usize, to_tile: usize) -> Option<usize> {
+    if piece_to_move & TYPES::KING == 0 || game.board[to_tile] & TYPES::ROOK == 0 || game.board[to_tile] & piece_to_move & (COLORS::WHITE | COLORS::BLACK) == 0 {
+        return None;
     }
-    if from_tile == 4 || to_tile == 4 {
-        game.chastling_ability[2] = false;
-        game.chastling_ability[3] = false;
+    let first_right = if piece_to_move & COLORS::WHITE > 0 { 0 } else { 2 };
+    (first_right..first_right + 2).find(|right| {
+        game.chastling_ability[*right] && game.chastling_rooks[*right] == to_tile && from_tile / 8 == to_tile / 8
+    })
+}
+
+// The castling right a king move uses. Normal games castle by moving the king two tiles from its
+// starting tile, chess960 games by taking the own rook.
+fn chastling_right_of_move(game: &GAME, piece_to_move: u8, from_tile: usize, to_tile: usize) -> Option<usize> {
+    if game.chess960 {
+        return king_takes_own_rook(game, piece_to_move, from_tile, to_tile);
     }
+    if piece_to_move & TYPES::KING == 0 {
+        return None;
+    }
+    let (first_right, king_tile) = if piece_to_move & COLORS::WHITE > 0 { (0, 60) } else { (2, 4) };
+    (first_right..first_right + 2).find(|right| {
+        game.chastling_ability[*right] && from_tile == king_tile && to_tile == chastling_squares(*right).0
+    })
 }
 
 fn piece_is_correct_color(game: &mut GAME, piece_to_move: u8, mut if_valid_move: bool) -> bool {
@@ -224,22 +652,22 @@ fn handle_un_passant_logic(game: &mut GAME, piece_to_move: u8, from_tile: usize,
     if piece_to_move & TYPES::PAWN > 0 {
         if piece_to_move & COLORS::WHITE > 0 {
             if from_tile >= 24 && from_tile <= 31 {
-                if to_tile == from_tile - 7 || to_tile == from_tile - 9 {
+                if to_tile == game.tile_available_to_un_passant as usize {
                     game.board[(game.tile_available_to_un_passant + 8) as usize] = TYPES::NONE;
                 }
             }
-            if to_tile == (from_tile - 16) as usize {
+            if from_tile == to_tile + 16 && from_tile < 56 {       // no en passant after a horde pawn leaves the first rank
                 game.tile_available_to_un_passant = game.potential_tile_to_un_passant;
             } else {
                 game.tile_available_to_un_passant = 100;
             } 
         } else if piece_to_move & COLORS::BLACK > 0 {
             if from_tile >= 32 && from_tile <= 39 {
-                if to_tile == from_tile + 7 || to_tile == from_tile + 9 {
+                if to_tile == game.tile_available_to_un_passant as usize {
                     game.board[(game.tile_available_to_un_passant - 8) as usize] = TYPES::NONE;
                 }
             }
-            if to_tile == (from_tile + 16) as usize {
+            if to_tile == (from_tile + 16) as usize && from_tile > 7 {
                 game.tile_available_to_un_passant = game.potential_tile_to_un_passant;
             } else {
                 game.tile_available_to_un_passant = 100;
@@ -266,88 +694,27 @@ fn handle_promote_logic(game: &mut GAME, piece_to_move: u8, to_tile: usize) {
     }
 }
 
-fn check_if_allied_king_is_checked(game_clone: &mut GAME, game: &mut GAME) {
-    let board = game_clone.board;
-    let turn = game.turn;
-    let color_is_playing;
-    let color_king_checkable;
-
-    if turn == COLORS::WHITE {
-        color_is_playing = COLORS::BLACK;
-        color_king_checkable = COLORS::WHITE;
-    } else {
-        color_is_playing = COLORS::WHITE;
-        color_king_checkable = COLORS::BLACK;
-    }
-
-    let attacked_tiles = get_all_attacked_squares(color_is_playing, game_clone);
-
-    for (tile, piece) in board.iter().enumerate() {
-        if (piece & color_king_checkable > 0) && (piece & TYPES::KING > 0) {
-            if attacked_tiles[tile] {
-                game.check = true;
-            } else {
-                game.check = false;
-            }
-        }
-    }
-}
-
-fn check_if_enemy_king_is_checked(game: &mut GAME) {
-    let board = game.board;
-    let turn = game.turn;
-    let color_is_playing;
-    let color_king_checkable;
-
-    if turn == COLORS::BLACK {
-        color_is_playing = COLORS::BLACK;
-        color_king_checkable = COLORS::WHITE;
-    } else {
-        color_is_playing = COLORS::WHITE;
-        color_king_checkable = COLORS::BLACK;
-    }
-
-    let attacked_tiles = get_all_attacked_squares(color_is_playing, game);
-
-    for (tile, piece) in board.iter().enumerate() {
-        if (piece & color_king_checkable > 0) && (piece & TYPES::KING > 0) {
-            if attacked_tiles[tile] {
-                game.check = true;
-            } else {
-                game.check = false;
-            }
-        }
-    }
+// Whether the king of color stands on an attacked tile, false for a side without a king like the horde.
+fn king_is_attacked(game: &GAME, color: u8) -> bool {
+    let enemy_color = if color == COLORS::WHITE { COLORS::BLACK } else { COLORS::WHITE };
+    analysis::king_tile(&game.board, color).is_some_and(|king_tile| analysis::is_attacked(&game.board, king_tile, enemy_color))
 }
 
 fn move_the_piece(game: &mut GAME, piece_to_move: u8, from_tile: usize, to_tile: usize) {
-    if game.chastling_ability[0] && (to_tile == 62 && (game.board[from_tile] & TYPES::KING > 0) && (game.board[63] & TYPES::ROOK > 0)) && 
-                    ((game.board[from_tile] & COLORS::WHITE > 0) && (game.board[63] & COLORS::WHITE > 0)) {
-        game.board[60] = TYPES::NONE;
-        game.board[61] = TYPES::ROOK + COLORS::WHITE;
-        game.board[62] = piece_to_move;
-        game.board[63] = TYPES::NONE;
-    } else if game.chastling_ability[1] && (to_tile == 58 && (game.board[from_tile] & TYPES::KING > 0) && (game.board[56] & TYPES::ROOK > 0)) && 
-            ((game.board[from_tile] & COLORS::WHITE > 0) && (game.board[56] & COLORS::WHITE > 0)) {
-        game.board[56] = TYPES::NONE;
-        game.board[59] = TYPES::ROOK + COLORS::WHITE;
-        game.board[58] = piece_to_move;
-        game.board[60] = TYPES::NONE;
-    } else if game.chastling_ability[2] && (to_tile == 6 && (game.board[from_tile] & TYPES::KING > 0)) && (game.board[7] & TYPES::ROOK > 0) && 
-            (game.board[from_tile] & COLORS::BLACK > 0) && (game.board[7] & COLORS::BLACK > 0) {
-        game.board[4] = TYPES::NONE;
-        game.board[5] = TYPES::ROOK + COLORS::BLACK;
-        game.board[6] = piece_to_move;
-        game.board[7] = TYPES::NONE;
-    } else if game.chastling_ability[3] && (to_tile == 2 && (game.board[from_tile] & TYPES::KING > 0) && (game.board[0] & TYPES::ROOK > 0)) && 
-            ((game.board[from_tile] & COLORS::BLACK > 0) && (game.board[0] & COLORS::BLACK > 0)) {
-        game.board[0] = TYPES::NONE;
-        game.board[3] = TYPES::ROOK + COLORS::BLACK;
-        game.board[2] = piece_to_move;
-        game.board[4] = TYPES::NONE;
+    if let Some(right) = chastling_right_of_move(game, piece_to_move, from_tile, to_tile) {
+        let (king_destination, rook_destination) = chastling_squares(right);
+        let rook_tile = game.chastling_rooks[right];
+        let rook = game.board[rook_tile];
+        game.board[from_tile] = TYPES::NONE;
+        game.board[rook_tile] = TYPES::NONE;
+        game.board[rook_destination] = rook;
+        game.board[king_destination] = piece_to_move;
+        game.promoted[rook_destination] = false;
+        game.promoted[king_destination] = false;
     } else {
         game.board[from_tile] = TYPES::NONE;
         game.board[to_tile] = piece_to_move;
+        game.promoted[to_tile] = game.promoted[from_tile];
     }
 }
 
@@ -359,42 +726,42 @@ fn swap_turn(game: &mut GAME) {
     }
 }
 
-pub fn promote_pawn(new_type: &str, game: &mut GAME) -> bool {
-    let mut if_valid_move = false;
-    if game.promoting <= 63 {
-        if_valid_move = true;
-        let promotiong_piece_color: u8;
-        let tile_promoting: usize = game.promoting as usize;
-
-        if (game.turn & COLORS::WHITE) > 0 {
-            promotiong_piece_color = COLORS::BLACK;
-        } else {
-            promotiong_piece_color = COLORS::WHITE;
-        }
-
-        if new_type.to_string() == "q" {
-            game.board[tile_promoting] = TYPES::QUEEN + promotiong_piece_color;
-            game.promoting = 100;
-        } else 
-        if new_type.to_string() == "r" {
-            game.board[tile_promoting] = TYPES::ROOK + promotiong_piece_color;
-            game.promoting = 100;
-        } else 
-        if new_type.to_string() == "b" {
-            game.board[tile_promoting] = TYPES::BISHOP + promotiong_piece_color;
-            game.promoting = 100;
-        } else 
-        if new_type.to_string() == "k" {
-            game.board[tile_promoting] = TYPES::KNIGHT + promotiong_piece_color;
-            game.promoting = 100;
-        } else {
-            if_valid_move = false;
-        } 
+// Only called by make_move on the pawn it just moved, kings are only allowed in antichess, where the king is an ordinary piece.
+fn promote_pawn_to(piece_type: u8, game: &mut GAME) -> bool {
+    let allowed = [TYPES::QUEEN, TYPES::ROOK, TYPES::BISHOP, TYPES::KNIGHT].contains(&piece_type) ||
+        (piece_type == TYPES::KING && game.variant == Variant::Antichess);
+    if game.promoting > 63 || !allowed {
+        return false;
     }
-    if_valid_move
+    let tile_promoting: usize = game.promoting as usize;
+    let pawn_color = game.board[tile_promoting] & (COLORS::WHITE | COLORS::BLACK);     // none when an atomic capture blew up the pawn
+
+    game.board[tile_promoting] = if pawn_color == 0 { TYPES::NONE } else { piece_type + pawn_color };
+    game.promoted[tile_promoting] = true;
+    game.promoting = 100;
+    true
 }
 
 pub fn init_game() -> GAME {
+    init_game_from_fen(STARTINGFEN)
+}
+
+pub fn init_variant_game(variant: Variant) -> GAME {
+    let mut game = init_game_from_fen(variant.get_starting_fen());
+    game.set_variant(variant);
+    game
+}
+
+// One of the 960 chess960 starting positions, 518 is the normal one.
+pub fn init_chess960_game(index: usize) -> Option<GAME> {
+    let mut game = init_game_from_fen(&chess960::start_position_fen(index)?);
+    game.chess960 = true;
+    Some(game)
+}
+
+// For FENs known to be good, like the starting positions. It panics on some broken ones, FENs from
+// files and users go through validation::game_from_fen.
+pub fn init_game_from_fen(fen: &str) -> GAME {
     let mut game = GAME {
         computed_distances: GAME::tiles_to_the_edge(),
         board: GAME::generate_board_array(),
@@ -403,9 +770,16 @@ pub fn init_game() -> GAME {
         tile_available_to_un_passant: 100,
         potential_tile_to_un_passant: 100,
         chastling_ability: [false, false, false, false],                    // KQkq
+        chastling_rooks: [63, 56, 7, 0],
+        chess960: false,
+        variant: Variant::Standard,
+        checks_given: [0, 0],
+        pockets: [[0; 5]; 2],
+        promoted: [false; 64],
+        halfmove_clock: 0,
+        fullmove_number: 1,
+        position_keys: Vec::new(),
         check: false,
-        draw: false,
-        check_mate: false,
         promoting: 100,
     };
     let mut piece_type_from_symbol = HashMap::new();
@@ -417,26 +791,32 @@ pub fn init_game() -> GAME {
     piece_type_from_symbol.insert('r', TYPES::ROOK);
     piece_type_from_symbol.insert('q', TYPES::QUEEN);
 
-    let (loaded_board, un_passant_default) = load_position_from_fen(STARTINGFEN, &mut game, &mut piece_type_from_symbol);
+    let (loaded_board, un_passant_default) = load_position_from_fen(fen, &mut game, &mut piece_type_from_symbol);
     game.board = loaded_board;
     game.tile_available_to_un_passant = un_passant_default;
+    game.check = side_to_move_is_checked(&game);
+    record_position(&mut game);
     game
 }
 
-const STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/6P/6p/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+fn side_to_move_is_checked(game: &GAME) -> bool {
+    !variant::ignores_check(game) && king_is_attacked(game, game.turn)
+}
+
+// const STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/6P/6p/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 // const STARTINGFEN: &str = "rnbqkbnr/8/8/6P/6p/8/8/RNBQKBNR w KQkq - 0 1";
 // const STARTINGFEN: &str = "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1";
 // const STARTINGFEN: &str = "3kq/7p/8/8/8/8/7P/3KQ w ---- - 0 1";
 // const STARTINGFEN: &str = "8/4PP/8/8/8/7P/5pp/8 w KQkq - 0 1";
 // const STARTINGFEN: &str = "4k2r/8/8/8/8/8/8/4K2R w KQkq - 0 1";
-// const STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 // kolla in breakpoints
 
 /// # Testing FEN algorithm
 ///```
 /// use chess_logic::*;
-/// use std::{collections::HashMap, convert::TryInto};
+/// use std::collections::HashMap;
 /// let mut piece_type_from_symbol = HashMap::new();
 /// piece_type_from_symbol.insert('k', 32);
 /// piece_type_from_symbol.insert('p', 1);
@@ -444,13 +824,13 @@ const STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/6P/6p/8/PPPPPPPP/RNBQKBNR w KQkq
 /// piece_type_from_symbol.insert('b', 4);
 /// piece_type_from_symbol.insert('r', 8);
 /// piece_type_from_symbol.insert('q', 16);
-/// let empty_board = [0u8; 64];
+/// let mut game = GAME::empty();
 /// let STARTINGFEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-/// let board = chess_logic::load_position_from_fen(STARTINGFEN, empty_board, &mut piece_type_from_symbol);
+/// let (board, un_passant) = chess_logic::load_position_from_fen(STARTINGFEN, &mut game, &mut piece_type_from_symbol);
 /// let expected_output = [136, 130, 132, 144, 160, 132, 130, 136, 129, 129, 129, 129, 129, 129, 129, 129, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 65, 65, 65, 65, 65, 65, 65, 65, 72, 66, 68, 80, 96, 68, 66, 72];
 /// assert_eq!(board, expected_output);
+/// assert_eq!(un_passant, 100);
 ///```
-
 pub fn load_position_from_fen(fen: &str, game: &mut GAME, piece_type_from_symbol: &mut HashMap<char, u8>) ->  ([u8; 64], u8) {
 
     let mut board = game.board;
@@ -472,6 +852,13 @@ pub fn load_position_from_fen(fen: &str, game: &mut GAME, piece_type_from_symbol
         let part = fen_parts.next();
         if part == None {
             empty = true;
+        } else if parts_index > 1 && part.unwrap().contains('+') {
+            // the checks of three-check, either +N+M after the move counters or N+M before them
+            if let Some(checks_given) = variant::parse_checks_field(part.unwrap()) {
+                game.checks_given = checks_given;
+                game.variant = Variant::ThreeCheck;
+            }
+            continue;
         } else {
             if parts_index == 0 {
                 positions = part.unwrap();
@@ -483,29 +870,20 @@ pub fn load_position_from_fen(fen: &str, game: &mut GAME, piece_type_from_symbol
                     game.turn = COLORS::BLACK;
                 }
             } else if parts_index == 2 {
-                castling_ability = part.unwrap();
-                for char in castling_ability.chars() {
-                    if char == 'K' {
-                        game.chastling_ability[0] = true;
-                    } else if char == 'Q' {
-                        game.chastling_ability[1] = true;
-                    } else if char == 'k' {
-                        game.chastling_ability[2] = true;
-                    } else if char == 'q' {
-                        game.chastling_ability[3] = true;
-                    }
-                }
+                castling_ability = part.unwrap();                   // read once the pieces are placed
             } else if parts_index == 3 {
                 moved_on_to_by_un_passant = part.unwrap();
                 if moved_on_to_by_un_passant.contains('-') {
                     tile_available_to_un_passant = 100;
                 } else {
-                    tile_available_to_un_passant = moved_on_to_by_un_passant.parse::<u8>().unwrap();
+                    tile_available_to_un_passant = algebraic_notation_to_memory_location(moved_on_to_by_un_passant) as u8;
                 }
             } else if parts_index == 4 {
                 halfmove = part.unwrap();
+                game.halfmove_clock = halfmove.parse().unwrap_or(0);
             } else {
                 fullmove = part.unwrap();
+                game.fullmove_number = fullmove.parse().unwrap_or(1);
             }
         }
         parts_index += 1;
@@ -514,8 +892,22 @@ pub fn load_position_from_fen(fen: &str, game: &mut GAME, piece_type_from_symbol
     let mut file = 0;
     let mut rank = 0;
 
+    // crazyhouse pockets follow the pieces in brackets, like [Qn]
+    let (positions, pocket) = match positions.find('[') {
+        Some(index) => (&positions[..index], Some(&positions[index..])),
+        None => (positions, None),
+    };
+    if let Some(pocket) = pocket {
+        game.pockets = variant::parse_pocket_field(pocket);
+        game.variant = Variant::Crazyhouse;
+    }
+
     for character in positions.chars() {
-        if character == '/'{
+        if character == '~' {
+            if file > 0 {
+                game.promoted[(rank * 8 + file - 1) as usize] = true;
+            }
+        } else if character == '/'{
             file = 0;
             rank += 1;
         } else {
@@ -531,9 +923,39 @@ pub fn load_position_from_fen(fen: &str, game: &mut GAME, piece_type_from_symbol
             }
         }
     }
+    load_chastling_from_fen(castling_ability, &board, game);
     (board, tile_available_to_un_passant)
 }
 
+// KQkq stand for the outermost rook on that side of the king (X-FEN), file letters like HAha
+// name the rook (Shredder-FEN). Kings or rooks away from their usual tiles make it a chess960 game.
+fn load_chastling_from_fen(castling_ability: &str, board: &[u8; 64], game: &mut GAME) {
+    for character in castling_ability.chars() {
+        let color = if character.is_ascii_uppercase() { COLORS::WHITE } else { COLORS::BLACK };
+        let back_rank = if color == COLORS::WHITE { 56 } else { 0 };
+        let king_tile = match (back_rank..back_rank + 8).find(|tile| board[*tile] == TYPES::KING + color) {
+            Some(king_tile) => king_tile,
+            None => continue,
+        };
+        let mut rook_tiles = (back_rank..back_rank + 8).filter(|tile| board[*tile] == TYPES::ROOK + color);
+
+        let rook_tile = match character.to_ascii_lowercase() {
+            'k' => rook_tiles.rev().find(|tile| *tile > king_tile),
+            'q' => rook_tiles.find(|tile| *tile < king_tile),
+            file @ 'a'..='h' => rook_tiles.find(|tile| *tile == back_rank + (file as usize - 'a' as usize)),
+            _ => None,
+        };
+        if let Some(rook_tile) = rook_tile {
+            let right = if color == COLORS::WHITE { 0 } else { 2 } + if rook_tile > king_tile { 0 } else { 1 };
+            game.chastling_ability[right] = true;
+            game.chastling_rooks[right] = rook_tile;
+            if king_tile != back_rank + 4 || (rook_tile != back_rank && rook_tile != back_rank + 7) {
+                game.chess960 = true;
+            }
+        }
+    }
+}
+
 pub fn algebraic_notation_to_memory_location(algebraic_notation: &str) -> usize {
     let alphabet_to_index = vec!['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
     let mut rank: usize = 0;
@@ -551,6 +973,13 @@ pub fn algebraic_notation_to_memory_location(algebraic_notation: &str) -> usize
     (rank * 8 + file) as usize
 }
 
+pub fn memory_location_to_algebraic_notation(tile: usize) -> String {
+    let alphabet = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h'];
+    let file = alphabet[tile % 8];
+    let rank = 8 - tile / 8;
+    format!("{}{}", file, rank)
+}
+
 
 pub fn is_black_king(piece: u8) -> bool {
     if (piece & TYPES::KING > 0) & (piece & COLORS::BLACK > 0) {
@@ -635,4 +1064,59 @@ pub fn is_white_pawn(piece: u8) -> bool {
     } else {
         false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    const PROMOTING: &str = "1r6/P6k/8/8/8/8/7p/K7 w - - 0 1";
+
+    #[test]
+    fn promotion_needs_a_piece() {
+        let mut game = init_game_from_fen(PROMOTING);
+        assert!(!make_move(&Move::new(tile("a7"), tile("a8"), TYPES::NONE), &mut game));
+        assert!(!make_move(&Move::new(tile("a7"), tile("b8"), TYPES::NONE), &mut game));
+        assert_eq!(game.get_fen(), PROMOTING);
+        // and only a promotion takes one
+        assert!(!make_move(&Move::new(tile("a1"), tile("b1"), TYPES::QUEEN), &mut game));
+        assert!(!make_move(&Move::new(tile("a7"), tile("a8"), TYPES::PAWN), &mut game));
+        assert!(!make_move(&Move::new(tile("a7"), tile("a8"), TYPES::KING), &mut game));
+        assert_eq!(game.get_fen(), PROMOTING);
+    }
+
+    #[test]
+    fn pending_promotion_lists_the_pieces() {
+        let game = init_game_from_fen(PROMOTING);
+        let mut pieces = game.pending_promotion(tile("a7"), tile("b8"));
+        pieces.sort_unstable();
+        assert_eq!(pieces, [TYPES::KNIGHT, TYPES::BISHOP, TYPES::ROOK, TYPES::QUEEN]);
+        assert_eq!(game.pending_promotion(tile("a7"), tile("a8")).len(), 4);
+        assert!(game.pending_promotion(tile("a1"), tile("b1")).is_empty());
+        assert!(game.pending_promotion(tile("a7"), tile("a6")).is_empty());
+
+        let mut antichess = init_game_from_fen("8/P7/8/8/8/8/8/7k w - - 0 1");
+        antichess.set_variant(Variant::Antichess);
+        let mut pieces = antichess.pending_promotion(tile("a7"), tile("a8"));
+        pieces.sort_unstable();
+        assert_eq!(pieces, [TYPES::KNIGHT, TYPES::BISHOP, TYPES::ROOK, TYPES::QUEEN, TYPES::KING]);
+    }
+
+    #[test]
+    fn underpromotion_lands_the_chosen_piece() {
+        for (piece, symbol) in [(TYPES::KNIGHT, 'N'), (TYPES::BISHOP, 'B'), (TYPES::ROOK, 'R'), (TYPES::QUEEN, 'Q')] {
+            let mut game = init_game_from_fen(PROMOTING);
+            assert!(make_move(&Move::new(tile("a7"), tile("b8"), piece), &mut game));
+            assert_eq!(game.get_board()[tile("b8")], piece + COLORS::WHITE);
+            assert_eq!(game.get_fen(), format!("1{}6/7k/8/8/8/8/7p/K7 b - - 0 1", symbol));
+        }
+        let mut game = init_game_from_fen(PROMOTING);
+        assert!(make_move(&Move::new(tile("a7"), tile("a8"), TYPES::ROOK), &mut game));
+        assert!(make_move(&Move::new(tile("h2"), tile("h1"), TYPES::KNIGHT), &mut game));
+        assert_eq!(game.get_fen(), "Rr6/7k/8/8/8/8/8/K6n w - - 0 2");
+    }
+}
@@ -1,8 +1,11 @@
+use crate::analysis::is_attacked;
+use crate::chastling_squares;
+use crate::variant::Variant;
 use crate::COLORS;
 use crate::TYPES;
 use crate::GAME;
 
-pub fn king_attacks_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64] {
+pub fn king_movement_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64] {
     let precomputed_distances = game.computed_distances;
     let board = game.board;
     let mut available_moves_board = [false; 64];
@@ -15,6 +18,7 @@ pub fn king_attacks_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool;
         piece_color = COLORS::BLACK;
         enemy_color = COLORS::WHITE;
     }
+    let checked_squares = get_all_attacked_squares(enemy_color, game);
     let offsets: [i8; 8] = [-8, 8, -1, 1, -9, 9, -7, 7];
     for (index, offset) in offsets.iter().enumerate() {
         let target_tile = tile as i8 + offset;
@@ -27,72 +31,43 @@ pub fn king_attacks_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool;
             }
         }
     }
+
+    let first_right = if piece_color == COLORS::WHITE { 0 } else { 2 };
+    for right in first_right..first_right + 2 {
+        if let Some(destination) = chastling_destination(game, right, tile, &checked_squares) {
+            available_moves_board[destination] = true;
+        }
+    }
+
     // draw_movement_board(available_moves_board);
     return available_moves_board
 }
 
-pub fn king_movement_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64] {
-    let precomputed_distances = game.computed_distances;
-    let board = game.board;
-    let mut available_moves_board = [false; 64];
-    let piece_color: u8;
-    let enemy_color: u8;
-    if piece & COLORS::WHITE > 0 {
-        piece_color = COLORS::WHITE;
-        enemy_color = COLORS::BLACK;
-    } else {
-        piece_color = COLORS::BLACK;
-        enemy_color = COLORS::WHITE;
+// The tiles between the king and rook and their castling tiles have to be empty, and the king
+// can't pass an attacked tile. Chess960 castling is played as the king taking its own rook.
+fn chastling_destination(game: &GAME, right: usize, king_tile: usize, attacked_tiles: &[bool; 64]) -> Option<usize> {
+    let rook_tile = game.chastling_rooks[right];
+    let king = game.board[king_tile];
+    let rook = game.board[rook_tile];
+    if !game.chastling_ability[right] || rook & TYPES::ROOK == 0 || rook & king & (COLORS::WHITE | COLORS::BLACK) == 0 || rook_tile / 8 != king_tile / 8 {
+        return None;
     }
-    let checked_squares = get_all_attacked_squares(enemy_color, game);
-    let offsets: [i8; 8] = [-8, 8, -1, 1, -9, 9, -7, 7];
-    for (index, offset) in offsets.iter().enumerate() {
-        let target_tile = tile as i8 + offset;
-        let distances_to_edge = precomputed_distances[tile];
-        if distances_to_edge[index] > 0 {
-            if (board[target_tile as usize] & piece_color > 0) && !checked_squares[target_tile as usize] {
-                continue;
-            } else {
-                available_moves_board[target_tile as usize] = true;
-            }
-        }
+    if !game.chess960 && king_tile % 8 != 4 {
+        return None;
     }
 
-    if game.chastling_ability[0] {              // King-side white chastling
-        let attacked_tiles = get_all_attacked_squares(enemy_color, game);
-        if !attacked_tiles[60] && !attacked_tiles[61] && !attacked_tiles[62] {
-            if board[61] == 0 && board[62] == 0 {
-                available_moves_board[62] = true;
-            }
+    let (king_destination, rook_destination) = chastling_squares(right);
+    let king_path = king_tile.min(king_destination)..=king_tile.max(king_destination);
+    let rook_path = rook_tile.min(rook_destination)..=rook_tile.max(rook_destination);
+    for tile in king_path.clone().chain(rook_path) {
+        if tile != king_tile && tile != rook_tile && game.board[tile] != TYPES::NONE {
+            return None;
         }
     }
-    if game.chastling_ability[1] {              // Queen-side white chastling
-        let attacked_tiles = get_all_attacked_squares(enemy_color, game);
-        if !attacked_tiles[60] && !attacked_tiles[59] && !attacked_tiles[58] {
-            if board[59] == 0 && board[58] == 0 && board[57] == 0 {
-                available_moves_board[58] = true;
-            }
-        }
+    if king_path.into_iter().any(|tile| attacked_tiles[tile]) {
+        return None;
     }
-    if game.chastling_ability[2] {              // King-side black chastling
-        let attacked_tiles = get_all_attacked_squares(enemy_color, game);
-        if !attacked_tiles[4] && !attacked_tiles[5] && !attacked_tiles[6] {
-            if board[5] == 0 && board[6] == 0 {
-                available_moves_board[6] = true;
-            }
-        }
-    }
-    if game.chastling_ability[3] {              // Queen-side white chastling
-        let attacked_tiles = get_all_attacked_squares(enemy_color, game);
-        if !attacked_tiles[2] && !attacked_tiles[3] && !attacked_tiles[4] {
-            if board[1] == 0 && board[2] == 0 && board[3] == 0 {
-                available_moves_board[2] = true;
-            }
-        }
-    }
-
-    // draw_movement_board(available_moves_board);
-    return available_moves_board
+    Some(if game.chess960 { rook_tile } else { king_destination })
 }
 
 fn queen_movement_from_tile(board: [u8; 64], piece: u8, tile: usize, precomputed_distances: [[u8; 8]; 64]) -> [bool; 64] {
@@ -300,7 +275,7 @@ fn pawn_movement_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64
                 }
             } else if index == 1 {
                 if precomputed_distances_to_edge[index] > 0 {       // tiles in front of white pawn
-                    if tile > 47 && tile < 56 {
+                    if (tile > 47 && tile < 56) || (tile > 55 && game.variant == Variant::Horde) {     // horde pawns on the first rank too
                         if board[(target_tile - 8) as usize] == 0 && board[target_tile as usize] == 0 {
                             available_moves_board[(target_tile) as usize] = true;
                             available_moves_board[(target_tile - 8) as usize] = true;
@@ -342,7 +317,7 @@ fn pawn_movement_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64
                 }
             } else if index == 1 {
                 if precomputed_distances_to_edge[index + 3] > 0 {       // tiles in front of black pawn
-                    if tile > 7 && tile < 16 {
+                    if (tile > 7 && tile < 16) || (tile < 8 && game.variant == Variant::Horde) {
                         if board[(target_tile + 8) as usize] == 0 && board[target_tile as usize] == 0 {
                             available_moves_board[(target_tile) as usize] = true;
                             available_moves_board[(target_tile + 8) as usize] = true;
@@ -396,102 +371,12 @@ pub fn available_moves_for_piece(piece_to_move: u8, from_tile: usize, game: &mut
 }
 
 
-fn pawn_attack_from_tile(game: &mut GAME, piece: u8, tile: usize) -> [bool; 64] {
-    let mut available_moves_board = [false; 64];
-    let precomputed_distances = game.computed_distances;
-    let un_passant_tile = game.tile_available_to_un_passant;
-    let board = game.board;
-    let enemy_piece_color: u8;
-    let offsets: [i8; 4] = [-9, -7, 9, 7];
-    let precomputed_distances_to_edge = [precomputed_distances[tile][4], 
-                                                precomputed_distances[tile][6],
-                                                precomputed_distances[tile][5],
-                                                precomputed_distances[tile][7]];
-    if piece & COLORS::WHITE > 0 {
-        enemy_piece_color = COLORS::BLACK;
-        for (index, offset) in offsets[0..2].iter().enumerate() {
-            let target_tile: i8 = tile as i8 + offset;
-            if index == 0 {
-                if precomputed_distances_to_edge[index] > 0 {       // tile diagonaly left from white pawn
-                    if (board[target_tile as usize] & enemy_piece_color > 0) || target_tile as u8 == un_passant_tile || board[target_tile as usize] == 0 {
-                        available_moves_board[target_tile as usize] = true;
-                    } else {
-                        continue;
-                    }
-                }
-            } else if index == 1 {
-                if precomputed_distances_to_edge[index] > 0 {       // tile diagonaly right from white pawn
-                    if (board[target_tile as usize] & enemy_piece_color > 0) || target_tile as u8 == un_passant_tile || board[target_tile as usize] == 0 {
-                        available_moves_board[target_tile as usize] = true;
-                    } else {
-                        continue;
-                    }
-                }
-            }
-        }
-    } else {
-        enemy_piece_color = COLORS::WHITE;
-        for (index, offset) in offsets[2..4].iter().enumerate() {
-            let target_tile: i8 = tile as i8 + offset;
-            if index == 0 {
-                if precomputed_distances_to_edge[index + 2] > 0 {       // tile diagonaly left from black pawn
-                    if (board[target_tile as usize] & enemy_piece_color > 0) || target_tile as u8 == un_passant_tile || board[target_tile as usize] == 0 {
-                        available_moves_board[target_tile as usize] = true;
-                    } else {
-                        continue;
-                    }
-                }
-            } else if index == 1 {
-                if precomputed_distances_to_edge[index + 2] > 0 {       // tile diagonaly right from black pawn
-                    if (board[target_tile as usize] & enemy_piece_color > 0) || target_tile as u8 == un_passant_tile || board[target_tile as usize] == 0 {
-                        available_moves_board[target_tile as usize] = true;
-                    } else {
-                        continue;
-                    }
-                }
-            }
-        }
-    }
-    // draw_movement_board(available_moves_board);
-    return available_moves_board
-}
-
-pub fn available_attacks_for_piece(piece_to_move: u8, from_tile: usize, game: &mut GAME) -> [bool; 64] {
-    let mut moves = [false; 64];
-    if (piece_to_move & TYPES::KING) > 0 {
-        moves = king_attacks_from_tile(game, piece_to_move, from_tile);
-    } else if (piece_to_move & TYPES::QUEEN) > 0 {
-        moves = queen_movement_from_tile(game.board, piece_to_move, from_tile, game.computed_distances);
-    } else if (piece_to_move & TYPES::ROOK) > 0 {
-        moves = rook_movement_from_tile(game.board, piece_to_move, from_tile, game.computed_distances);
-    } else if (piece_to_move & TYPES::BISHOP) > 0 {
-        moves = bishop_movement_from_tile(game.board, piece_to_move, from_tile, game.computed_distances);
-    } else if (piece_to_move & TYPES::KNIGHT) > 0 {
-        moves = knight_movement_from_tile(game.board, piece_to_move, from_tile, game.computed_distances);
-    } else if (piece_to_move & TYPES::PAWN) > 0 {
-        moves = pawn_attack_from_tile(game, piece_to_move, from_tile);
-    }
-    moves
-}
-
 pub fn get_all_attacked_squares(enemy_color: u8, game:&mut GAME) -> [bool; 64] {
     let mut attacked_tiles = [false; 64];
-    let board: [u8; 64] = game.board.clone();
-    for (tile, piece) in board.iter().enumerate() {
-        if enemy_color & piece > 0 {
-            let piece_attacks_these_tiles = available_attacks_for_piece(*piece, tile, game);
-
-            for tile in 0..board.len() {
-                if attacked_tiles[tile as usize] || piece_attacks_these_tiles[tile as usize] {
-                    attacked_tiles[tile as usize] = true;
-                } else {
-                    attacked_tiles[tile as usize] = false;
-                }
-            }
-        }
+    for (tile, attacked) in attacked_tiles.iter_mut().enumerate() {
+        *attacked = is_attacked(&game.board, tile, enemy_color);
     }
-    // draw_movement_board(attacked_tiles);
-    return attacked_tiles
+    attacked_tiles
 }
 
 
@@ -509,3 +394,63 @@ fn draw_movement_board(board: [bool; 64]) {
         rank += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, init_game_from_fen, make_move, Move};
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    fn castling_destination(fen: &str, right: usize) -> Option<usize> {
+        let mut game = init_game_from_fen(fen);
+        let king_tile = game.board.iter().position(|piece| *piece == TYPES::KING + game.turn).unwrap();
+        let enemy_color = if game.turn == COLORS::WHITE { COLORS::BLACK } else { COLORS::WHITE };
+        let attacked_tiles = get_all_attacked_squares(enemy_color, &mut game);
+        chastling_destination(&game, right, king_tile, &attacked_tiles)
+    }
+
+    fn castle(fen: &str, from: &str, to: &str) -> String {
+        let mut game = init_game_from_fen(fen);
+        assert!(game.is_chess960());
+        let chess_move = Move::new(tile(from), tile(to), TYPES::NONE);
+        assert!(game.generate_legal_moves().contains(&chess_move), "{}{} in {}", from, to, fen);
+        assert!(game.is_castling(&chess_move));
+        assert!(make_move(&chess_move, &mut game));
+        game.get_fen()
+    }
+
+    #[test]
+    fn castling_in_standard_chess_moves_the_king_two_tiles() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(castling_destination(fen, 0), Some(tile("g1")));
+        assert_eq!(castling_destination(fen, 1), Some(tile("c1")));
+        assert_eq!(castling_destination("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", 3), Some(tile("c8")));
+        assert_eq!(castling_destination("r3k2r/8/8/8/8/8/8/RN2K2R w KQkq - 0 1", 1), None);
+    }
+
+    #[test]
+    fn the_king_takes_its_own_rook() {
+        assert_eq!(castling_destination("4k3/8/8/8/8/8/8/5KR1 w G - 0 1", 0), Some(tile("g1")));
+        assert_eq!(castle("4k3/8/8/8/8/8/8/5KR1 w G - 0 1", "f1", "g1"), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        assert_eq!(castle("4k3/8/8/8/8/8/8/RK6 w A - 0 1", "b1", "a1"), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        // castling long from g1 ends on c1 and d1 as well
+        assert_eq!(castle("4k3/8/8/8/8/8/8/4R1K1 w E - 0 1", "g1", "e1"), "4k3/8/8/8/8/8/8/2KR4 b - - 1 1");
+        assert_eq!(castle("rk6/8/8/8/8/8/8/4K3 b a - 0 1", "b8", "a8"), "2kr4/8/8/8/8/8/8/4K3 w - - 1 2");
+    }
+
+    #[test]
+    fn castling_through_attacked_tiles() {
+        // only the tiles the king crosses count, the rook may pass b1
+        assert_eq!(castling_destination("1r2k3/8/8/8/8/8/8/R4K2 w A - 0 1", 1), Some(tile("a1")));
+        assert_eq!(castling_destination("3rk3/8/8/8/8/8/8/R4K2 w A - 0 1", 1), None);
+        assert_eq!(castling_destination("2r1k3/8/8/8/8/8/8/R4K2 w A - 0 1", 1), None);     // the king's tile c1
+        assert_eq!(castling_destination("5rk1/8/8/8/8/8/8/R4K2 w A - 0 1", 1), None);      // out of check
+        assert_eq!(castling_destination("4r1k1/8/8/8/8/8/8/1K4R1 w G - 0 1", 0), None);
+        // pieces between king and rook or on their tiles block it
+        assert_eq!(castling_destination("4k3/8/8/8/8/8/8/R1N2K2 w A - 0 1", 1), None);
+        assert_eq!(castling_destination("4k3/8/8/8/8/8/8/R2N1K2 w A - 0 1", 1), None);
+    }
+}
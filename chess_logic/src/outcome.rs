@@ -0,0 +1,562 @@
+use crate::analysis::opposite;
+use crate::make_move;
+use crate::pgn::{moves_to_pgn, PgnGame};
+use crate::variant::Variant;
+use crate::Move;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(color: u8) -> GameResult {
+        if color == COLORS::WHITE { GameResult::WhiteWins } else { GameResult::BlackWins }
+    }
+
+    pub fn get_winner(&self) -> Option<u8> {
+        match self {
+            GameResult::WhiteWins => Some(COLORS::WHITE),
+            GameResult::BlackWins => Some(COLORS::BLACK),
+            GameResult::Draw => None,
+        }
+    }
+
+    // "1-0", "0-1" or "1/2-1/2" like the PGN Result tag.
+    pub fn to_pgn(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Resignation,
+    Timeout,
+    Stalemate,
+    Repetition,
+    FiftyMove,
+    Insufficient,
+    Agreement,
+    Abandoned,
+    TimeoutVsInsufficient,                                      // the flag fell but the other side had no way to mate
+    Variant,                                                    // a rule of the variant, like reaching the hill
+}
+
+impl Termination {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Termination::Checkmate => "checkmate",
+            Termination::Resignation => "resignation",
+            Termination::Timeout => "timeout",
+            Termination::Stalemate => "stalemate",
+            Termination::Repetition => "repetition",
+            Termination::FiftyMove => "fifty-move rule",
+            Termination::Insufficient => "insufficient material",
+            Termination::Agreement => "agreement",
+            Termination::Abandoned => "abandonment",
+            Termination::TimeoutVsInsufficient => "timeout vs insufficient material",
+            Termination::Variant => "variant rules",
+        }
+    }
+
+    // The PGN Termination tag, "normal" for everything decided over the board.
+    pub fn to_pgn(&self) -> &'static str {
+        match self {
+            Termination::Timeout | Termination::TimeoutVsInsufficient => "time forfeit",
+            Termination::Abandoned => "abandoned",
+            _ => "normal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameOutcome {
+    pub result: GameResult,
+    pub reason: Termination,
+}
+
+impl GameOutcome {
+    pub fn win(winner: u8, reason: Termination) -> GameOutcome {
+        GameOutcome { result: GameResult::win_for(winner), reason }
+    }
+
+    pub fn draw(reason: Termination) -> GameOutcome {
+        GameOutcome { result: GameResult::Draw, reason }
+    }
+
+    // The flag of flagged fell, it loses unless the other side has nothing left to mate with.
+    pub fn timeout(game: &GAME, flagged: u8) -> GameOutcome {
+        if game.can_checkmate(opposite(flagged)) {
+            GameOutcome::win(opposite(flagged), Termination::Timeout)
+        } else {
+            GameOutcome::draw(Termination::TimeoutVsInsufficient)
+        }
+    }
+
+    // The outcome of a finished PGN game. Results are kept without the reason, so anything that doesn't say
+    // time forfeit or abandoned is taken as a resignation or a draw agreement.
+    pub fn from_pgn(result: &str, termination: Option<&str>) -> Option<GameOutcome> {
+        let result = match result {
+            "1-0" => GameResult::WhiteWins,
+            "0-1" => GameResult::BlackWins,
+            "1/2-1/2" => GameResult::Draw,
+            _ => return None,
+        };
+        let reason = match (termination.map(|termination| termination.to_ascii_lowercase()).as_deref(), result) {
+            (Some("time forfeit"), GameResult::Draw) => Termination::TimeoutVsInsufficient,
+            (Some("time forfeit"), _) => Termination::Timeout,
+            (Some("abandoned"), _) => Termination::Abandoned,
+            (_, GameResult::Draw) => Termination::Agreement,
+            _ => Termination::Resignation,
+        };
+        Some(GameOutcome { result, reason })
+    }
+
+    // Like "white wins by checkmate" or "draw by repetition".
+    pub fn describe(&self) -> String {
+        match self.result {
+            GameResult::WhiteWins => format!("white wins by {}", self.reason.get_name()),
+            GameResult::BlackWins => format!("black wins by {}", self.reason.get_name()),
+            GameResult::Draw => format!("draw by {}", self.reason.get_name()),
+        }
+    }
+}
+
+// The ways a game ends by the rules. Repetitions and the fifty-move rule end it right away, nobody has to claim them.
+impl GAME {
+    pub fn get_outcome(&self) -> Option<GameOutcome> {
+        if let Some(winner) = self.get_variant_winner() {
+            return Some(GameOutcome::win(winner, Termination::Variant));
+        }
+        if self.is_variant_draw() {
+            return Some(GameOutcome::draw(Termination::Variant));
+        }
+        if self.generate_legal_moves().is_empty() {
+            if self.is_check() {
+                return Some(GameOutcome::win(opposite(self.turn), Termination::Checkmate));
+            }
+            return Some(GameOutcome::draw(Termination::Stalemate));
+        }
+        if self.variant == Variant::Standard && self.has_insufficient_material() {
+            return Some(GameOutcome::draw(Termination::Insufficient));
+        }
+        if self.halfmove_clock >= 100 {
+            return Some(GameOutcome::draw(Termination::FiftyMove));
+        }
+        if self.is_repetition() {
+            return Some(GameOutcome::draw(Termination::Repetition));
+        }
+        None
+    }
+
+    // The position came up for the third time, with the same crazyhouse pockets and three-check counts.
+    pub fn is_repetition(&self) -> bool {
+        match self.position_keys.last() {
+            Some(key) => self.position_keys.iter().filter(|earlier| *earlier == key).count() >= 3,
+            None => false,
+        }
+    }
+
+    // Neither side could ever give mate.
+    pub fn has_insufficient_material(&self) -> bool {
+        !self.can_checkmate(COLORS::WHITE) && !self.can_checkmate(COLORS::BLACK)
+    }
+
+    // Whether color could give mate by some series of legal moves, the other side helping. A lone king can't,
+    // a knight or bishops of one colour can only when the enemy has pieces of its own to block its king in.
+    pub fn can_checkmate(&self, color: u8) -> bool {
+        let pieces = |side: u8| -> Vec<(usize, u8)> {
+            self.board.iter().enumerate()
+                .filter(|(_, piece)| **piece & side > 0 && **piece & TYPES::KING == 0)
+                .map(|(tile, piece)| (tile, *piece))
+                .collect()
+        };
+        let own = pieces(color);
+        let enemy = pieces(opposite(color));
+        if own.iter().any(|(_, piece)| piece & (TYPES::PAWN | TYPES::ROOK | TYPES::QUEEN) > 0) {
+            return true;
+        }
+        let knights = own.iter().filter(|(_, piece)| piece & TYPES::KNIGHT > 0).count();
+        let bishop_colors: Vec<usize> = own.iter().filter(|(_, piece)| piece & TYPES::BISHOP > 0).map(|(tile, _)| (tile / 8 + tile % 8) % 2).collect();
+        match (knights, bishop_colors.first()) {
+            (0, None) => false,
+            (1, None) => !enemy.is_empty(),
+            (0, Some(bishop_color)) if bishop_colors.iter().all(|other| other == bishop_color) => {
+                // any enemy piece but a bishop on the same colour can block the king in
+                enemy.iter().any(|(tile, piece)| piece & TYPES::BISHOP == 0 || (tile / 8 + tile % 8) % 2 != *bishop_color)
+            }
+            _ => true,
+        }
+    }
+}
+
+// Everything that happened in a game, in order. Colours are the side doing it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    Move(Move),
+    DrawOffer(u8),
+    DrawDeclined(u8),                                           // also when the offer is answered with a move
+    DrawAgreed(u8),                                             // the offer was accepted
+    Resignation(u8),
+    Timeout(u8),                                                // the flag of color fell
+    Abandoned(u8),
+}
+
+// A game with its moves, draw offers and how it ended, for frontends that play whole games.
+#[derive(Clone)]
+pub struct GameRecord {
+    start: GAME,
+    game: GAME,
+    history: Vec<(GAME, Move)>,                                 // the position before every move played
+    events: Vec<GameEvent>,
+}
+
+impl GameRecord {
+    pub fn new(start: GAME) -> GameRecord {
+        GameRecord { game: start.clone(), start, history: Vec::new(), events: Vec::new() }
+    }
+
+    pub fn get_game(&self) -> &GAME {
+        &self.game
+    }
+
+    pub fn get_start(&self) -> &GAME {
+        &self.start
+    }
+
+    pub fn get_events(&self) -> &[GameEvent] {
+        &self.events
+    }
+
+    pub fn get_history(&self) -> &[(GAME, Move)] {
+        &self.history
+    }
+
+    pub fn get_moves(&self) -> Vec<Move> {
+        self.history.iter().map(|(_, chess_move)| *chess_move).collect()
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.history.last().map(|(_, chess_move)| *chess_move)
+    }
+
+    // A resignation, agreement, flag or abandonment ends the game, otherwise the rules decide.
+    pub fn get_outcome(&self) -> Option<GameOutcome> {
+        match self.events.last() {
+            Some(GameEvent::Resignation(color)) => Some(GameOutcome::win(opposite(*color), Termination::Resignation)),
+            Some(GameEvent::Abandoned(color)) => Some(GameOutcome::win(opposite(*color), Termination::Abandoned)),
+            Some(GameEvent::DrawAgreed(_)) => Some(GameOutcome::draw(Termination::Agreement)),
+            Some(GameEvent::Timeout(color)) => Some(GameOutcome::timeout(&self.game, *color)),
+            _ => self.game.get_outcome(),
+        }
+    }
+
+    // The colour whose draw offer waits for an answer.
+    pub fn get_draw_offer(&self) -> Option<u8> {
+        let mut offer = None;
+        for event in &self.events {
+            match event {
+                GameEvent::DrawOffer(color) => offer = Some(*color),
+                GameEvent::DrawDeclined(_) | GameEvent::DrawAgreed(_) => offer = None,
+                _ => {}
+            }
+        }
+        offer
+    }
+
+    // Moving while the other side offers a draw declines it.
+    pub fn play(&mut self, chess_move: Move) -> Result<(), String> {
+        self.check_not_over()?;
+        let mover = self.side_to_move();
+        let before = self.game.clone();
+        if !make_move(&chess_move, &mut self.game) {
+            return Err(format!("{} is not a legal move", chess_move.to_uci()));
+        }
+        if self.get_draw_offer() == Some(opposite(mover)) {
+            self.events.push(GameEvent::DrawDeclined(mover));
+        }
+        self.history.push((before, chess_move));
+        self.events.push(GameEvent::Move(chess_move));
+        Ok(())
+    }
+
+    // Offering while the other side's offer stands accepts it.
+    pub fn offer_draw(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        match self.get_draw_offer() {
+            Some(offering) if offering == color => Err("the draw offer already stands".to_string()),
+            Some(_) => self.accept_draw(color),
+            None => {
+                self.events.push(GameEvent::DrawOffer(color));
+                Ok(())
+            }
+        }
+    }
+
+    pub fn accept_draw(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        if self.get_draw_offer() != Some(opposite(color)) {
+            return Err("there is no draw offer to accept".to_string());
+        }
+        self.events.push(GameEvent::DrawAgreed(color));
+        Ok(())
+    }
+
+    pub fn decline_draw(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        if self.get_draw_offer() != Some(opposite(color)) {
+            return Err("there is no draw offer to decline".to_string());
+        }
+        self.events.push(GameEvent::DrawDeclined(color));
+        Ok(())
+    }
+
+    pub fn resign(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        self.events.push(GameEvent::Resignation(color));
+        Ok(())
+    }
+
+    pub fn lose_on_time(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        self.events.push(GameEvent::Timeout(color));
+        Ok(())
+    }
+
+    pub fn abandon(&mut self, color: u8) -> Result<(), String> {
+        self.check_not_over()?;
+        self.events.push(GameEvent::Abandoned(color));
+        Ok(())
+    }
+
+    // Takes back the last move, or the end of the game by resignation, agreement, flag or abandonment,
+    // with the draw offers and declines made after it.
+    pub fn undo(&mut self) -> Option<GameEvent> {
+        while let Some(event) = self.events.pop() {
+            match event {
+                GameEvent::DrawOffer(_) | GameEvent::DrawDeclined(_) => continue,
+                GameEvent::Move(_) => {
+                    if let Some((before, _)) = self.history.pop() {
+                        self.game = before;
+                    }
+                }
+                _ => {}
+            }
+            return Some(event);
+        }
+        None
+    }
+
+    // Ends the game the way a finished PGN game says it ended, when the rules didn't end it already.
+    // Nothing changes when the position gives another outcome, like a loss on time against a lone king.
+    pub fn finish(&mut self, outcome: GameOutcome) -> Result<(), String> {
+        self.check_not_over()?;
+        let loser = match outcome.result.get_winner() {
+            Some(winner) => opposite(winner),
+            None => self.side_to_move(),
+        };
+        let mut finished = self.clone();
+        match outcome.reason {
+            Termination::Resignation => finished.resign(loser)?,
+            Termination::Timeout | Termination::TimeoutVsInsufficient => finished.lose_on_time(loser)?,
+            Termination::Abandoned => finished.abandon(loser)?,
+            Termination::Agreement => {
+                finished.offer_draw(loser)?;
+                finished.accept_draw(opposite(loser))?;
+            }
+            _ => return Err(format!("{} ends a game by the rules only", outcome.reason.get_name())),
+        }
+        match finished.get_outcome() {
+            Some(recorded) if recorded == outcome => {
+                *self = finished;
+                Ok(())
+            }
+            Some(recorded) => Err(format!("{} doesn't fit the position, it would be {}", outcome.describe(), recorded.describe())),
+            None => Err(format!("{} doesn't fit the position", outcome.describe())),
+        }
+    }
+
+    // The moves with the Result and Termination tags once the game is over.
+    pub fn to_pgn(&self) -> PgnGame {
+        let outcome = self.get_outcome();
+        let result = outcome.map_or("*", |outcome| outcome.result.to_pgn());
+        let mut pgn_game = moves_to_pgn(&self.start, &self.get_moves(), result);
+        if let Some(outcome) = outcome {
+            pgn_game.tags.push(("Termination".to_string(), outcome.reason.to_pgn().to_string()));
+        }
+        pgn_game
+    }
+
+    fn side_to_move(&self) -> u8 {
+        if self.game.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK }
+    }
+
+    fn check_not_over(&self) -> Result<(), String> {
+        match self.get_outcome() {
+            Some(outcome) => Err(format!("the game is over, {}", outcome.describe())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, init_game, init_game_from_fen, make_move, zobrist};
+
+    fn play(record: &mut GameRecord, from: &str, to: &str) {
+        let chess_move = Move::new(algebraic_notation_to_memory_location(from), algebraic_notation_to_memory_location(to), TYPES::NONE);
+        record.play(chess_move).expect("a legal move");
+    }
+
+    #[test]
+    fn draw_offer_accepted() {
+        let mut record = GameRecord::new(init_game());
+        play(&mut record, "e2", "e4");
+        record.offer_draw(COLORS::WHITE).unwrap();
+        assert_eq!(record.get_draw_offer(), Some(COLORS::WHITE));
+        assert!(record.offer_draw(COLORS::WHITE).is_err());
+        assert!(record.accept_draw(COLORS::WHITE).is_err());
+        record.accept_draw(COLORS::BLACK).unwrap();
+        assert_eq!(record.get_outcome(), Some(GameOutcome::draw(Termination::Agreement)));
+        assert!(record.play(Move::new(algebraic_notation_to_memory_location("e7"), algebraic_notation_to_memory_location("e5"), TYPES::NONE)).is_err());
+
+        let pgn_game = record.to_pgn();
+        assert_eq!(pgn_game.tag("Result"), Some("1/2-1/2"));
+        assert_eq!(pgn_game.tag("Termination"), Some("normal"));
+    }
+
+    #[test]
+    fn draw_offer_declined_or_answered_with_a_move() {
+        let mut record = GameRecord::new(init_game());
+        assert!(record.decline_draw(COLORS::BLACK).is_err());
+        record.offer_draw(COLORS::WHITE).unwrap();
+        record.decline_draw(COLORS::BLACK).unwrap();
+        assert_eq!(record.get_draw_offer(), None);
+
+        play(&mut record, "e2", "e4");
+        record.offer_draw(COLORS::WHITE).unwrap();
+        play(&mut record, "e7", "e5");
+        assert_eq!(record.get_draw_offer(), None);
+        assert_eq!(record.get_events().last(), Some(&GameEvent::Move(record.last_move().unwrap())));
+        assert!(record.get_events().contains(&GameEvent::DrawDeclined(COLORS::BLACK)));
+        // the other side offering accepts a standing offer
+        record.offer_draw(COLORS::WHITE).unwrap();
+        record.offer_draw(COLORS::BLACK).unwrap();
+        assert_eq!(record.get_outcome(), Some(GameOutcome::draw(Termination::Agreement)));
+    }
+
+    #[test]
+    fn undo_takes_back_moves_and_endings() {
+        let mut record = GameRecord::new(init_game());
+        play(&mut record, "e2", "e4");
+        let e4 = record.last_move().unwrap();
+        record.offer_draw(COLORS::WHITE).unwrap();
+        assert_eq!(record.undo(), Some(GameEvent::Move(e4)));
+        assert_eq!(record.get_game().get_fen(), init_game().get_fen());
+        assert_eq!(record.get_draw_offer(), None);
+        assert_eq!(record.undo(), None);
+
+        play(&mut record, "e2", "e4");
+        record.resign(COLORS::BLACK).unwrap();
+        assert_eq!(record.get_outcome(), Some(GameOutcome::win(COLORS::WHITE, Termination::Resignation)));
+        assert_eq!(record.undo(), Some(GameEvent::Resignation(COLORS::BLACK)));
+        assert_eq!(record.get_outcome(), None);
+        assert_eq!(record.get_moves().len(), 1);
+    }
+
+    #[test]
+    fn pgn_tags_follow_the_outcome() {
+        let mut record = GameRecord::new(init_game());
+        assert_eq!(record.to_pgn().tag("Result"), Some("*"));
+        assert_eq!(record.to_pgn().tag("Termination"), None);
+        for (from, to) in [("f2", "f3"), ("e7", "e5"), ("g2", "g4"), ("d8", "h4")] {
+            play(&mut record, from, to);
+        }
+        let pgn_game = record.to_pgn();
+        assert_eq!(pgn_game.tag("Result"), Some("0-1"));
+        assert_eq!(pgn_game.tag("Termination"), Some("normal"));
+
+        let mut record = GameRecord::new(init_game());
+        record.lose_on_time(COLORS::WHITE).unwrap();
+        let pgn_game = record.to_pgn();
+        assert_eq!(pgn_game.tag("Result"), Some("0-1"));
+        assert_eq!(pgn_game.tag("Termination"), Some("time forfeit"));
+    }
+
+    #[test]
+    fn finish_keeps_the_outcome_or_refuses() {
+        let mut record = GameRecord::new(init_game());
+        assert!(record.finish(GameOutcome::draw(Termination::TimeoutVsInsufficient)).is_err());
+        assert_eq!(record.get_outcome(), None);
+        assert!(record.get_events().is_empty());
+        assert!(record.finish(GameOutcome::win(COLORS::WHITE, Termination::Checkmate)).is_err());
+
+        record.finish(GameOutcome::win(COLORS::WHITE, Termination::Resignation)).unwrap();
+        assert_eq!(record.get_outcome(), Some(GameOutcome::win(COLORS::WHITE, Termination::Resignation)));
+        assert_eq!(record.to_pgn().tag("Result"), Some("1-0"));
+
+        // white's flag falls but black has only a king
+        let mut record = GameRecord::new(init_game_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        record.finish(GameOutcome::draw(Termination::TimeoutVsInsufficient)).unwrap();
+        let pgn_game = record.to_pgn();
+        assert_eq!(pgn_game.tag("Result"), Some("1/2-1/2"));
+        assert_eq!(pgn_game.tag("Termination"), Some("time forfeit"));
+
+        let mut record = GameRecord::new(init_game());
+        record.finish(GameOutcome::draw(Termination::Agreement)).unwrap();
+        assert_eq!(record.get_outcome(), Some(GameOutcome::draw(Termination::Agreement)));
+    }
+
+    fn shuffle(game: &mut GAME, moves: &[(&str, &str)]) {
+        for (from, to) in moves {
+            let chess_move = Move::new(algebraic_notation_to_memory_location(from), algebraic_notation_to_memory_location(to), TYPES::NONE);
+            assert!(make_move(&chess_move, game), "{}{} in {}", from, to, game.get_fen());
+        }
+    }
+
+    #[test]
+    fn repetitions_count_checks_and_pockets() {
+        let checking_cycle = [("a1", "a8"), ("e8", "e7"), ("a8", "a1"), ("e7", "e8")];
+        let mut game = init_game_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        shuffle(&mut game, &checking_cycle);
+        assert!(!game.is_repetition());
+        shuffle(&mut game, &checking_cycle);
+        assert!(game.is_repetition());
+        assert_eq!(game.get_outcome().unwrap().describe(), "draw by repetition");
+
+        // in three-check every cycle gives another check, the positions differ
+        let mut game = init_game_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +0+0");
+        shuffle(&mut game, &checking_cycle);
+        shuffle(&mut game, &checking_cycle);
+        assert_eq!(game.get_checks_given(), [2, 0]);
+        assert!(!game.is_repetition());
+        assert!(game.get_outcome().is_none());
+
+        // crazyhouse positions with other pockets are other positions, the Polyglot key doesn't see them
+        let with_knight = init_game_from_fen("4k3/8/8/8/8/8/8/4K1N1[N] w - - 0 1");
+        let without = init_game_from_fen("4k3/8/8/8/8/8/8/4K1N1[] w - - 0 1");
+        assert_eq!(with_knight.get_zobrist_key(), without.get_zobrist_key());
+        assert_ne!(zobrist::repetition_key(&with_knight), zobrist::repetition_key(&without));
+        assert_ne!(zobrist::repetition_key(&with_knight), zobrist::repetition_key(&init_game_from_fen("4k3/8/8/8/8/8/8/4K1N1[n] w - - 0 1")));
+        assert_eq!(zobrist::repetition_key(&init_game()), init_game().get_zobrist_key());
+
+        // the same pockets still repeat
+        let knight_cycle = [("g1", "f3"), ("e8", "d8"), ("f3", "g1"), ("d8", "e8")];
+        let mut game = with_knight;
+        shuffle(&mut game, &knight_cycle);
+        shuffle(&mut game, &knight_cycle);
+        assert_eq!(game.get_pocket(COLORS::WHITE), [0, 1, 0, 0, 0]);
+        assert!(game.is_repetition());
+    }
+}
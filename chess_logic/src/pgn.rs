@@ -0,0 +1,474 @@
+use std::fs;
+use std::io;
+
+use crate::init_variant_game;
+use crate::make_move;
+use crate::memory_location_to_algebraic_notation;
+use crate::validation::game_from_fen;
+use crate::Move;
+use crate::variant::Variant;
+use crate::TYPES;
+use crate::GAME;
+
+#[derive(Clone, Debug, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,                                     // SAN as written in the movetext
+    pub result: String,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str())
+    }
+
+    // "1-0", "0-1", "1/2-1/2" or "*", the Result tag wins over the movetext token.
+    pub fn get_result(&self) -> &str {
+        match self.tag("Result") {
+            Some(result) => result,
+            None => self.result.as_str(),
+        }
+    }
+
+    // Standard without a Variant tag, None for variants this library doesn't play.
+    pub fn get_variant(&self) -> Option<Variant> {
+        match self.tag("Variant") {
+            Some(name) => Variant::from_name(name),
+            None => Some(Variant::Standard),
+        }
+    }
+
+    // The position before the first move, from the FEN and Variant tags. An error for variants this
+    // library doesn't play and for FEN tags that aren't a legal position.
+    pub fn get_starting_game(&self) -> Result<GAME, String> {
+        let variant = match self.get_variant() {
+            Some(variant) => variant,
+            None => return Err(format!("{} is a variant this library doesn't play", self.tag("Variant").unwrap_or(""))),
+        };
+        let mut game = game_from_fen(self.tag("FEN").unwrap_or(variant.get_starting_fen()), variant)?;
+        let variant_name = self.tag("Variant").unwrap_or("").to_ascii_lowercase();
+        if variant_name.contains("960") || variant_name.contains("fischer") {
+            game.set_chess960(true);
+        }
+        game.set_variant(variant);
+        Ok(game)
+    }
+}
+
+// PGN for moves played from start. The seven standard tags come first, then Variant and FEN
+// (with SetUp) when the game is another variant or doesn't start from the usual position.
+pub fn moves_to_pgn(start: &GAME, moves: &[Move], result: &str) -> PgnGame {
+    let mut pgn_game = PgnGame::default();
+    for (name, value) in [("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"), ("White", "?"), ("Black", "?"), ("Result", result)] {
+        pgn_game.tags.push((name.to_string(), value.to_string()));
+    }
+    if let Some(variant) = variant_tag(start) {
+        pgn_game.tags.push(("Variant".to_string(), variant));
+    }
+    let fen = start.get_fen();
+    if start.is_chess960() || fen != init_variant_game(start.get_variant()).get_fen() {
+        pgn_game.tags.push(("SetUp".to_string(), "1".to_string()));
+        pgn_game.tags.push(("FEN".to_string(), fen));
+    }
+
+    let mut game = start.clone();
+    for chess_move in moves {
+        pgn_game.moves.push(move_to_san(chess_move, &game));
+        make_move(chess_move, &mut game);
+    }
+    pgn_game.result = result.to_string();
+    pgn_game
+}
+
+// The tags and the movetext with move numbers, lines kept under 80 characters.
+pub fn write_pgn(pgn_game: &PgnGame) -> String {
+    let start = pgn_game.get_starting_game().unwrap_or_else(|_| crate::init_game());
+    let mut text = String::new();
+    for (name, value) in &pgn_game.tags {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+    }
+    text.push('\n');
+
+    let mut words = Vec::new();
+    let mut white_to_move = start.is_whites_turn();
+    let mut move_number = start.get_fullmove_number();
+    for (index, san) in pgn_game.moves.iter().enumerate() {
+        if white_to_move {
+            words.push(format!("{}.", move_number));
+        } else if index == 0 {
+            words.push(format!("{}...", move_number));
+        }
+        words.push(san.clone());
+        if !white_to_move {
+            move_number += 1;
+        }
+        white_to_move = !white_to_move;
+    }
+    words.push(pgn_game.get_result().to_string());
+
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + word.len() + 1 > 79 {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+// The Variant tag of a game, None for standard chess which goes without one.
+pub fn variant_tag(game: &GAME) -> Option<String> {
+    match (game.get_variant(), game.is_chess960()) {
+        (Variant::Standard, false) => None,
+        (Variant::Standard, true) => Some("Chess960".to_string()),
+        (variant, _) => Some(variant.get_name().to_string()),
+    }
+}
+
+pub fn read_pgn_file(path: &str) -> io::Result<Vec<PgnGame>> {
+    let text = fs::read_to_string(path)?;
+    Ok(parse_pgn(&text))
+}
+
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut current = PgnGame::default();
+    let mut in_movetext = false;
+    let mut comment_depth = 0;
+    let mut variation_depth = 0;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if comment_depth == 0 && variation_depth == 0 && line.starts_with('[') {
+            if in_movetext {
+                games.push(current);
+                current = PgnGame::default();
+                in_movetext = false;
+            }
+            if let Some(tag) = parse_tag(line) {
+                current.tags.push(tag);
+            }
+            continue;
+        }
+        if line.starts_with('%') {
+            continue;
+        }
+
+        let mut token = String::new();
+        for character in line.chars() {
+            if comment_depth > 0 {
+                if character == '}' {
+                    comment_depth = 0;
+                }
+                continue;
+            }
+            match character {
+                '{' => {
+                    finish_token(&mut token, &mut current, &mut in_movetext, variation_depth);
+                    comment_depth = 1;
+                }
+                ';' => break,
+                '(' => {
+                    finish_token(&mut token, &mut current, &mut in_movetext, variation_depth);
+                    variation_depth += 1;
+                }
+                ')' => {
+                    finish_token(&mut token, &mut current, &mut in_movetext, variation_depth);
+                    variation_depth = variation_depth.saturating_sub(1);
+                }
+                _ if character.is_whitespace() => finish_token(&mut token, &mut current, &mut in_movetext, variation_depth),
+                _ => token.push(character),
+            }
+        }
+        finish_token(&mut token, &mut current, &mut in_movetext, variation_depth);
+    }
+
+    if in_movetext || !current.tags.is_empty() {
+        games.push(current);
+    }
+    games
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.trim_start_matches('[').trim_end_matches(']').trim();
+    let space = inner.find(char::is_whitespace)?;
+    let name = inner[..space].to_string();
+    let quoted = inner[space..].trim();
+    let quoted = quoted.strip_prefix('"').unwrap_or(quoted);
+    let quoted = quoted.strip_suffix('"').unwrap_or(quoted);
+
+    // \" and \\ stand for a quote and a backslash
+    let mut value = String::new();
+    let mut characters = quoted.chars();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' => value.extend(characters.next()),
+            _ => value.push(character),
+        }
+    }
+    Some((name, value))
+}
+
+fn finish_token(token: &mut String, game: &mut PgnGame, in_movetext: &mut bool, variation_depth: u32) {
+    if token.is_empty() {
+        return;
+    }
+    *in_movetext = true;
+    if variation_depth == 0 {
+        // move numbers can be glued to the move, as in "12.e4" or "12...Nf6"
+        let word = match token.rfind('.') {
+            Some(index) => &token[index + 1..],
+            None => token.as_str(),
+        };
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&word) {
+            game.result = word.to_string();
+        } else if word.starts_with(|c: char| c.is_ascii_alphabetic()) || word.starts_with("0-0") || word.starts_with('@') {
+            game.moves.push(word.to_string());
+        }
+    }
+    token.clear();
+}
+
+// Finds the legal move that the SAN string describes, None if there is none or it is ambiguous.
+pub fn san_to_move(san: &str, game: &GAME) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let legal_moves = game.generate_legal_moves();
+
+    // crazyhouse drops like N@f3, pawns as P@e4 or @e4
+    if let Some(at) = san.find('@') {
+        let piece_type = match &san[..at] {
+            "" | "P" => TYPES::PAWN,
+            symbol => piece_type_from_san(symbol.chars().next()?).filter(|_| symbol.len() == 1)?,
+        };
+        let drop = Move::new_drop(piece_type, parse_square(&san[at + 1..])?);
+        return legal_moves.into_iter().find(|chess_move| *chess_move == drop);
+    }
+
+    if san == "O-O" || san == "0-0" || san == "O-O-O" || san == "0-0-0" {
+        let king_side = san.len() == 3;
+        return legal_moves.into_iter().find(|chess_move| game.is_castling(chess_move) && (chess_move.to > chess_move.from) == king_side);
+    }
+
+    let mut characters: Vec<char> = san.chars().filter(|c| *c != 'x' && *c != '-' && *c != '=').collect();
+
+    let mut promotion = TYPES::NONE;
+    if let Some(last) = characters.last() {
+        if let Some(piece_type) = piece_type_from_san(*last) {
+            promotion = piece_type;
+            characters.pop();
+        }
+    }
+
+    let piece_type = match characters.first().and_then(|c| piece_type_from_san(*c)) {
+        Some(piece_type) => {
+            characters.remove(0);
+            piece_type
+        }
+        None => TYPES::PAWN,
+    };
+
+    if characters.len() < 2 {
+        return None;
+    }
+    let destination: String = characters[characters.len() - 2..].iter().collect();
+    let to_tile = parse_square(&destination)?;
+    let disambiguation = &characters[..characters.len() - 2];
+
+    let mut candidates = legal_moves.into_iter().filter(|chess_move| {
+        chess_move.to == to_tile &&
+            chess_move.promotion == promotion &&
+            game.board[chess_move.from] & piece_type > 0 &&
+            disambiguation.iter().all(|c| {
+                if c.is_ascii_digit() {
+                    (8 - chess_move.from / 8) as u32 == c.to_digit(10).unwrap()
+                } else {
+                    (b'a' + (chess_move.from % 8) as u8) as char == *c
+                }
+            })
+    });
+    let found = candidates.next()?;
+    if candidates.next().is_some() {
+        return None;
+    }
+    Some(found)
+}
+
+// The SAN of a legal move, with the file, rank or square of the moving piece added when
+// another piece of the same type could go to the same square.
+pub fn move_to_san(chess_move: &Move, game: &GAME) -> String {
+    let piece = game.board[chess_move.from];
+    let mut san = String::new();
+
+    if chess_move.is_drop() {
+        san.push(if chess_move.drop == TYPES::PAWN { 'P' } else { san_symbol(chess_move.drop) });
+        san.push('@');
+        san.push_str(&memory_location_to_algebraic_notation(chess_move.to));
+    } else if game.is_castling(chess_move) {
+        san.push_str(if chess_move.to > chess_move.from { "O-O" } else { "O-O-O" });
+    } else {
+        let from_square = memory_location_to_algebraic_notation(chess_move.from);
+        if piece & TYPES::PAWN > 0 {
+            if game.is_capture(chess_move) {
+                san.push_str(&from_square[..1]);
+            }
+        } else {
+            san.push(san_symbol(piece));
+            let others: Vec<Move> = game.generate_legal_moves().into_iter().filter(|other| {
+                other.to == chess_move.to && other.from != chess_move.from && game.board[other.from] == piece
+            }).collect();
+            if !others.is_empty() {
+                let same_file = others.iter().any(|other| other.from % 8 == chess_move.from % 8);
+                let same_rank = others.iter().any(|other| other.from / 8 == chess_move.from / 8);
+                if !same_file {
+                    san.push_str(&from_square[..1]);
+                } else if !same_rank {
+                    san.push_str(&from_square[1..]);
+                } else {
+                    san.push_str(&from_square);
+                }
+            }
+        }
+        if game.is_capture(chess_move) {
+            san.push('x');
+        }
+        san.push_str(&memory_location_to_algebraic_notation(chess_move.to));
+        if chess_move.promotion != TYPES::NONE {
+            san.push('=');
+            san.push(san_symbol(chess_move.promotion));
+        }
+    }
+
+    let mut virtual_game = game.clone();
+    if make_move(chess_move, &mut virtual_game) && virtual_game.is_check() {
+        san.push(if virtual_game.generate_legal_moves().is_empty() { '#' } else { '+' });
+    }
+    san
+}
+
+fn san_symbol(piece: u8) -> char {
+    if piece & TYPES::KNIGHT > 0 {
+        'N'
+    } else if piece & TYPES::BISHOP > 0 {
+        'B'
+    } else if piece & TYPES::ROOK > 0 {
+        'R'
+    } else if piece & TYPES::QUEEN > 0 {
+        'Q'
+    } else {
+        'K'
+    }
+}
+
+fn piece_type_from_san(character: char) -> Option<u8> {
+    match character {
+        'N' => Some(TYPES::KNIGHT),
+        'B' => Some(TYPES::BISHOP),
+        'R' => Some(TYPES::ROOK),
+        'Q' => Some(TYPES::QUEEN),
+        'K' => Some(TYPES::KING),
+        _ => None,
+    }
+}
+
+pub fn parse_square(square: &str) -> Option<usize> {
+    let mut characters = square.chars();
+    let file = characters.next()?;
+    let rank = characters.next()?;
+    if characters.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+    let file = file as usize - 'a' as usize;
+    let rank = rank as usize - '1' as usize;
+    Some((7 - rank) * 8 + file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_game, init_game_from_fen};
+
+    fn san(game: &GAME, text: &str) -> Option<String> {
+        san_to_move(text, game).map(|chess_move| chess_move.to_uci())
+    }
+
+    // Every legal move gets a SAN that reads back as the same move.
+    fn assert_san_round_trip(game: &GAME) {
+        for chess_move in game.generate_legal_moves() {
+            let text = move_to_san(&chess_move, game);
+            assert_eq!(san_to_move(&text, game), Some(chess_move), "{} in {}", text, game.get_fen());
+        }
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        let game = init_game_from_fen("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1");
+        assert_eq!(san(&game, "Nd2"), None);
+        assert_eq!(san(&game, "Nbd2"), Some("b1d2".to_string()));
+        assert_eq!(san(&game, "Nfd2"), Some("f1d2".to_string()));
+        assert_eq!(san(&game, "Ra3"), None);
+        assert_eq!(san(&game, "R1a3"), Some("a1a3".to_string()));
+        assert_eq!(san(&game, "R5a3"), Some("a5a3".to_string()));
+        assert_eq!(san(&game, "Nc4"), None);
+        assert_eq!(move_to_san(&san_to_move("Nbd2", &game).unwrap(), &game), "Nbd2");
+        assert_eq!(move_to_san(&san_to_move("R1a3", &game).unwrap(), &game), "R1a3");
+        assert_san_round_trip(&game);
+    }
+
+    #[test]
+    fn san_castling() {
+        let game = init_game_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(san(&game, "O-O"), Some("e1g1".to_string()));
+        assert_eq!(san(&game, "0-0-0"), Some("e1c1".to_string()));
+        assert_eq!(move_to_san(&san_to_move("O-O-O", &game).unwrap(), &game), "O-O-O");
+        assert_san_round_trip(&game);
+        assert_eq!(san(&init_game(), "O-O"), None);
+    }
+
+    #[test]
+    fn san_drops() {
+        let game = game_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Np] w KQkq - 0 1", Variant::Crazyhouse).unwrap();
+        assert_eq!(san_to_move("N@e4", &game), Some(Move::new_drop(TYPES::KNIGHT, parse_square("e4").unwrap())));
+        assert_eq!(san(&game, "P@e4"), None);                  // the pawn is black's
+        assert_eq!(san(&game, "N@e2"), None);
+        assert_eq!(move_to_san(&Move::new_drop(TYPES::KNIGHT, parse_square("e4").unwrap()), &game), "N@e4");
+        assert_san_round_trip(&game);
+    }
+
+    #[test]
+    fn san_promotions_and_mates() {
+        let game = init_game_from_fen("3nk3/2P5/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(san(&game, "c8=Q"), Some("c7c8q".to_string()));
+        assert_eq!(san(&game, "cxd8N"), Some("c7d8n".to_string()));
+        assert_eq!(san(&game, "c8"), None);
+        assert_san_round_trip(&game);
+
+        let game = init_game_from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4");
+        assert_eq!(move_to_san(&san_to_move("Qxf7", &game).unwrap(), &game), "Qxf7#");
+        assert_san_round_trip(&game);
+        assert_san_round_trip(&init_game());
+    }
+
+    #[test]
+    fn tags_escape_quotes_and_backslashes() {
+        let mut pgn_game = moves_to_pgn(&init_game(), &[], "*");
+        pgn_game.tags[0].1 = "a \\ and a \" in the name".to_string();
+        let text = write_pgn(&pgn_game);
+        assert!(text.starts_with("[Event \"a \\\\ and a \\\" in the name\"]\n"), "{}", text);
+        assert_eq!(parse_pgn(&text)[0].tag("Event"), Some("a \\ and a \" in the name"));
+    }
+
+    #[test]
+    fn movetext_skips_comments_variations_and_numbers() {
+        let games = parse_pgn("[Event \"x\"]\n\n1. e4 {best by test} e5 (1... c5 2. Nf3) 2.Nf3 Nc6 ; rest\n3. Bb5 1-0\n");
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].moves, ["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(games[0].get_result(), "1-0");
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::io;
+
+use crate::engine::Engine;
+use crate::init_game_from_fen;
+use crate::make_move;
+use crate::pgn::{san_to_move, PgnGame};
+use crate::tactics::{find_tactics, is_checkmate};
+use crate::validation::game_from_fen;
+use crate::variant::Variant;
+use crate::Move;
+use crate::GAME;
+
+// A puzzle starts right after the blunder with the solver to move. The solution alternates the
+// solver's moves and the replies, ending on a move of the solver.
+// In a file every puzzle is one line, the FEN, the solution in UCI and the themes split by commas:
+// r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4,h5f7,mateIn1 oneMove
+#[derive(Clone, Debug, Default)]
+pub struct Puzzle {
+    pub fen: String,
+    pub solution: Vec<String>,                                  // moves in UCI, like e2e4 or e7e8q
+    pub themes: Vec<String>,                                    // tactics motif names and oneMove, short or long
+}
+
+impl Puzzle {
+    pub fn get_game(&self) -> GAME {
+        init_game_from_fen(&self.fen)
+    }
+
+    // The solution as moves, None when one of them can't be played from the puzzle's position.
+    pub fn get_solution_moves(&self) -> Option<Vec<Move>> {
+        let mut game = self.get_game();
+        let mut moves = Vec::new();
+        for uci in &self.solution {
+            let chess_move = game.generate_legal_moves().into_iter().find(|chess_move| chess_move.to_uci() == *uci)?;
+            make_move(&chess_move, &mut game);
+            moves.push(chess_move);
+        }
+        Some(moves)
+    }
+
+    pub fn to_line(&self) -> String {
+        format!("{},{},{}", self.fen, self.solution.join(" "), self.themes.join(" "))
+    }
+}
+
+// None for empty lines, comments starting with # and lines without a position and a solution,
+// or with a FEN that isn't a legal position.
+pub fn parse_puzzle_line(line: &str) -> Option<Puzzle> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let mut fields = line.split(',');
+    let fen = fields.next()?.trim().to_string();
+    let solution: Vec<String> = fields.next()?.split_whitespace().map(|uci| uci.to_string()).collect();
+    let themes = fields.next().unwrap_or("").split_whitespace().map(|theme| theme.to_string()).collect();
+    if fen.is_empty() || solution.is_empty() || game_from_fen(&fen, Variant::Standard).is_err() {
+        return None;
+    }
+    Some(Puzzle { fen, solution, themes })
+}
+
+pub fn read_puzzle_file(path: &str) -> io::Result<Vec<Puzzle>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.lines().filter_map(parse_puzzle_line).collect())
+}
+
+pub fn write_puzzle_file(path: &str, puzzles: &[Puzzle]) -> io::Result<()> {
+    let mut text = String::new();
+    for puzzle in puzzles {
+        text.push_str(&puzzle.to_line());
+        text.push('\n');
+    }
+    fs::write(path, text)
+}
+
+// Replays games through the engine looking for blunders: moves that lose at least blunder_threshold
+// centipawns and leave the opponent at least winning_score up. The engine has no book or movetime so
+// the same games always give the same puzzles.
+pub struct PuzzleGenerator {
+    pub depth: u8,
+    pub blunder_threshold: i32,
+    pub winning_score: i32,
+    pub max_solution_moves: usize,                              // moves of the solver, the replies not counted
+}
+
+impl Default for PuzzleGenerator {
+    fn default() -> Self {
+        PuzzleGenerator::new()
+    }
+}
+
+impl PuzzleGenerator {
+    pub fn new() -> PuzzleGenerator {
+        PuzzleGenerator {
+            depth: 3,
+            blunder_threshold: 200,
+            winning_score: 300,
+            max_solution_moves: 3,
+        }
+    }
+
+    // Standard games only, the moves after one that can't be played are left out.
+    pub fn puzzles_from_game(&self, pgn_game: &PgnGame) -> Vec<Puzzle> {
+        let mut puzzles = Vec::new();
+        if pgn_game.get_variant() != Some(Variant::Standard) {
+            return puzzles;
+        }
+        let mut game = match pgn_game.get_starting_game() {
+            Ok(game) => game,
+            Err(_) => return puzzles,
+        };
+        let engine = Engine::new(self.depth);
+        let mut score = engine.search(&game).score;             // for the side to move
+
+        for san in &pgn_game.moves {
+            let chess_move = match san_to_move(san, &game) {
+                Some(chess_move) => chess_move,
+                None => break,
+            };
+            make_move(&chess_move, &mut game);
+            let score_after = engine.search(&game).score;
+            let was_lost = score <= -self.winning_score;
+            if !was_lost && score + score_after >= self.blunder_threshold && score_after >= self.winning_score {
+                if let Some(puzzle) = self.puzzle_at(&game) {
+                    puzzles.push(puzzle);
+                }
+            }
+            score = score_after;
+        }
+        puzzles
+    }
+
+    // A puzzle for the side to move when it wins decisively with only one move, followed by the
+    // engine's replies for as long as the winning move stays the only one.
+    pub fn puzzle_at(&self, game: &GAME) -> Option<Puzzle> {
+        let engine = Engine::new(self.depth);
+        let mut position = game.clone();
+        let mut line = Vec::new();
+
+        for _ in 0..self.max_solution_moves {
+            let scored_moves = engine.score_moves(&position);
+            let (best_move, best_score) = match scored_moves.first() {
+                Some(best) => *best,
+                None => break,
+            };
+            if best_score < self.winning_score {
+                break;
+            }
+            let mut after = position.clone();
+            make_move(&best_move, &mut after);
+            // other mates are fine as well, the trainer accepts them
+            let only_move = scored_moves.get(1).iter().all(|(_, score)| *score < self.winning_score);
+            if !only_move && !is_checkmate(&after) {
+                break;
+            }
+            line.push(best_move);
+            position = after;
+
+            let reply = match engine.best_move(&position) {
+                Some(reply) if position.get_variant_winner().is_none() => reply,
+                _ => break,
+            };
+            line.push(reply);
+            make_move(&reply, &mut position);
+        }
+        if line.len() % 2 == 0 {
+            line.pop();
+        }
+        if line.is_empty() {
+            return None;
+        }
+
+        let mut themes: Vec<String> = Vec::new();
+        for tag in find_tactics(game, Some(&line)) {
+            let name = tag.motif.get_name();
+            if !themes.contains(&name) {
+                themes.push(name);
+            }
+        }
+        themes.push(match (line.len() + 1) / 2 {
+            1 => "oneMove".to_string(),
+            2 => "short".to_string(),
+            _ => "long".to_string(),
+        });
+        Some(Puzzle {
+            fen: game.get_fen(),
+            solution: line.iter().map(|chess_move| chess_move.to_uci()).collect(),
+            themes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgn::parse_pgn;
+
+    const SCHOLARS_MATE: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4";
+
+    // a shallow engine is enough for mate in one and keeps the tests quick
+    fn generator() -> PuzzleGenerator {
+        PuzzleGenerator { depth: 2, ..PuzzleGenerator::new() }
+    }
+
+    #[test]
+    fn puzzle_line_round_trip() {
+        let line = format!("{},h5f7,mateIn1 oneMove", SCHOLARS_MATE);
+        let puzzle = parse_puzzle_line(&line).expect("a puzzle");
+        assert_eq!(puzzle.solution, vec!["h5f7"]);
+        assert_eq!(puzzle.get_solution_moves().map(|moves| moves.len()), Some(1));
+        assert_eq!(puzzle.to_line(), line);
+        assert!(parse_puzzle_line("# a comment").is_none());
+        assert!(parse_puzzle_line(SCHOLARS_MATE).is_none());
+        assert!(parse_puzzle_line("xyz,e2e4,fork").is_none());
+        assert!(parse_puzzle_line("8/8/8/8/8/8/8/8 w - - 0 1,e2e4,fork").is_none());
+    }
+
+    #[test]
+    fn puzzle_at_finds_the_mate() {
+        let puzzle = generator().puzzle_at(&init_game_from_fen(SCHOLARS_MATE)).expect("a puzzle");
+        assert_eq!(puzzle.fen, SCHOLARS_MATE);
+        assert_eq!(puzzle.solution, vec!["h5f7"]);
+        assert_eq!(puzzle.themes, vec!["mateIn1", "oneMove"]);
+    }
+
+    #[test]
+    fn puzzles_from_game_start_after_the_blunder() {
+        let pgn_game = &parse_pgn("1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0")[0];
+        let puzzles = generator().puzzles_from_game(pgn_game);
+        assert!(puzzles.iter().any(|puzzle| puzzle.fen == SCHOLARS_MATE && puzzle.solution == vec!["h5f7"]), "{:?}", puzzles);
+    }
+}
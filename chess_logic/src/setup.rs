@@ -0,0 +1,208 @@
+use crate::validation::{castling_loaded, PositionProblem};
+use crate::zobrist;
+use crate::{init_game_from_fen, load_chastling_from_fen, side_to_move_is_checked};
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+// The king and rook tiles of every castling right in the usual starting position, KQkq.
+const CASTLING_START_TILES: [(usize, usize); 4] = [(60, 63), (60, 56), (4, 7), (4, 0)];
+
+// Setting up a position by hand: start from GAME::empty() (or any game), put and remove pieces, choose the
+// side to move, castling and en passant, then call finish_setup before playing from it. Castling rights
+// follow the pieces, a king and rook put on their starting tiles get theirs and moving them away takes it back.
+impl GAME {
+    // No pieces, white to move, move 1.
+    pub fn empty() -> GAME {
+        init_game_from_fen("8/8/8/8/8/8/8/8 w - - 0 1")
+    }
+
+    // piece is a type and a colour, like TYPES::KNIGHT + COLORS::WHITE, whatever stood on the tile is replaced.
+    pub fn put_piece(&mut self, tile: usize, piece: u8) -> bool {
+        let piece_type = piece & !(COLORS::WHITE | COLORS::BLACK);
+        let color = piece & (COLORS::WHITE | COLORS::BLACK);
+        if tile > 63 || piece_type.count_ones() != 1 || color.count_ones() != 1 {
+            return false;
+        }
+        self.board[tile] = piece;
+        self.promoted[tile] = false;
+        self.after_edit();
+        for (right, (king_tile, rook_tile)) in CASTLING_START_TILES.iter().enumerate() {
+            if tile == *king_tile || tile == *rook_tile {
+                self.infer_castling_right(right);
+            }
+        }
+        true
+    }
+
+    pub fn remove_piece(&mut self, tile: usize) -> bool {
+        if tile > 63 || self.board[tile] == TYPES::NONE {
+            return false;
+        }
+        self.board[tile] = TYPES::NONE;
+        self.promoted[tile] = false;
+        self.after_edit();
+        true
+    }
+
+    // COLORS::WHITE or COLORS::BLACK, anything else leaves the turn as it was.
+    pub fn set_side_to_move(&mut self, color: u8) -> bool {
+        if color != COLORS::WHITE && color != COLORS::BLACK {
+            return false;
+        }
+        self.turn = color;
+        self.after_edit();
+        true
+    }
+
+    // A FEN castling field, KQkq, Shredder files like HAha or - for none. Nothing changes when one
+    // of the rights has no king and rook for it.
+    pub fn set_castling(&mut self, castling: &str) -> Result<(), PositionProblem> {
+        let (ability, rooks, chess960) = (self.chastling_ability, self.chastling_rooks, self.chess960);
+        self.chastling_ability = [false; 4];
+        let board = self.board;
+        load_chastling_from_fen(castling, &board, self);
+        if let Some(symbol) = castling.chars().find(|symbol| *symbol != '-' && !castling_loaded(self, *symbol)) {
+            self.chastling_ability = ability;
+            self.chastling_rooks = rooks;
+            self.chess960 = chess960;
+            return Err(PositionProblem::ImpossibleCastling(symbol));
+        }
+        Ok(())
+    }
+
+    // Gives every castling right whose king and rook stand on their usual starting tiles.
+    pub fn infer_castling(&mut self) {
+        for right in 0..4 {
+            self.infer_castling_right(right);
+        }
+    }
+
+    // The tile a pawn of the side that just moved skipped, None for no en passant.
+    pub fn set_en_passant(&mut self, tile: Option<usize>) -> Result<(), PositionProblem> {
+        match tile {
+            None => self.tile_available_to_un_passant = 100,
+            Some(tile) if tile <= 63 && self.en_passant_possible(tile) => self.tile_available_to_un_passant = tile as u8,
+            Some(tile) => return Err(PositionProblem::BadEnPassant(tile)),
+        }
+        Ok(())
+    }
+
+    // Ends the setup: the position starts the game's history and is validated, play only from an Ok.
+    pub fn finish_setup(&mut self) -> Result<(), Vec<PositionProblem>> {
+        self.moves.clear();
+        self.potential_tile_to_un_passant = 100;
+        self.check = side_to_move_is_checked(self);
+        self.position_keys.clear();
+        self.position_keys.push(zobrist::repetition_key(self));
+        self.validate()
+    }
+
+    fn infer_castling_right(&mut self, right: usize) {
+        let color = if right < 2 { COLORS::WHITE } else { COLORS::BLACK };
+        let (king_tile, rook_tile) = CASTLING_START_TILES[right];
+        if self.board[king_tile] == TYPES::KING + color && self.board[rook_tile] == TYPES::ROOK + color && !self.chastling_ability[right] {
+            self.chastling_ability[right] = true;
+            self.chastling_rooks[right] = rook_tile;
+        }
+    }
+
+    // Castling and en passant that the edit made impossible are dropped.
+    fn after_edit(&mut self) {
+        for right in 0..4 {
+            if self.chastling_ability[right] && !self.castling_pieces_in_place(right) {
+                self.chastling_ability[right] = false;
+            }
+        }
+        if self.tile_available_to_un_passant <= 63 && !self.en_passant_possible(self.tile_available_to_un_passant as usize) {
+            self.tile_available_to_un_passant = 100;
+        }
+        self.check = side_to_move_is_checked(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algebraic_notation_to_memory_location;
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    // Kings on e1 and e8 with a white rook on h1, the usual kingside castling setup.
+    fn kings_and_rook() -> GAME {
+        let mut game = GAME::empty();
+        assert!(game.put_piece(tile("e1"), TYPES::KING + COLORS::WHITE));
+        assert!(game.put_piece(tile("e8"), TYPES::KING + COLORS::BLACK));
+        assert!(game.put_piece(tile("h1"), TYPES::ROOK + COLORS::WHITE));
+        game
+    }
+
+    #[test]
+    fn put_piece_infers_castling_and_replacing_the_rook_loses_it() {
+        let mut game = kings_and_rook();
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert!(game.put_piece(tile("h1"), TYPES::KNIGHT + COLORS::WHITE));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2N w - - 0 1");
+        assert!(game.put_piece(tile("h1"), TYPES::ROOK + COLORS::WHITE));
+        assert!(game.remove_piece(tile("e1")));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/7R w - - 0 1");
+        assert!(!game.put_piece(64, TYPES::ROOK + COLORS::WHITE));
+        assert!(!game.put_piece(tile("a1"), TYPES::ROOK | TYPES::KNIGHT | COLORS::WHITE));
+        assert!(!game.remove_piece(tile("a1")));
+    }
+
+    #[test]
+    fn side_to_move_is_white_or_black() {
+        let mut game = kings_and_rook();
+        assert!(game.set_side_to_move(COLORS::BLACK));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+        assert!(!game.set_side_to_move(0));
+        assert!(!game.set_side_to_move(COLORS::WHITE | COLORS::BLACK));
+        assert_eq!(game.turn, COLORS::BLACK);
+        assert!(game.finish_setup().is_ok());
+        assert!(!game.generate_legal_moves().is_empty());
+    }
+
+    #[test]
+    fn set_castling_keeps_the_rights_on_error() {
+        let mut game = kings_and_rook();
+        assert_eq!(game.set_castling("KQ"), Err(PositionProblem::ImpossibleCastling('Q')));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(game.set_castling("-"), Ok(()));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w - - 0 1");
+        assert_eq!(game.set_castling("H"), Ok(()));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+    }
+
+    #[test]
+    fn set_en_passant_needs_a_pawn_that_just_moved_past() {
+        let mut game = kings_and_rook();
+        assert!(game.put_piece(tile("d5"), TYPES::PAWN + COLORS::BLACK));
+        assert!(game.put_piece(tile("d7"), TYPES::PAWN + COLORS::BLACK));
+        // the d-pawn can't have come from d7 with another pawn standing there
+        assert_eq!(game.set_en_passant(Some(tile("d6"))), Err(PositionProblem::BadEnPassant(tile("d6"))));
+        assert!(game.remove_piece(tile("d7")));
+        assert_eq!(game.set_en_passant(Some(tile("e6"))), Err(PositionProblem::BadEnPassant(tile("e6"))));
+        assert_eq!(game.set_en_passant(Some(tile("d3"))), Err(PositionProblem::BadEnPassant(tile("d3"))));
+        assert_eq!(game.set_en_passant(Some(99)), Err(PositionProblem::BadEnPassant(99)));
+
+        assert_eq!(game.set_en_passant(Some(tile("d6"))), Ok(()));
+        assert_eq!(game.get_fen(), "4k3/8/8/3p4/8/8/8/4K2R w K d6 0 1");
+        assert_eq!(game.set_en_passant(Some(tile("e6"))), Err(PositionProblem::BadEnPassant(tile("e6"))));
+        assert_eq!(game.get_fen(), "4k3/8/8/3p4/8/8/8/4K2R w K d6 0 1");
+        // taking the pawn away takes the en passant square with it
+        assert!(game.remove_piece(tile("d5")));
+        assert_eq!(game.get_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+    }
+
+    #[test]
+    fn finish_setup_reports_every_problem() {
+        let mut game = GAME::empty();
+        assert_eq!(game.finish_setup(), Err(vec![PositionProblem::KingCount(COLORS::WHITE, 0), PositionProblem::KingCount(COLORS::BLACK, 0)]));
+        let mut game = kings_and_rook();
+        assert_eq!(game.finish_setup(), Ok(()));
+        assert!(!game.generate_legal_moves().is_empty());
+    }
+}
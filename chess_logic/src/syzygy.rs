@@ -0,0 +1,1562 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::make_move;
+use crate::Move;
+use crate::COLORS;
+use crate::variant::Variant;
+use crate::TYPES;
+use crate::GAME;
+
+// Probing of Syzygy WDL (.rtbw) and DTZ (.rtbz) files. The index computation and the
+// decompression follow the reference probing code by Ronald de Man.
+
+pub const SYZYGY_PATH_VARIABLE: &str = "SYZYGY_PATH";
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+const MAX_PIECES: usize = 7;
+
+// Table flags
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+// Pieces are coded like in the files: white pawn 1 to white king 6, black pieces 8 higher.
+const PAWN_CODE: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,                                           // loss that is a draw by the 50 move rule
+    Draw = 0,
+    CursedWin = 1,                                              // win that is a draw by the 50 move rule
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    fn negate(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Wdl::Loss => "loss",
+            Wdl::BlessedLoss => "blessed loss",
+            Wdl::Draw => "draw",
+            Wdl::CursedWin => "cursed win",
+            Wdl::Win => "win",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProbeState {
+    Ok,
+    ZeroingBestMove,                                            // the best move is a capture or pawn move
+    ChangeSideToMove,                                           // the DTZ table only has the other side to move
+    Fail,
+}
+
+struct Indices {
+    map_pawns: [u64; 64],
+    map_b1h1h7: [u64; 64],
+    map_a1d1d4: [usize; 64],
+    map_kk: [[u64; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+fn rank_of(square: usize) -> usize {
+    square >> 3
+}
+
+fn file_of(square: usize) -> usize {
+    square & 7
+}
+
+fn off_diagonal(square: usize) -> i32 {
+    rank_of(square) as i32 - file_of(square) as i32
+}
+
+fn kings_touch(a: usize, b: usize) -> bool {
+    (rank_of(a) as i32 - rank_of(b) as i32).abs() <= 1 && (file_of(a) as i32 - file_of(b) as i32).abs() <= 1
+}
+
+impl Indices {
+    fn new() -> Indices {
+        let mut indices = Indices {
+            map_pawns: [0; 64],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for square in 0..64 {
+            if off_diagonal(square) < 0 {
+                indices.map_b1h1h7[square] = code;
+                code += 1;
+            }
+        }
+
+        let mut diagonal = Vec::new();
+        let mut code = 0;
+        for square in 0..28 {
+            if off_diagonal(square) < 0 && file_of(square) <= 3 {
+                indices.map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && file_of(square) <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // The 462 ways to place two kings with the first one in the a1-d1-d4 triangle
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for index in 0..10 {
+            for first in 0..28 {
+                if indices.map_a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                for second in 0..64 {
+                    if kings_touch(first, second) || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                        continue;
+                    } else if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        indices.map_kk[index][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            indices.map_kk[index][second] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6 {
+                if k > n {
+                    break;
+                }
+                let with = if k > 0 { indices.binomial[k - 1][n - 1] } else { 0 };
+                let without = if k < n { indices.binomial[k][n - 1] } else { 0 };
+                indices.binomial[k][n] = with + without;
+            }
+        }
+
+        let mut available_squares = 47;
+        for lead_pawns_count in 1..6 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if lead_pawns_count == 1 {
+                        indices.map_pawns[square] = available_squares;
+                        available_squares -= 1;
+                        indices.map_pawns[square ^ 7] = available_squares;
+                        available_squares = available_squares.saturating_sub(1);
+                    }
+                    indices.lead_pawn_idx[lead_pawns_count][square] = index;
+                    index += indices.binomial[lead_pawns_count - 1][indices.map_pawns[square] as usize];
+                }
+                indices.lead_pawns_size[lead_pawns_count][file] = index;
+            }
+        }
+        indices
+    }
+}
+
+#[derive(Clone, Default)]
+struct PairsData {
+    flags: u8,
+    block_size: usize,
+    span: u64,
+    num_blocks: usize,
+    min_sym_len: u8,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    data: usize,
+    base64: Vec<u64>,
+    symlen: Vec<u8>,
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    map_idx: [u16; 4],
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    is_dtz: bool,
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    pawn_count: [usize; 2],                                     // leading color, other color
+    symmetric: bool,                                            // both sides have the same pieces
+    items: Vec<Vec<PairsData>>,                                 // [side][file]
+    map: usize,
+}
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> u16 {
+    match bytes.get(offset..offset + 2) {
+        Some(slice) => u16::from_le_bytes([slice[0], slice[1]]),
+        None => 0,
+    }
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> u32 {
+    match bytes.get(offset..offset + 4) {
+        Some(slice) => u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]),
+        None => 0,
+    }
+}
+
+fn read_u32_be(bytes: &[u8], offset: usize) -> u32 {
+    match bytes.get(offset..offset + 4) {
+        Some(slice) => u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]]),
+        None => 0,
+    }
+}
+
+fn read_byte(bytes: &[u8], offset: usize) -> u8 {
+    bytes.get(offset).copied().unwrap_or(0)
+}
+
+impl Table {
+    // material is a file name without extension like "KRPvKR", white being the first side.
+    fn load(path: &PathBuf, material: &str, is_dtz: bool, indices: &Indices) -> Option<Table> {
+        Table::from_bytes(fs::read(path).ok()?, material, is_dtz, indices)
+    }
+
+    fn from_bytes(bytes: Vec<u8>, material: &str, is_dtz: bool, indices: &Indices) -> Option<Table> {
+        let magic = if is_dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 5 || bytes[0..4] != magic {
+            return None;
+        }
+
+        let mut sides = material.split('v');
+        let white = sides.next()?;
+        let black = sides.next()?;
+        let white_pawns = white.matches('P').count();
+        let black_pawns = black.matches('P').count();
+
+        let mut has_unique_pieces = false;
+        for side in [white, black] {
+            for piece in ['Q', 'R', 'B', 'N', 'P'] {
+                if side.matches(piece).count() == 1 {
+                    has_unique_pieces = true;
+                }
+            }
+        }
+
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads { [white_pawns, black_pawns] } else { [black_pawns, white_pawns] };
+
+        let mut table = Table {
+            bytes,
+            is_dtz,
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+            items: Vec::new(),
+            map: 0,
+        };
+        if table.piece_count > MAX_PIECES {
+            return None;
+        }
+        table.read_header(indices);
+        Some(table)
+    }
+
+    fn sides(&self) -> usize {
+        if !self.is_dtz && !self.symmetric { 2 } else { 1 }
+    }
+
+    fn get(&self, side: usize, file: usize) -> &PairsData {
+        &self.items[side % self.items.len()][if self.has_pawns { file } else { 0 }]
+    }
+
+    fn read_header(&mut self, indices: &Indices) {
+        let sides = self.sides();
+        let max_file = if self.has_pawns { 3 } else { 0 };
+        let pawns_on_both_sides = self.has_pawns && self.pawn_count[1] > 0;
+        self.items = vec![vec![PairsData::default(); max_file + 1]; sides];
+
+        let mut data = 5;                                       // magic and the flags byte
+        for file in 0..=max_file {
+            let first = read_byte(&self.bytes, data);
+            let second = if pawns_on_both_sides { read_byte(&self.bytes, data + 1) } else { 0xff };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            data += 1 + pawns_on_both_sides as usize;
+
+            for k in 0..self.piece_count {
+                let byte = read_byte(&self.bytes, data);
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] = if side == 0 { byte & 0xf } else { byte >> 4 };
+                }
+                data += 1;
+            }
+
+            for (side, side_order) in order.iter().enumerate().take(sides) {
+                let pairs_data = self.set_groups(side, file, *side_order, indices);
+                self.items[side][file] = pairs_data;
+            }
+        }
+        data += data & 1;
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                data = self.set_sizes(side, file, data);
+            }
+        }
+
+        if self.is_dtz {
+            data = self.set_dtz_map(data, max_file);
+        }
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                let pairs_data = &mut self.items[side][file];
+                pairs_data.sparse_index = data;
+                data += pairs_data.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                let pairs_data = &mut self.items[side][file];
+                pairs_data.block_length = data;
+                data += pairs_data.block_length_size * 2;
+            }
+        }
+
+        for file in 0..=max_file {
+            for side in 0..sides {
+                data = (data + 0x3f) & !0x3f;
+                let pairs_data = &mut self.items[side][file];
+                pairs_data.data = data;
+                data += pairs_data.num_blocks * pairs_data.block_size;
+            }
+        }
+    }
+
+    // Pieces of the same type and color are encoded together. The leading group is the
+    // pawns, three unique pieces or the two kings.
+    fn set_groups(&self, side: usize, file: usize, order: [u8; 2], indices: &Indices) -> PairsData {
+        let mut pairs_data = self.items[side][file].clone();
+        let mut first_len: i32 = if self.has_pawns { 0 } else if self.has_unique_pieces { 3 } else { 2 };
+        let mut n = 0;
+        pairs_data.group_len[n] = 1;
+
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || pairs_data.pieces[i] == pairs_data.pieces[i - 1] {
+                pairs_data.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs_data.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs_data.group_len[n] = 0;
+
+        let pawns_on_both_sides = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if pawns_on_both_sides { 2 } else { 1 };
+        let mut free_squares = 64 - pairs_data.group_len[0] - if pawns_on_both_sides { pairs_data.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                pairs_data.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    indices.lead_pawns_size[pairs_data.group_len[0]][file]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                pairs_data.group_idx[1] = idx;
+                idx *= indices.binomial[pairs_data.group_len[1]][48 - pairs_data.group_len[0]];
+            } else {
+                pairs_data.group_idx[next] = idx;
+                idx *= indices.binomial[pairs_data.group_len[next]][free_squares];
+                free_squares -= pairs_data.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs_data.group_idx[n] = idx;
+        pairs_data
+    }
+
+    fn set_sizes(&mut self, side: usize, file: usize, mut data: usize) -> usize {
+        let bytes = &self.bytes;
+        let pairs_data = &mut self.items[side][file];
+
+        pairs_data.flags = read_byte(bytes, data);
+        data += 1;
+        if pairs_data.flags & FLAG_SINGLE_VALUE > 0 {
+            pairs_data.min_sym_len = read_byte(bytes, data);
+            return data + 1;
+        }
+
+        let group_count = pairs_data.group_len.iter().position(|len| *len == 0).unwrap_or(MAX_PIECES);
+        let table_size = pairs_data.group_idx[group_count];
+
+        pairs_data.block_size = 1 << read_byte(bytes, data);
+        pairs_data.span = 1 << read_byte(bytes, data + 1);
+        pairs_data.sparse_index_size = ((table_size + pairs_data.span - 1) / pairs_data.span) as usize;
+        let padding = read_byte(bytes, data + 2) as usize;
+        pairs_data.num_blocks = read_u32_le(bytes, data + 3) as usize;
+        pairs_data.block_length_size = pairs_data.num_blocks + padding;
+        let max_sym_len = read_byte(bytes, data + 7);
+        pairs_data.min_sym_len = read_byte(bytes, data + 8);
+        data += 9;
+        pairs_data.lowest_sym = data;
+
+        // Canonical Huffman code: base64[l] is the lowest code of length l + min_sym_len,
+        // padded to 64 bits.
+        let lengths = (max_sym_len as usize + 1).saturating_sub(pairs_data.min_sym_len as usize);
+        let mut base64 = vec![0u64; lengths];
+        for i in (0..lengths.saturating_sub(1)).rev() {
+            let lowest = read_u16_le(bytes, pairs_data.lowest_sym + 2 * i) as u64;
+            let next_lowest = read_u16_le(bytes, pairs_data.lowest_sym + 2 * (i + 1)) as u64;
+            base64[i] = (base64[i + 1] + lowest - next_lowest) / 2;
+        }
+        for (i, base) in base64.iter_mut().enumerate() {
+            let shift = 64 - i - pairs_data.min_sym_len as usize;
+            *base = if shift >= 64 { 0 } else { *base << shift };
+        }
+        pairs_data.base64 = base64;
+        data += lengths * 2;
+
+        let symbol_count = read_u16_le(bytes, data) as usize;
+        data += 2;
+        pairs_data.btree = data;
+        pairs_data.symlen = vec![0; symbol_count];
+
+        let mut visited = vec![false; symbol_count];
+        for symbol in 0..symbol_count {
+            if !visited[symbol] {
+                let length = set_symlen(bytes, pairs_data, symbol, &mut visited);
+                pairs_data.symlen[symbol] = length;
+            }
+        }
+
+        data + symbol_count * 3 + (symbol_count & 1)
+    }
+
+    fn set_dtz_map(&mut self, mut data: usize, max_file: usize) -> usize {
+        self.map = data;
+        for file in 0..=max_file {
+            let flags = self.items[0][file].flags;
+            if flags & FLAG_MAPPED == 0 {
+                continue;
+            }
+            if flags & FLAG_WIDE > 0 {
+                data += data & 1;
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = ((data - self.map) / 2 + 1) as u16;
+                    data += 2 * read_u16_le(&self.bytes, data) as usize + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = (data - self.map + 1) as u16;
+                    data += read_byte(&self.bytes, data) as usize + 1;
+                }
+            }
+        }
+        data + (data & 1)
+    }
+
+    fn left_symbol(&self, pairs_data: &PairsData, symbol: usize) -> usize {
+        left_symbol(&self.bytes, pairs_data.btree, symbol)
+    }
+
+    fn decompress_pairs(&self, pairs_data: &PairsData, idx: u64) -> i32 {
+        if pairs_data.flags & FLAG_SINGLE_VALUE > 0 {
+            return pairs_data.min_sym_len as i32;
+        }
+        let bytes = &self.bytes;
+
+        // The sparse index points at a block and an offset in it about every span values.
+        let k = (idx / pairs_data.span) as usize;
+        let mut block = read_u32_le(bytes, pairs_data.sparse_index + 6 * k) as i64;
+        let mut offset = read_u16_le(bytes, pairs_data.sparse_index + 6 * k + 4) as i64;
+        offset += (idx % pairs_data.span) as i64 - (pairs_data.span / 2) as i64;
+
+        let block_length = |block: i64| read_u16_le(bytes, pairs_data.block_length + 2 * block as usize) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        let mut pointer = pairs_data.data + block as usize * pairs_data.block_size;
+        let mut buffer = ((read_u32_be(bytes, pointer) as u64) << 32) | read_u32_be(bytes, pointer + 4) as u64;
+        pointer += 8;
+        let mut buffer_size = 64;
+        let min_sym_len = pairs_data.min_sym_len as usize;
+
+        let mut symbol;
+        loop {
+            let mut length = 0;
+            while length + 1 < pairs_data.base64.len() && buffer < pairs_data.base64[length] {
+                length += 1;
+            }
+            symbol = (buffer - pairs_data.base64[length]).checked_shr((64 - length - min_sym_len) as u32).unwrap_or(0) as usize;
+            symbol += read_u16_le(bytes, pairs_data.lowest_sym + 2 * length) as usize;
+            if symbol >= pairs_data.symlen.len() {
+                return 0;
+            }
+
+            if offset < pairs_data.symlen[symbol] as i64 + 1 {
+                break;
+            }
+            offset -= pairs_data.symlen[symbol] as i64 + 1;
+            length += min_sym_len;
+            buffer = buffer.checked_shl(length as u32).unwrap_or(0);
+            buffer_size -= length as i32;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (read_u32_be(bytes, pointer) as u64) << (64 - buffer_size);
+                pointer += 4;
+            }
+        }
+
+        // Every symbol expands to a pair of symbols, walk down to the value we want.
+        while pairs_data.symlen[symbol] > 0 {
+            let left = self.left_symbol(pairs_data, symbol);
+            if offset < pairs_data.symlen[left] as i64 + 1 {
+                symbol = left;
+            } else {
+                offset -= pairs_data.symlen[left] as i64 + 1;
+                symbol = right_symbol(bytes, pairs_data.btree, symbol);
+            }
+        }
+        self.left_symbol(pairs_data, symbol) as i32
+    }
+
+    fn map_score(&self, file: usize, value: i32, wdl: Wdl) -> i32 {
+        if !self.is_dtz {
+            return value - 2;
+        }
+        let wdl_map = [1, 3, 0, 2, 0];
+        let pairs_data = self.get(0, file);
+        let flags = pairs_data.flags;
+        let mut value = value as usize;
+
+        if flags & FLAG_MAPPED > 0 {
+            let index = pairs_data.map_idx[wdl_map[(wdl as i32 + 2) as usize]] as usize + value;
+            value = if flags & FLAG_WIDE > 0 {
+                read_u16_le(&self.bytes, self.map + 2 * index) as usize
+            } else {
+                read_byte(&self.bytes, self.map + index) as usize
+            };
+        }
+
+        // DTZ is stored in moves or plies, we always return plies.
+        if (wdl == Wdl::Win && flags & FLAG_WIN_PLIES == 0) ||
+            (wdl == Wdl::Loss && flags & FLAG_LOSS_PLIES == 0) ||
+            wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+        value as i32 + 1
+    }
+
+    fn probe(&self, position: &TablebasePosition, black_stronger: bool, wdl: Wdl, indices: &Indices) -> (i32, ProbeState) {
+        let (side_to_move, table_file, idx) = match self.encode(position, black_stronger, indices) {
+            Some(encoded) => encoded,
+            None => return (0, ProbeState::Fail),
+        };
+
+        if self.is_dtz {
+            let flags = self.get(side_to_move, table_file).flags;
+            if (self.has_pawns || !self.symmetric) && (flags & FLAG_STM) as usize != side_to_move {
+                return (0, ProbeState::ChangeSideToMove);
+            }
+        }
+
+        let value = self.decompress_pairs(self.get(side_to_move, table_file), idx);
+        (self.map_score(table_file, value, wdl), ProbeState::Ok)
+    }
+
+    // The side to move and file of the table part a position is in and its index there.
+    fn encode(&self, position: &TablebasePosition, black_stronger: bool, indices: &Indices) -> Option<(usize, usize, u64)> {
+        let symmetric_black_to_move = self.symmetric && !position.white_to_move;
+        let flip = symmetric_black_to_move || black_stronger;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let side_to_move = (flip as usize) ^ (!position.white_to_move as usize);
+
+        let mut squares = Vec::with_capacity(MAX_PIECES);
+        let mut pieces = Vec::with_capacity(MAX_PIECES);
+        let mut lead_pawns_count = 0;
+        let mut table_file = 0;
+        let mut lead_pawn = 0;
+
+        if self.has_pawns {
+            lead_pawn = self.get(0, 0).pieces[0] ^ flip_color;
+            for (square, piece) in &position.pieces {
+                if *piece == lead_pawn {
+                    squares.push(square ^ flip_squares);
+                    pieces.push(piece ^ flip_color);
+                }
+            }
+            lead_pawns_count = squares.len();
+            let mut lead_index = 0;
+            for i in 1..lead_pawns_count {
+                if indices.map_pawns[squares[i]] > indices.map_pawns[squares[lead_index]] {
+                    lead_index = i;
+                }
+            }
+            squares.swap(0, lead_index);
+            table_file = file_of(squares[0]).min(7 - file_of(squares[0]));
+        }
+
+        for (square, piece) in &position.pieces {
+            if self.has_pawns && *piece == lead_pawn {
+                continue;
+            }
+            squares.push(square ^ flip_squares);
+            pieces.push(piece ^ flip_color);
+        }
+        let size = squares.len();
+        if size != self.piece_count {
+            return None;
+        }
+
+        let pairs_data = self.get(side_to_move, table_file);
+
+        // Put the pieces in the order the table was encoded with.
+        for i in lead_pawns_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if pairs_data.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // Mirror so that the leading piece is on the a-d files.
+        if file_of(squares[0]) > 3 {
+            for square in squares.iter_mut() {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if self.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawns_count][squares[0]];
+            squares[1..lead_pawns_count].sort_by_key(|square| indices.map_pawns[*square]);
+            for (i, square) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[*square] as usize];
+            }
+        } else {
+            if rank_of(squares[0]) > 3 {
+                for square in squares.iter_mut() {
+                    *square ^= 56;
+                }
+            }
+
+            for i in 0..pairs_data.group_len[0] {
+                if off_diagonal(squares[i]) == 0 {
+                    continue;
+                }
+                if off_diagonal(squares[i]) > 0 {
+                    for square in squares[i..].iter_mut() {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if self.has_unique_pieces {
+                let adjust1 = (squares[1] > squares[0]) as u64;
+                let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+                let (s0, s1, s2) = (squares[0], squares[1] as u64, squares[2] as u64);
+
+                idx = if off_diagonal(s0) != 0 {
+                    (indices.map_a1d1d4[s0] as u64 * 63 + (s1 - adjust1)) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[1]) != 0 {
+                    (6 * 63 + rank_of(s0) as u64 * 28 + indices.map_b1h1h7[squares[1]]) * 62 + s2 - adjust2
+                } else if off_diagonal(squares[2]) != 0 {
+                    6 * 63 * 62 + 4 * 28 * 62 +
+                        rank_of(s0) as u64 * 7 * 28 +
+                        (rank_of(squares[1]) as u64 - adjust1) * 28 +
+                        indices.map_b1h1h7[squares[2]]
+                } else {
+                    6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 +
+                        rank_of(s0) as u64 * 7 * 6 +
+                        (rank_of(squares[1]) as u64 - adjust1) * 6 +
+                        (rank_of(squares[2]) as u64 - adjust2)
+                };
+            } else {
+                idx = indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        idx *= pairs_data.group_idx[0];
+        let mut group_start = pairs_data.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+
+        while pairs_data.group_len[next] > 0 {
+            let group_end = group_start + pairs_data.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+            let mut n = 0;
+            for i in 0..pairs_data.group_len[next] {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|other| square > **other).count();
+                let pawn_adjust = if remaining_pawns { 8 } else { 0 };
+                n += indices.binomial[i + 1][square - adjust - pawn_adjust];
+            }
+            remaining_pawns = false;
+            idx += n * pairs_data.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        Some((side_to_move, table_file, idx))
+    }
+}
+
+fn left_symbol(bytes: &[u8], btree: usize, symbol: usize) -> usize {
+    let offset = btree + 3 * symbol;
+    (((read_byte(bytes, offset + 1) & 0xf) as usize) << 8) | read_byte(bytes, offset) as usize
+}
+
+fn right_symbol(bytes: &[u8], btree: usize, symbol: usize) -> usize {
+    let offset = btree + 3 * symbol;
+    ((read_byte(bytes, offset + 2) as usize) << 4) | (read_byte(bytes, offset + 1) >> 4) as usize
+}
+
+// Number of values a symbol stands for, minus one.
+fn set_symlen(bytes: &[u8], pairs_data: &mut PairsData, symbol: usize, visited: &mut Vec<bool>) -> u8 {
+    visited[symbol] = true;
+    let right = right_symbol(bytes, pairs_data.btree, symbol);
+    if right == 0xfff {
+        return 0;
+    }
+    let left = left_symbol(bytes, pairs_data.btree, symbol);
+    if left >= visited.len() || right >= visited.len() {
+        return 0;
+    }
+    if !visited[left] {
+        let length = set_symlen(bytes, pairs_data, left, visited);
+        pairs_data.symlen[left] = length;
+    }
+    if !visited[right] {
+        let length = set_symlen(bytes, pairs_data, right, visited);
+        pairs_data.symlen[right] = length;
+    }
+    pairs_data.symlen[left].wrapping_add(pairs_data.symlen[right]).wrapping_add(1)
+}
+
+// Pieces by square numbered from a1 like in the files.
+struct TablebasePosition {
+    pieces: Vec<(usize, u8)>,
+    white_to_move: bool,
+}
+
+fn piece_code(piece: u8) -> u8 {
+    let code = if piece & TYPES::PAWN > 0 {
+        PAWN_CODE
+    } else if piece & TYPES::KNIGHT > 0 {
+        2
+    } else if piece & TYPES::BISHOP > 0 {
+        3
+    } else if piece & TYPES::ROOK > 0 {
+        4
+    } else if piece & TYPES::QUEEN > 0 {
+        5
+    } else {
+        6
+    };
+    if piece & COLORS::BLACK > 0 { code + 8 } else { code }
+}
+
+impl TablebasePosition {
+    fn from_game(game: &GAME) -> TablebasePosition {
+        let mut pieces = Vec::new();
+        for square in 0..64 {
+            let tile = (7 - square / 8) * 8 + square % 8;
+            if game.board[tile] != TYPES::NONE {
+                pieces.push((square, piece_code(game.board[tile])));
+            }
+        }
+        TablebasePosition { pieces, white_to_move: game.turn == COLORS::WHITE }
+    }
+}
+
+// Material of one side in file name order, like "KRP".
+fn material_of(game: &GAME, color: u8) -> String {
+    let mut material = String::new();
+    for (piece_type, symbol) in [(TYPES::KING, 'K'), (TYPES::QUEEN, 'Q'), (TYPES::ROOK, 'R'),
+                                 (TYPES::BISHOP, 'B'), (TYPES::KNIGHT, 'N'), (TYPES::PAWN, 'P')] {
+        for piece in game.board.iter() {
+            if piece & color > 0 && piece & piece_type > 0 {
+                material.push(symbol);
+            }
+        }
+    }
+    material
+}
+
+fn is_zeroing(game: &GAME, chess_move: &Move) -> bool {
+    game.is_capture(chess_move) || game.board[chess_move.from] & TYPES::PAWN > 0
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+fn sign_of(value: i32) -> i32 {
+    value.signum()
+}
+
+pub struct Tablebase {
+    directory: PathBuf,
+    max_pieces: usize,
+    indices: Indices,
+    wdl_tables: RefCell<HashMap<String, Option<Rc<Table>>>>,
+    dtz_tables: RefCell<HashMap<String, Option<Rc<Table>>>>,
+}
+
+// The tablebase directory can be set with the SYZYGY_PATH environment variable.
+pub fn syzygy_path() -> Option<String> {
+    env::var(SYZYGY_PATH_VARIABLE).ok()
+}
+
+impl Tablebase {
+    pub fn open(directory: &str) -> io::Result<Tablebase> {
+        let mut max_pieces = 0;
+        for entry in fs::read_dir(directory)? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(material) = name.strip_suffix(".rtbw") {
+                let piece_count = material.chars().filter(|c| *c != 'v').count();
+                if piece_count <= MAX_PIECES && piece_count > max_pieces {
+                    max_pieces = piece_count;
+                }
+            }
+        }
+        Ok(Tablebase {
+            directory: PathBuf::from(directory),
+            max_pieces,
+            indices: Indices::new(),
+            wdl_tables: RefCell::new(HashMap::new()),
+            dtz_tables: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn get_max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Tables only exist for positions without castling rights and with few enough pieces.
+    pub fn can_probe(&self, game: &GAME) -> bool {
+        let piece_count = game.board.iter().filter(|piece| **piece != TYPES::NONE).count();
+        game.variant == Variant::Standard && piece_count <= self.max_pieces && !game.chastling_ability.iter().any(|can_chastle| *can_chastle)
+    }
+
+    fn table(&self, game: &GAME, is_dtz: bool) -> Option<(Rc<Table>, bool)> {
+        let white = material_of(game, COLORS::WHITE);
+        let black = material_of(game, COLORS::BLACK);
+        let cache = if is_dtz { &self.dtz_tables } else { &self.wdl_tables };
+        let extension = if is_dtz { "rtbz" } else { "rtbw" };
+
+        for (material, black_stronger) in [(format!("{}v{}", white, black), false), (format!("{}v{}", black, white), true)] {
+            if let Some(table) = cache.borrow().get(&material) {
+                match table {
+                    Some(table) => return Some((table.clone(), black_stronger)),
+                    None => continue,
+                }
+            }
+            let path = self.directory.join(format!("{}.{}", material, extension));
+            let table = Table::load(&path, &material, is_dtz, &self.indices).map(Rc::new);
+            cache.borrow_mut().insert(material, table.clone());
+            if let Some(table) = table {
+                return Some((table, black_stronger));
+            }
+        }
+        None
+    }
+
+    fn probe_table(&self, game: &GAME, is_dtz: bool, wdl: Wdl) -> (i32, ProbeState) {
+        let piece_count = game.board.iter().filter(|piece| **piece != TYPES::NONE).count();
+        if piece_count == 2 {
+            return (0, ProbeState::Ok);                         // king against king
+        }
+        match self.table(game, is_dtz) {
+            Some((table, black_stronger)) => table.probe(&TablebasePosition::from_game(game), black_stronger, wdl, &self.indices),
+            None => (0, ProbeState::Fail),
+        }
+    }
+
+    // Tables store "don't care" values where the side to move has a winning capture, so the
+    // captures (and for DTZ the pawn moves) have to be searched as well.
+    fn search(&self, game: &GAME, check_zeroing_moves: bool) -> (Wdl, ProbeState) {
+        let legal_moves = game.generate_legal_moves();
+        let mut best_value = Wdl::Loss;
+        let mut move_count = 0;
+
+        for chess_move in &legal_moves {
+            if !game.is_capture(chess_move) && (!check_zeroing_moves || game.board[chess_move.from] & TYPES::PAWN == 0) {
+                continue;
+            }
+            move_count += 1;
+
+            let mut virtual_game = game.clone();
+            make_move(chess_move, &mut virtual_game);
+            let (value, state) = self.search(&virtual_game, false);
+            if state == ProbeState::Fail {
+                return (Wdl::Draw, ProbeState::Fail);
+            }
+            let value = value.negate();
+            if value > best_value {
+                best_value = value;
+                if value >= Wdl::Win {
+                    return (value, ProbeState::ZeroingBestMove);
+                }
+            }
+        }
+
+        let no_more_moves = move_count > 0 && move_count == legal_moves.len();
+        let value = if no_more_moves {
+            best_value
+        } else {
+            let (value, state) = self.probe_table(game, false, Wdl::Draw);
+            if state == ProbeState::Fail {
+                return (Wdl::Draw, ProbeState::Fail);
+            }
+            Wdl::from_value(value)
+        };
+
+        if best_value >= value {
+            let state = if best_value > Wdl::Draw || no_more_moves { ProbeState::ZeroingBestMove } else { ProbeState::Ok };
+            return (best_value, state);
+        }
+        (value, ProbeState::Ok)
+    }
+
+    pub fn probe_wdl(&self, game: &GAME) -> Option<Wdl> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        match self.search(game, false) {
+            (_, ProbeState::Fail) => None,
+            (wdl, _) => Some(wdl),
+        }
+    }
+
+    // Distance to the next capture or pawn move in plies, positive when winning and zero for draws.
+    pub fn probe_dtz(&self, game: &GAME) -> Option<i32> {
+        if !self.can_probe(game) {
+            return None;
+        }
+        self.dtz(game)
+    }
+
+    fn dtz(&self, game: &GAME) -> Option<i32> {
+        let (wdl, state) = self.search(game, true);
+        if state == ProbeState::Fail {
+            return None;
+        }
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == ProbeState::ZeroingBestMove {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        let (dtz, state) = self.probe_table(game, true, wdl);
+        match state {
+            ProbeState::Fail => return None,
+            ProbeState::ChangeSideToMove => {}
+            _ => {
+                let cursed = (wdl == Wdl::BlessedLoss || wdl == Wdl::CursedWin) as i32;
+                return Some((dtz + 100 * cursed) * sign_of(wdl as i32));
+            }
+        }
+
+        // The table only has the other side to move, so look one ply ahead.
+        let mut min_dtz = 0xffff;
+        for chess_move in game.generate_legal_moves() {
+            let zeroing = is_zeroing(game, &chess_move);
+            let mut virtual_game = game.clone();
+            make_move(&chess_move, &mut virtual_game);
+
+            let mut dtz = if zeroing {
+                match self.search(&virtual_game, false) {
+                    (_, ProbeState::Fail) => return None,
+                    (value, _) => -dtz_before_zeroing(value),
+                }
+            } else {
+                -self.dtz(&virtual_game)?
+            };
+
+            if dtz == 1 && virtual_game.check && virtual_game.generate_legal_moves().is_empty() {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += sign_of(dtz);
+            }
+            if dtz < min_dtz && sign_of(dtz) == sign_of(wdl as i32) {
+                min_dtz = dtz;
+            }
+        }
+        Some(if min_dtz == 0xffff { -1 } else { min_dtz })
+    }
+
+    // DTZ of the position before a move, as the root ranking needs it. Captures and pawn
+    // moves reset the counter so their DTZ comes from the WDL after the move.
+    pub fn probe_dtz_after_move(&self, game: &GAME, chess_move: &Move) -> Option<i32> {
+        let mut virtual_game = game.clone();
+        if !make_move(chess_move, &mut virtual_game) || !self.can_probe(&virtual_game) {
+            return None;
+        }
+        if is_zeroing(game, chess_move) {
+            let wdl = self.probe_wdl(&virtual_game)?;
+            return Some(-dtz_before_zeroing(wdl));
+        }
+        let dtz = -self.dtz(&virtual_game)?;
+        if dtz == 1 && virtual_game.check && virtual_game.generate_legal_moves().is_empty() {
+            return Some(1);                                     // mating move
+        }
+        Some(dtz + sign_of(dtz))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endgame::probe_kpk;
+    use crate::engine::{Engine, TABLEBASE_WIN_SCORE};
+    use crate::init_game_from_fen;
+    use crate::pgn::san_to_move;
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use std::process;
+    use std::sync::OnceLock;
+
+    // The real tables can't be checked in, so the tests write KQvK, KRvK and KPvK WDL tables and
+    // KQvK and KRvK DTZ tables in the same format. Every value is stored as a code of the same
+    // length without any pairs.
+    static FIXTURES: OnceLock<Vec<(String, Vec<u8>)>> = OnceLock::new();
+    static MATE_DISTANCES: OnceLock<[Vec<Option<u16>>; 2]> = OnceLock::new();
+
+    const QUEEN_CODE: u8 = 5;
+    const ROOK_CODE: u8 = 4;
+    const WHITE_KING_CODE: u8 = 6;
+    const BLACK_KING_CODE: u8 = 14;
+    const BLOCK_SIZE: usize = 64;                               // bytes
+    const SPAN: usize = 64;
+
+    struct Layout {
+        bits: usize,
+        block_values: usize,
+    }
+
+    impl Layout {
+        fn block_count(&self, values: &[u16]) -> usize {
+            (values.len() + SPAN) / self.block_values + 1
+        }
+    }
+
+    // values[file][side] by index, a table part without values has a single value.
+    fn table_bytes(is_dtz: bool, has_pawns: bool, pieces: &[u8], flags: u8, values: &[Vec<Vec<u16>>]) -> Vec<u8> {
+        let symbol_count = values.iter().flatten().flatten().max().map_or(2, |max| *max as usize + 1).max(2);
+        let bits = (usize::BITS - (symbol_count - 1).leading_zeros()) as usize;
+        let layout = Layout { bits, block_values: BLOCK_SIZE * 8 / bits };
+
+        let mut bytes = if is_dtz { DTZ_MAGIC.to_vec() } else { WDL_MAGIC.to_vec() };
+        bytes.push(!is_dtz as u8 | (has_pawns as u8) << 1);
+        for _ in values {
+            bytes.push(0);                                      // the leading group comes first
+            bytes.extend(pieces.iter().map(|piece| piece | piece << 4));
+        }
+        bytes.resize(bytes.len() + bytes.len() % 2, 0);
+
+        for side_values in values.iter().flatten() {
+            if side_values.is_empty() {
+                bytes.extend([FLAG_SINGLE_VALUE | flags, 0]);
+                continue;
+            }
+            bytes.extend([flags, BLOCK_SIZE.trailing_zeros() as u8, SPAN.trailing_zeros() as u8, 0]);
+            bytes.extend((layout.block_count(side_values) as u32).to_le_bytes());
+            bytes.extend([bits as u8, bits as u8]);
+            bytes.extend(0u16.to_le_bytes());
+            bytes.extend((symbol_count as u16).to_le_bytes());
+            for value in 0..symbol_count {
+                bytes.extend([value as u8, 0xf0 | (value >> 8) as u8, 0xff]);
+            }
+            bytes.resize(bytes.len() + symbol_count % 2, 0);
+        }
+        if is_dtz {
+            bytes.resize(bytes.len() + bytes.len() % 2, 0);     // an empty map
+        }
+
+        for side_values in values.iter().flatten() {
+            for k in 0..(side_values.len() + SPAN - 1) / SPAN {
+                let position = k * SPAN + SPAN / 2;
+                bytes.extend(((position / layout.block_values) as u32).to_le_bytes());
+                bytes.extend(((position % layout.block_values) as u16).to_le_bytes());
+            }
+        }
+        for side_values in values.iter().flatten().filter(|side_values| !side_values.is_empty()) {
+            for _ in 0..layout.block_count(side_values) {
+                bytes.extend((layout.block_values as u16 - 1).to_le_bytes());
+            }
+        }
+
+        for side_values in values.iter().flatten() {
+            bytes.resize((bytes.len() + 0x3f) & !0x3f, 0);
+            if side_values.is_empty() {
+                continue;
+            }
+            let mut padded = side_values.clone();
+            padded.resize(layout.block_count(side_values) * layout.block_values, 0);
+            for block in padded.chunks(layout.block_values) {
+                let mut block_bytes = [0u8; BLOCK_SIZE];
+                for (i, value) in block.iter().enumerate() {
+                    for bit in 0..layout.bits {
+                        if (value >> (layout.bits - 1 - bit)) & 1 > 0 {
+                            let position = layout.bits * i + bit;
+                            block_bytes[position / 8] |= 0x80 >> (position % 8);
+                        }
+                    }
+                }
+                bytes.extend(block_bytes);
+            }
+        }
+        bytes
+    }
+
+    fn empty_table(material: &str, is_dtz: bool, pieces: &[u8], indices: &Indices) -> Table {
+        let has_pawns = pieces.contains(&PAWN_CODE);
+        let sides = if is_dtz { 1 } else { 2 };
+        let empty = vec![vec![Vec::new(); sides]; if has_pawns { 4 } else { 1 }];
+        Table::from_bytes(table_bytes(is_dtz, has_pawns, pieces, 0, &empty), material, is_dtz, indices).unwrap()
+    }
+
+    // The indices come from a table with the same pieces, so they are the ones probing uses.
+    // Values of positions that aren't given are default.
+    fn fixture(material: &str, is_dtz: bool, pieces: &[u8], flags: u8, default: u16, positions: &[(TablebasePosition, u16)]) -> Vec<u8> {
+        let indices = Indices::new();
+        let table = empty_table(material, is_dtz, pieces, &indices);
+        let mut values: Vec<Vec<Vec<u16>>> = table.items.iter().enumerate().map(|(side, files)| (0..files.len()).map(|file| {
+            let pairs_data = table.get(side, file);
+            let group_count = pairs_data.group_len.iter().position(|len| *len == 0).unwrap();
+            vec![default; pairs_data.group_idx[group_count] as usize]
+        }).collect::<Vec<_>>()).collect();
+        let sides = values.len();
+        for (position, value) in positions {
+            let (side, file, idx) = table.encode(position, false, &indices).unwrap();
+            values[side % sides][file][idx as usize] = *value;
+        }
+        let by_file: Vec<Vec<Vec<u16>>> = (0..values[0].len()).map(|file| values.iter().map(|sides| sides[file].clone()).collect()).collect();
+        table_bytes(is_dtz, table.has_pawns, pieces, flags, &by_file)
+    }
+
+    fn slides(piece: u8, from: usize, to: usize, blockers: &[usize]) -> bool {
+        let rank_distance = rank_of(to) as i32 - rank_of(from) as i32;
+        let file_distance = file_of(to) as i32 - file_of(from) as i32;
+        let straight = rank_distance == 0 || file_distance == 0;
+        let diagonal = rank_distance.abs() == file_distance.abs();
+        if from == to || !(straight || (piece == QUEEN_CODE && diagonal)) {
+            return false;
+        }
+        let step = rank_distance.signum() * 8 + file_distance.signum();
+        let mut square = from as i32 + step;
+        while square != to as i32 {
+            if blockers.contains(&(square as usize)) {
+                return false;
+            }
+            square += step;
+        }
+        true
+    }
+
+    fn pawn_attacks(pawn: usize, square: usize) -> bool {
+        rank_of(square) == rank_of(pawn) + 1 && (file_of(square) as i32 - file_of(pawn) as i32).abs() == 1
+    }
+
+    // The squares the lone king can go to, taking the piece isn't one of them.
+    fn black_moves(piece: u8, square: usize, white_king: usize, black_king: usize) -> Vec<usize> {
+        (0..64).filter(|to| *to != black_king && *to != square && kings_touch(black_king, *to) &&
+            !kings_touch(white_king, *to) && !slides(piece, square, *to, &[white_king])).collect()
+    }
+
+    // With a queen or a rook every position with white to move is won, the lone king only draws
+    // by stalemate or by taking the piece.
+    fn kxk_value(piece: u8, square: usize, white_king: usize, black_king: usize, white_to_move: bool) -> Wdl {
+        if white_to_move {
+            return Wdl::Win;
+        }
+        let takes_piece = kings_touch(black_king, square) && !kings_touch(white_king, square);
+        let in_check = slides(piece, square, black_king, &[white_king]);
+        if takes_piece || (!in_check && black_moves(piece, square, white_king, black_king).is_empty()) { Wdl::Draw } else { Wdl::Loss }
+    }
+
+    fn kpk_value(pawn: usize, white_king: usize, black_king: usize, white_to_move: bool) -> Wdl {
+        let mirror = if file_of(pawn) > 3 { 7 } else { 0 };
+        match (probe_kpk(white_king ^ mirror, pawn ^ mirror, black_king ^ mirror, white_to_move), white_to_move) {
+            (true, true) => Wdl::Win,
+            (true, false) => Wdl::Loss,
+            (false, _) => Wdl::Draw,
+        }
+    }
+
+    fn black_is_checked(piece: u8, square: usize, white_king: usize, black_king: usize) -> bool {
+        if piece == PAWN_CODE { pawn_attacks(square, black_king) } else { slides(piece, square, black_king, &[white_king]) }
+    }
+
+    fn position(white_king: usize, black_king: usize, square: usize, piece: u8, white_to_move: bool) -> TablebasePosition {
+        TablebasePosition { pieces: vec![(white_king, WHITE_KING_CODE), (black_king, BLACK_KING_CODE), (square, piece)], white_to_move }
+    }
+
+    // Squares of the white king, the black king and the piece, with white to move or not.
+    fn legal_positions(piece: u8) -> Vec<(usize, usize, usize, bool)> {
+        let squares = if piece == PAWN_CODE { 8..56 } else { 0..64 };
+        let mut positions = Vec::new();
+        for square in squares {
+            for white_king in 0..64 {
+                for black_king in 0..64 {
+                    if kings_touch(white_king, black_king) || square == white_king || square == black_king {
+                        continue;
+                    }
+                    if !black_is_checked(piece, square, white_king, black_king) {
+                        positions.push((white_king, black_king, square, true));
+                    }
+                    positions.push((white_king, black_king, square, false));
+                }
+            }
+        }
+        positions
+    }
+
+    fn index(white_king: usize, black_king: usize, square: usize) -> usize {
+        (white_king * 64 + black_king) * 64 + square
+    }
+
+    fn mate_distances(piece: u8) -> &'static Vec<Option<u16>> {
+        let distances = MATE_DISTANCES.get_or_init(|| [solve_mates(QUEEN_CODE), solve_mates(ROOK_CODE)]);
+        &distances[(piece == ROOK_CODE) as usize]
+    }
+
+    // Plies to mate with white to move in KQvK and KRvK, found backwards from the mates.
+    fn solve_mates(piece: u8) -> Vec<Option<u16>> {
+        let mut white_to_move = vec![None; 64 * 64 * 64];
+        let mut black_to_move = vec![None; 64 * 64 * 64];
+        let mut queue = VecDeque::new();
+        for (white_king, black_king, square, white_moves) in legal_positions(piece) {
+            if !white_moves && kxk_value(piece, square, white_king, black_king, false) == Wdl::Loss &&
+                black_moves(piece, square, white_king, black_king).is_empty() {
+                black_to_move[index(white_king, black_king, square)] = Some(0);
+                queue.push_back((white_king, black_king, square, false));
+            }
+        }
+
+        while let Some((white_king, black_king, square, white_moves)) = queue.pop_front() {
+            if white_moves {
+                let distance = white_to_move[index(white_king, black_king, square)].unwrap() + 1;
+                for from in 0..64 {
+                    if from == white_king || from == square || !kings_touch(from, black_king) || kings_touch(from, white_king) {
+                        continue;
+                    }
+                    let previous = index(white_king, from, square);
+                    if black_to_move[previous].is_none() && kxk_value(piece, square, white_king, from, false) == Wdl::Loss &&
+                        black_moves(piece, square, white_king, from).iter().all(|to| white_to_move[index(white_king, *to, square)].is_some()) {
+                        black_to_move[previous] = Some(distance);
+                        queue.push_back((white_king, from, square, false));
+                    }
+                }
+            } else {
+                let distance = black_to_move[index(white_king, black_king, square)].unwrap() + 1;
+                let king_moves = (0..64).filter(|from| *from != white_king && *from != square && kings_touch(*from, white_king) &&
+                    !kings_touch(*from, black_king)).map(|from| (from, square));
+                let piece_moves = (0..64).filter(|from| *from != black_king && slides(piece, *from, square, &[white_king, black_king]))
+                    .map(|from| (white_king, from));
+                for (from_king, from_square) in king_moves.chain(piece_moves).collect::<Vec<_>>() {
+                    let previous = index(from_king, black_king, from_square);
+                    if white_to_move[previous].is_none() && !black_is_checked(piece, from_square, from_king, black_king) {
+                        white_to_move[previous] = Some(distance);
+                        queue.push_back((from_king, black_king, from_square, true));
+                    }
+                }
+            }
+        }
+        white_to_move
+    }
+
+    fn fixtures() -> &'static Vec<(String, Vec<u8>)> {
+        FIXTURES.get_or_init(|| {
+            let mut tables = Vec::new();
+            for (material, piece) in [("KQvK", QUEEN_CODE), ("KRvK", ROOK_CODE), ("KPvK", PAWN_CODE)] {
+                let pieces = [piece, WHITE_KING_CODE, BLACK_KING_CODE];
+                let wdl_positions: Vec<(TablebasePosition, u16)> = legal_positions(piece).into_iter().map(|(white_king, black_king, square, white_to_move)| {
+                    let wdl = if piece == PAWN_CODE {
+                        kpk_value(square, white_king, black_king, white_to_move)
+                    } else {
+                        kxk_value(piece, square, white_king, black_king, white_to_move)
+                    };
+                    (position(white_king, black_king, square, piece, white_to_move), (wdl as i32 + 2) as u16)
+                }).collect();
+                tables.push((format!("{}.rtbw", material), fixture(material, false, &pieces, 0, 2, &wdl_positions)));
+                if piece == PAWN_CODE {
+                    continue;
+                }
+
+                // DTZ in plies for white to move, stored one less
+                let distances = mate_distances(piece);
+                let dtz_positions: Vec<(TablebasePosition, u16)> = legal_positions(piece).into_iter()
+                    .filter(|(_, _, _, white_to_move)| *white_to_move)
+                    .map(|(white_king, black_king, square, _)| {
+                        let distance = distances[index(white_king, black_king, square)].expect("white always wins");
+                        (position(white_king, black_king, square, piece, true), distance - 1)
+                    }).collect();
+                tables.push((format!("{}.rtbz", material), fixture(material, true, &pieces, FLAG_WIN_PLIES | FLAG_LOSS_PLIES, 0, &dtz_positions)));
+            }
+            tables
+        })
+    }
+
+    // A directory with the fixtures that is removed again when the test is done.
+    struct FixtureDirectory(PathBuf);
+
+    impl FixtureDirectory {
+        fn new(test: &str) -> FixtureDirectory {
+            let directory = env::temp_dir().join(format!("chess_logic_syzygy_{}_{}", test, process::id()));
+            fs::create_dir_all(&directory).unwrap();
+            for (name, bytes) in fixtures() {
+                fs::write(directory.join(name), bytes).unwrap();
+            }
+            FixtureDirectory(directory)
+        }
+
+        fn open(&self) -> Tablebase {
+            Tablebase::open(self.0.to_str().unwrap()).unwrap()
+        }
+
+        // The fixtures, and the real tables as well when SYZYGY_PATH has them.
+        fn tablebases(&self) -> Vec<Tablebase> {
+            let mut tablebases = vec![self.open()];
+            let has_tables = |path: &String| ["KQvK", "KRvK", "KPvK"].iter().all(|material| Path::new(path).join(format!("{}.rtbw", material)).exists());
+            if let Some(path) = syzygy_path().filter(has_tables) {
+                tablebases.push(Tablebase::open(&path).unwrap());
+            }
+            tablebases
+        }
+    }
+
+    impl Drop for FixtureDirectory {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn assert_wdl(tablebases: &[Tablebase], fen: &str, wdl: Wdl) {
+        for tablebase in tablebases {
+            assert_eq!(tablebase.probe_wdl(&init_game_from_fen(fen)), Some(wdl), "{}", fen);
+        }
+    }
+
+    fn after(game: &GAME, chess_move: &Move) -> GAME {
+        let mut virtual_game = game.clone();
+        assert!(make_move(chess_move, &mut virtual_game));
+        virtual_game
+    }
+
+    // The board symmetries the index leaves out, the files only get mirrored with pawns.
+    fn canonical(squares: [usize; 3], has_pawns: bool) -> [usize; 3] {
+        let symmetries = if has_pawns { 2 } else { 8 };
+        (0..symmetries).map(|symmetry| squares.map(|mut square| {
+            if symmetry & 1 > 0 {
+                square ^= 7;
+            }
+            if symmetry & 2 > 0 {
+                square ^= 56;
+            }
+            if symmetry & 4 > 0 {
+                square = ((square >> 3) | (square << 3)) & 63;
+            }
+            square
+        })).min().unwrap()
+    }
+
+    #[test]
+    fn indices_only_join_symmetric_positions() {
+        let indices = Indices::new();
+        for (material, piece) in [("KQvK", QUEEN_CODE), ("KPvK", PAWN_CODE)] {
+            let table = empty_table(material, false, &[piece, WHITE_KING_CODE, BLACK_KING_CODE], &indices);
+            let mut seen = HashMap::new();
+            for (white_king, black_king, square, white_to_move) in legal_positions(piece) {
+                let (side, file, idx) = table.encode(&position(white_king, black_king, square, piece, white_to_move), false, &indices).unwrap();
+                let pairs_data = table.get(side, file);
+                let group_count = pairs_data.group_len.iter().position(|len| *len == 0).unwrap();
+                assert!(idx < pairs_data.group_idx[group_count], "{} index {} out of the table", material, idx);
+                let squares = canonical([white_king, black_king, square], piece == PAWN_CODE);
+                assert_eq!(*seen.entry((side, file, idx)).or_insert(squares), squares, "{} index {}", material, idx);
+            }
+        }
+    }
+
+    #[test]
+    fn fixtures_are_found() {
+        let fixtures = FixtureDirectory::new("found");
+        let tablebase = fixtures.open();
+        assert_eq!(tablebase.get_max_pieces(), 3);
+        assert!(tablebase.can_probe(&init_game_from_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1")));
+        assert!(!tablebase.can_probe(&init_game_from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1")));
+        assert_eq!(tablebase.probe_wdl(&init_game_from_fen("4k3/8/8/8/8/8/8/3BK3 w - - 0 1")), None);
+        assert_eq!(tablebase.probe_wdl(&init_game_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1")), Some(Wdl::Draw));
+    }
+
+    #[test]
+    fn kqvk() {
+        let fixtures = FixtureDirectory::new("kqvk");
+        let tablebases = fixtures.tablebases();
+        assert_wdl(&tablebases, "4k3/8/8/8/8/8/8/4K2Q w - - 0 1", Wdl::Win);
+        assert_wdl(&tablebases, "4k3/8/8/8/8/8/8/3QK3 b - - 0 1", Wdl::Loss);
+        assert_wdl(&tablebases, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", Wdl::Draw);     // stalemate
+        assert_wdl(&tablebases, "8/8/8/8/8/8/6kQ/4K3 b - - 0 1", Wdl::Draw);      // the queen is taken
+        assert_wdl(&tablebases, "3qk3/8/8/8/8/8/8/4K3 w - - 0 1", Wdl::Loss);
+    }
+
+    #[test]
+    fn krvk() {
+        let fixtures = FixtureDirectory::new("krvk");
+        let tablebases = fixtures.tablebases();
+        assert_wdl(&tablebases, "8/8/8/4k3/8/8/8/R3K3 w - - 0 1", Wdl::Win);
+        assert_wdl(&tablebases, "4k3/8/4K3/8/8/8/8/R7 b - - 0 1", Wdl::Loss);
+        assert_wdl(&tablebases, "8/8/8/8/8/8/8/Rk2K3 b - - 0 1", Wdl::Draw);      // the rook is taken
+        assert_wdl(&tablebases, "r3k3/8/8/4K3/8/8/8/8 b - - 0 1", Wdl::Win);
+    }
+
+    #[test]
+    fn kpvk() {
+        let fixtures = FixtureDirectory::new("kpvk");
+        let tablebases = fixtures.tablebases();
+        assert_wdl(&tablebases, "3k4/8/3K4/3P4/8/8/8/8 w - - 0 1", Wdl::Win);
+        assert_wdl(&tablebases, "3k4/8/3K4/3P4/8/8/8/8 b - - 0 1", Wdl::Loss);
+        // the opposition decides
+        assert_wdl(&tablebases, "8/4k3/8/4K3/4P3/8/8/8 w - - 0 1", Wdl::Draw);
+        assert_wdl(&tablebases, "8/4k3/8/4K3/4P3/8/8/8 b - - 0 1", Wdl::Loss);
+        assert_wdl(&tablebases, "k7/8/8/P1K5/8/8/8/8 w - - 0 1", Wdl::Draw);      // rook pawn
+        assert_wdl(&tablebases, "7k/8/P7/8/8/8/8/7K w - - 0 1", Wdl::Win);        // the pawn outruns the king
+        assert_wdl(&tablebases, "8/8/8/8/4p3/4k3/8/4K3 b - - 0 1", Wdl::Win);
+    }
+
+    #[test]
+    fn longest_mates() {
+        // the queen mates in at most 10 moves and the rook in at most 16
+        assert_eq!(mate_distances(QUEEN_CODE).iter().flatten().max(), Some(&19));
+        assert_eq!(mate_distances(ROOK_CODE).iter().flatten().max(), Some(&31));
+    }
+
+    #[test]
+    fn dtz() {
+        let fixtures = FixtureDirectory::new("dtz");
+        let tablebase = fixtures.open();
+        let mate_in_one = init_game_from_fen("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1");
+        assert_eq!(tablebase.probe_dtz(&mate_in_one), Some(1));
+        assert_eq!(tablebase.probe_dtz_after_move(&mate_in_one, &san_to_move("Qg8", &mate_in_one).unwrap()), Some(1));
+        assert_eq!(tablebase.probe_dtz(&init_game_from_fen("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1")), Some(-1));   // mated
+        assert_eq!(tablebase.probe_dtz(&init_game_from_fen("8/8/8/8/8/8/6kQ/4K3 b - - 0 1")), Some(0));
+        assert_eq!(tablebase.probe_dtz(&init_game_from_fen("4k3/8/8/8/8/8/8/3BK3 w - - 0 1")), None);
+
+        // White takes the fastest way, black the slowest, the table only has white to move.
+        for fen in ["8/8/8/4k3/8/8/8/R3K3 w - - 0 1", "4k3/8/8/8/8/8/8/4K2Q w - - 0 1", "4k3/8/4K3/8/8/8/8/R7 b - - 0 1", "8/8/3k4/8/8/8/1Q6/6K1 b - - 0 1"] {
+            let game = init_game_from_fen(fen);
+            let dtz = tablebase.probe_dtz(&game).unwrap();
+            let legal_moves = game.generate_legal_moves();
+            if game.is_whites_turn() {
+                let fastest = legal_moves.iter().filter_map(|chess_move| tablebase.probe_dtz_after_move(&game, chess_move)).filter(|dtz| *dtz > 0).min();
+                assert!(dtz > 1 && dtz % 2 == 1, "{} {}", fen, dtz);
+                assert_eq!(Some(dtz), fastest, "{}", fen);
+            } else {
+                let slowest = legal_moves.iter().map(|chess_move| -tablebase.probe_dtz(&after(&game, chess_move)).unwrap() - 1).min();
+                assert!(dtz < -1 && dtz % 2 == 0, "{} {}", fen, dtz);
+                assert_eq!(Some(dtz), slowest, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn engine_keeps_the_tablebase_result() {
+        let fixtures = FixtureDirectory::new("engine");
+
+        // only taking the queen holds the draw
+        let game = init_game_from_fen("8/8/8/8/8/8/6kQ/4K3 b - - 0 1");
+        let engine = Engine::new(2).with_tablebase(fixtures.open());
+        let moves: Vec<String> = engine.score_moves(&game).iter().map(|(chess_move, _)| chess_move.to_uci()).collect();
+        assert_eq!(moves, ["g2h2"]);
+        assert_eq!(engine.search(&game).score, 0);
+
+        // when winning only the moves with the lowest DTZ are searched
+        let tablebase = fixtures.open();
+        for fen in ["k7/8/1K6/8/8/8/8/6Q1 w - - 0 1", "8/8/8/4k3/8/8/8/R3K3 w - - 0 1"] {
+            let game = init_game_from_fen(fen);
+            let dtz = tablebase.probe_dtz(&game).unwrap();
+            let root_moves = engine.score_moves(&game);
+            assert!(!root_moves.is_empty() && root_moves.len() < game.generate_legal_moves().len(), "{}", fen);
+            for (chess_move, _) in &root_moves {
+                assert_eq!(tablebase.probe_dtz_after_move(&game, chess_move), Some(dtz), "{} {}", fen, chess_move.to_uci());
+            }
+            let result = engine.search(&game);
+            assert!(root_moves.iter().any(|(chess_move, _)| Some(*chess_move) == result.best_move));
+        }
+
+        // the search stops at tablebase positions and scores them below mates
+        let score = engine.search(&init_game_from_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1")).score;
+        assert!(score > TABLEBASE_WIN_SCORE - 10 && score < TABLEBASE_WIN_SCORE, "{}", score);
+    }
+}
@@ -0,0 +1,340 @@
+use crate::analysis::{opposite, ray_tiles, BISHOP_DIRECTIONS, ROOK_DIRECTIONS};
+use crate::engine::piece_value;
+use crate::make_move;
+use crate::memory_location_to_algebraic_notation;
+use crate::Move;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+pub const MATE_SEARCH_MOVES: u8 = 2;                            // mates looked for without a line from the engine
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motif {
+    Fork,                                                       // one piece attacking two or more targets
+    Pin,                                                        // a piece that can't move without exposing a bigger one behind it
+    Skewer,                                                     // a big piece attacked with a smaller one behind it
+    DiscoveredAttack,                                           // a move that opens the line of another piece
+    BackRank,                                                   // a king shut in on its first rank by its own pieces
+    MateIn(u8),
+}
+
+impl Motif {
+    // Names like the lichess puzzle themes, fork, pin, skewer, discoveredAttack, backRank and mateIn2.
+    pub fn get_name(&self) -> String {
+        match self {
+            Motif::Fork => "fork".to_string(),
+            Motif::Pin => "pin".to_string(),
+            Motif::Skewer => "skewer".to_string(),
+            Motif::DiscoveredAttack => "discoveredAttack".to_string(),
+            Motif::BackRank => "backRank".to_string(),
+            Motif::MateIn(moves) => format!("mateIn{}", moves),
+        }
+    }
+}
+
+// A motif with the tiles taking part, the attacking piece first and then what it goes after.
+// For back rank weaknesses the king comes first, followed by the pieces boxing it in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TacticTag {
+    pub motif: Motif,
+    pub tiles: Vec<usize>,
+}
+
+impl TacticTag {
+    pub fn describe(&self) -> String {
+        let squares: Vec<String> = self.tiles.iter().map(|tile| memory_location_to_algebraic_notation(*tile)).collect();
+        let (first, rest) = match squares.split_first() {
+            Some((first, rest)) => (first.clone(), rest.join(", ")),
+            None => return self.motif.get_name(),
+        };
+        match self.motif {
+            Motif::Fork => format!("fork: {} attacks {}", first, rest),
+            Motif::Pin => format!("pin: {} pins {}", first, rest),
+            Motif::Skewer => format!("skewer: {} skewers {}", first, rest),
+            Motif::DiscoveredAttack => format!("discovered attack: moving {} lets {}", first, rest),
+            Motif::BackRank => format!("back rank: the king on {} is shut in by {}", first, rest),
+            Motif::MateIn(moves) => format!("mate in {} starting with {}{}", moves, first, rest),
+        }
+    }
+}
+
+// The motifs of a position, or with an engine line those of the moves of the side playing first.
+// A line ending in mate is tagged as mate in N, otherwise short mates are looked for directly.
+pub fn find_tactics(game: &GAME, line: Option<&[Move]>) -> Vec<TacticTag> {
+    let mut tags = Vec::new();
+    match line {
+        Some(line) if !line.is_empty() => {
+            let mut position = game.clone();
+            for (ply, chess_move) in line.iter().enumerate() {
+                let before = position.clone();
+                if !make_move(chess_move, &mut position) {
+                    break;
+                }
+                if ply % 2 == 0 {
+                    for tag in move_tactics(&before, chess_move, &position) {
+                        if !tags.contains(&tag) {
+                            tags.push(tag);
+                        }
+                    }
+                }
+                if ply % 2 == 0 && is_checkmate(&position) {
+                    tags.push(TacticTag { motif: Motif::MateIn((ply / 2 + 1) as u8), tiles: vec![line[0].from, line[0].to] });
+                }
+            }
+        }
+        _ => {
+            for color in [COLORS::WHITE, COLORS::BLACK] {
+                tags.extend(forks(game, color, None));
+                tags.extend(pins_and_skewers(game, color, None));
+                tags.extend(back_rank_weakness(game, opposite(color)));
+            }
+            if let Some(mate) = find_mate(game, MATE_SEARCH_MOVES) {
+                tags.push(TacticTag { motif: Motif::MateIn(((mate.len() + 1) / 2) as u8), tiles: vec![mate[0].from, mate[0].to] });
+            }
+        }
+    }
+    tags
+}
+
+// What a move sets up: forks and pins by the moved piece, lines it opens and back rank mates.
+pub fn move_tactics(before: &GAME, chess_move: &Move, after: &GAME) -> Vec<TacticTag> {
+    let color = before.turn;
+    let mut tags = forks(after, color, Some(chess_move.to));
+    tags.extend(pins_and_skewers(after, color, Some(chess_move.to)));
+    tags.extend(discovered_attacks(before, chess_move, after));
+    if is_checkmate(after) && after.board[chess_move.to] & (TYPES::ROOK | TYPES::QUEEN) > 0 {
+        if let Some(weakness) = back_rank_weakness(after, opposite(color)) {
+            if weakness.tiles[0] / 8 == chess_move.to / 8 {
+                tags.push(weakness);
+            }
+        }
+    }
+    tags
+}
+
+// Pieces of color attacking two or more enemy pieces that are the king, worth more or not defended.
+fn forks(game: &GAME, color: u8, only_tile: Option<usize>) -> Vec<TacticTag> {
+    let mut tags = Vec::new();
+    for forker in 0..64 {
+        let piece = game.board[forker];
+        if piece & color == 0 || only_tile.is_some_and(|tile| tile != forker) {
+            continue;
+        }
+        let targets: Vec<usize> = (0..64).filter(|target| {
+            let target_piece = game.board[*target];
+            target_piece & opposite(color) > 0 && game.attackers_of(*target, color).contains(&forker) &&
+                (target_piece & TYPES::KING > 0 || piece_value(target_piece) > piece_value(piece) || game.defenders_of(*target).is_empty())
+        }).collect();
+        if targets.len() >= 2 {
+            let mut tiles = vec![forker];
+            tiles.extend(targets);
+            tags.push(TacticTag { motif: Motif::Fork, tiles });
+        }
+    }
+    tags
+}
+
+// Rooks, bishops and queens of color looking through one enemy piece at another: a pin when the
+// one behind is worth more (or is the king), a skewer when the one in front is.
+fn pins_and_skewers(game: &GAME, color: u8, only_tile: Option<usize>) -> Vec<TacticTag> {
+    let mut tags = Vec::new();
+    for attacker in 0..64 {
+        let piece = game.board[attacker];
+        if piece & color == 0 || piece & (TYPES::ROOK | TYPES::BISHOP | TYPES::QUEEN) == 0 || only_tile.is_some_and(|tile| tile != attacker) {
+            continue;
+        }
+        for (directions, slider) in [(ROOK_DIRECTIONS, TYPES::ROOK), (BISHOP_DIRECTIONS, TYPES::BISHOP)] {
+            if piece & (slider | TYPES::QUEEN) == 0 {
+                continue;
+            }
+            for direction in directions {
+                let mut ray = ray_tiles(attacker, direction).filter(|tile| game.board[*tile] != TYPES::NONE);
+                let (front, back) = match (ray.next(), ray.next()) {
+                    (Some(front), Some(back)) => (front, back),
+                    _ => continue,
+                };
+                let (front_piece, back_piece) = (game.board[front], game.board[back]);
+                if front_piece & opposite(color) == 0 || back_piece & opposite(color) == 0 {
+                    continue;
+                }
+                let front_value = if front_piece & TYPES::KING > 0 { i32::MAX } else { piece_value(front_piece) };
+                let back_value = if back_piece & TYPES::KING > 0 { i32::MAX } else { piece_value(back_piece) };
+                if back_value > front_value && back_value > piece_value(piece) {
+                    tags.push(TacticTag { motif: Motif::Pin, tiles: vec![attacker, front, back] });
+                } else if front_value > back_value && back_piece & TYPES::PAWN == 0 {
+                    tags.push(TacticTag { motif: Motif::Skewer, tiles: vec![attacker, front, back] });
+                }
+            }
+        }
+    }
+    tags
+}
+
+// Other pieces of the mover that attack the king or a piece worth at least a rook, or an undefended
+// piece, only because the moving piece got out of their way.
+fn discovered_attacks(before: &GAME, chess_move: &Move, after: &GAME) -> Vec<TacticTag> {
+    let color = before.turn;
+    let mut tags = Vec::new();
+    for target in 0..64 {
+        let target_piece = after.board[target];
+        if target_piece & opposite(color) == 0 {
+            continue;
+        }
+        let valuable = target_piece & TYPES::KING > 0 || piece_value(target_piece) >= piece_value(TYPES::ROOK) || after.defenders_of(target).is_empty();
+        if !valuable {
+            continue;
+        }
+        let attackers_before = before.attackers_of(target, color);
+        for attacker in after.attackers_of(target, color) {
+            if attacker != chess_move.to && !attackers_before.contains(&attacker) && after.board[attacker] & (TYPES::ROOK | TYPES::BISHOP | TYPES::QUEEN) > 0 {
+                tags.push(TacticTag { motif: Motif::DiscoveredAttack, tiles: vec![chess_move.from, attacker, target] });
+            }
+        }
+    }
+    tags
+}
+
+// The king of color on its first rank with every tile in front of it taken by its own pieces or attacked.
+fn back_rank_weakness(game: &GAME, color: u8) -> Option<TacticTag> {
+    let king_tile = crate::analysis::king_tile(&game.board, color)?;
+    let (back_rank, forward) = if color == COLORS::WHITE { (7, -1) } else { (0, 1) };
+    if king_tile / 8 != back_rank {
+        return None;
+    }
+    let file = (king_tile % 8) as i32;
+    let front_rank = back_rank as i32 + forward;
+    let mut blockers = Vec::new();
+    for front_file in file - 1..=file + 1 {
+        if !(0..8).contains(&front_file) {
+            continue;
+        }
+        let tile = (front_rank * 8 + front_file) as usize;
+        if game.board[tile] & color > 0 {
+            blockers.push(tile);
+        } else if !game.is_square_attacked(tile, opposite(color)) {
+            return None;
+        }
+    }
+    if blockers.is_empty() {
+        return None;
+    }
+    let mut tiles = vec![king_tile];
+    tiles.extend(blockers);
+    Some(TacticTag { motif: Motif::BackRank, tiles })
+}
+
+pub fn is_checkmate(game: &GAME) -> bool {
+    game.is_check() && game.generate_legal_moves().is_empty()
+}
+
+// The shortest forced mate for the side to move within max_moves of its moves, as the moves of the
+// line with the longest defence. Every move is tried so keep max_moves small.
+pub fn find_mate(game: &GAME, max_moves: u8) -> Option<Vec<Move>> {
+    (1..=max_moves).find_map(|moves| mate_line(game, moves))
+}
+
+fn mate_line(game: &GAME, moves: u8) -> Option<Vec<Move>> {
+    let mut candidates = game.generate_legal_moves();
+    candidates.sort_by_key(|chess_move| !game.is_capture(chess_move));
+    for chess_move in candidates {
+        let mut after = game.clone();
+        make_move(&chess_move, &mut after);
+        let replies = after.generate_legal_moves();
+        if replies.is_empty() {
+            if after.is_check() {
+                return Some(vec![chess_move]);
+            }
+            continue;
+        }
+        if moves == 1 {
+            continue;
+        }
+        let mut longest_defence: Option<Vec<Move>> = Some(Vec::new());
+        for reply in replies {
+            let mut after_reply = after.clone();
+            make_move(&reply, &mut after_reply);
+            match mate_line(&after_reply, moves - 1) {
+                Some(rest) => {
+                    if longest_defence.iter().all(|line| rest.len() + 1 > line.len()) {
+                        let mut line = vec![reply];
+                        line.extend(rest);
+                        longest_defence = Some(line);
+                    }
+                }
+                None => {
+                    longest_defence = None;
+                    break;
+                }
+            }
+        }
+        if let Some(defence) = longest_defence {
+            let mut line = vec![chess_move];
+            line.extend(defence);
+            return Some(line);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, init_game_from_fen};
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    fn tag(motif: Motif, squares: &[&str]) -> TacticTag {
+        TacticTag { motif, tiles: squares.iter().map(|square| tile(square)).collect() }
+    }
+
+    // The tags of a line of moves written like d5c7.
+    fn line_tactics(fen: &str, moves: &[&str]) -> Vec<TacticTag> {
+        let line: Vec<Move> = moves.iter().map(|uci| Move::new(tile(&uci[..2]), tile(&uci[2..]), TYPES::NONE)).collect();
+        find_tactics(&init_game_from_fen(fen), Some(&line))
+    }
+
+    #[test]
+    fn knight_forks_king_and_rook() {
+        let tags = line_tactics("r3k3/8/8/3N4/8/8/8/4K3 w - - 0 1", &["d5c7"]);
+        assert!(tags.contains(&tag(Motif::Fork, &["c7", "a8", "e8"])), "{:?}", tags);
+    }
+
+    #[test]
+    fn bishop_pins_knight_to_king() {
+        let tags = find_tactics(&init_game_from_fen("4k3/8/2n5/1B6/8/8/8/4K3 b - - 0 1"), None);
+        assert!(tags.contains(&tag(Motif::Pin, &["b5", "c6", "e8"])), "{:?}", tags);
+    }
+
+    #[test]
+    fn rook_skewers_king_and_queen() {
+        let tags = line_tactics("q7/8/8/k7/8/8/4K3/7R w - - 0 1", &["h1a1"]);
+        assert!(tags.contains(&tag(Motif::Skewer, &["a1", "a5", "a8"])), "{:?}", tags);
+    }
+
+    #[test]
+    fn knight_move_discovers_the_bishop() {
+        let tags = line_tactics("4k3/6r1/8/8/3N4/8/1B6/4K3 w - - 0 1", &["d4b5"]);
+        assert!(tags.contains(&tag(Motif::DiscoveredAttack, &["d4", "b2", "g7"])), "{:?}", tags);
+    }
+
+    #[test]
+    fn back_rank_mate() {
+        let tags = line_tactics("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", &["d1d8"]);
+        assert!(tags.contains(&tag(Motif::BackRank, &["g8", "f7", "g7", "h7"])), "{:?}", tags);
+        assert!(tags.contains(&tag(Motif::MateIn(1), &["d1", "d8"])), "{:?}", tags);
+    }
+
+    #[test]
+    fn mate_is_found_without_a_line() {
+        let game = init_game_from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1");
+        assert_eq!(find_mate(&game, MATE_SEARCH_MOVES), Some(vec![Move::new(tile("d1"), tile("d8"), TYPES::NONE)]));
+        assert!(find_tactics(&game, None).contains(&tag(Motif::MateIn(1), &["d1", "d8"])));
+        // boxed in by its own pawns the king is mated on the back rank, with luft on h6 it isn't
+        let game = init_game_from_fen("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(find_mate(&game, 1), Some(vec![Move::new(tile("a1"), tile("a8"), TYPES::NONE)]));
+        assert!(find_mate(&init_game_from_fen("6k1/5pp1/7p/8/8/8/8/R3K3 w - - 0 1"), 2).is_none());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::analysis::opposite;
+use crate::pgn::parse_square;
+use crate::variant::{self, Variant};
+use crate::{init_game_from_fen, king_is_attacked, memory_location_to_algebraic_notation};
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+const CASTLING_SYMBOLS: [char; 4] = ['K', 'Q', 'k', 'q'];
+
+// Something that can't happen in a game played from the start, found by GAME::validate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionProblem {
+    KingCount(u8, usize),                                       // the colour and how many kings it has
+    PawnOnBackRank(usize),
+    OpponentInCheck(u8),                                        // the king of the side that just moved can be taken
+    ImpossibleCastling(char),                                   // the right as written in the FEN, like K or H
+    BadEnPassant(usize),
+    TooManyPromoted(u8),                                        // more promoted pieces than pawns gone
+}
+
+impl PositionProblem {
+    pub fn describe(&self) -> String {
+        match self {
+            PositionProblem::KingCount(color, 0) => format!("{} has no king", color_name(*color)),
+            PositionProblem::KingCount(color, count) => format!("{} has {} kings", color_name(*color), count),
+            PositionProblem::PawnOnBackRank(tile) => format!("pawn on {}", memory_location_to_algebraic_notation(*tile)),
+            PositionProblem::OpponentInCheck(color) => format!("the {} king is in check with {} to move", color_name(*color), color_name(opposite(*color))),
+            PositionProblem::ImpossibleCastling(symbol) => format!("castling right {} without the king and rook for it", symbol),
+            PositionProblem::BadEnPassant(tile) => format!("en passant on {} without a pawn that just moved past it", memory_location_to_algebraic_notation(*tile)),
+            PositionProblem::TooManyPromoted(color) => format!("{} has more promoted pieces than missing pawns", color_name(*color)),
+        }
+    }
+}
+
+impl GAME {
+    // Checks the position could come up in a game of its variant, every problem found is returned.
+    pub fn validate(&self) -> Result<(), Vec<PositionProblem>> {
+        let mut problems = Vec::new();
+        for color in [COLORS::WHITE, COLORS::BLACK] {
+            let kings = self.board.iter().filter(|piece| **piece == TYPES::KING + color).count();
+            let expected_kings = if self.variant == Variant::Horde && color == COLORS::WHITE { 0 } else { 1 };
+            if self.variant != Variant::Antichess && kings != expected_kings {
+                problems.push(PositionProblem::KingCount(color, kings));
+            }
+        }
+        for (tile, piece) in self.board.iter().enumerate() {
+            // horde pawns start on the first rank
+            let horde_pawn = self.variant == Variant::Horde && *piece & COLORS::WHITE > 0 && tile >= 56;
+            if *piece & TYPES::PAWN > 0 && !(8..56).contains(&tile) && !horde_pawn {
+                problems.push(PositionProblem::PawnOnBackRank(tile));
+            }
+        }
+        if !variant::ignores_check(self) && king_is_attacked(self, opposite(self.turn)) {
+            problems.push(PositionProblem::OpponentInCheck(opposite(self.turn)));
+        }
+        for (right, symbol) in CASTLING_SYMBOLS.iter().enumerate() {
+            if self.chastling_ability[right] && !self.castling_pieces_in_place(right) {
+                problems.push(PositionProblem::ImpossibleCastling(*symbol));
+            }
+        }
+        if self.tile_available_to_un_passant <= 63 && !self.en_passant_possible(self.tile_available_to_un_passant as usize) {
+            problems.push(PositionProblem::BadEnPassant(self.tile_available_to_un_passant as usize));
+        }
+        for color in [COLORS::WHITE, COLORS::BLACK] {
+            // captured crazyhouse pieces change sides and horde has more than eight pawns
+            let counts_pieces = self.variant != Variant::Crazyhouse && !(self.variant == Variant::Horde && color == COLORS::WHITE);
+            if counts_pieces && self.promoted_pieces(color) > 8 - self.count(TYPES::PAWN + color).min(8) {
+                problems.push(PositionProblem::TooManyPromoted(color));
+            }
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    pub(crate) fn castling_pieces_in_place(&self, right: usize) -> bool {
+        let color = if right < 2 { COLORS::WHITE } else { COLORS::BLACK };
+        let back_rank = if color == COLORS::WHITE { 56 } else { 0 };
+        let rook_tile = self.chastling_rooks[right];
+        let king_tile = match (back_rank..back_rank + 8).find(|tile| self.board[*tile] == TYPES::KING + color) {
+            Some(king_tile) => king_tile,
+            None => return false,
+        };
+        let rook_on_its_side = if right == 0 || right == 2 { rook_tile > king_tile } else { rook_tile < king_tile };
+        (back_rank..back_rank + 8).contains(&rook_tile) && self.board[rook_tile] == TYPES::ROOK + color && rook_on_its_side
+    }
+
+    // The tile has to be the one an enemy pawn just skipped, with the pawn in front of it and nothing
+    // on the tiles it came from and passed. Horde pawns stepping two tiles from the first rank can't be
+    // taken en passant, so the second rank never is one.
+    pub(crate) fn en_passant_possible(&self, tile: usize) -> bool {
+        let (pawn_tile, from_tile, on_its_rank) = if self.turn == COLORS::WHITE {
+            (tile + 8, tile.wrapping_sub(8), tile / 8 == 2)
+        } else {
+            (tile.wrapping_sub(8), tile + 8, tile / 8 == 5)
+        };
+        on_its_rank
+            && self.board[tile] == TYPES::NONE
+            && self.board[from_tile] == TYPES::NONE
+            && self.board[pawn_tile] == TYPES::PAWN + opposite(self.turn)
+    }
+
+    // The pieces of color beyond the starting army, each had to be a pawn. Bishops are counted per
+    // square colour, a second bishop on the same colour is a promoted one too.
+    fn promoted_pieces(&self, color: u8) -> usize {
+        let bishops_on = |square_color: usize| (0..64)
+            .filter(|tile| self.board[*tile] == TYPES::BISHOP + color && (tile % 8 + tile / 8) % 2 == square_color)
+            .count();
+        let extra = |count: usize, usual: usize| count.saturating_sub(usual);
+        extra(self.count(TYPES::QUEEN + color), 1)
+            + extra(self.count(TYPES::ROOK + color), 2)
+            + extra(self.count(TYPES::KNIGHT + color), 2)
+            + extra(bishops_on(0), 1)
+            + extra(bishops_on(1), 1)
+            + extra(self.count(TYPES::KING + color), 1)         // antichess pawns promote to kings
+    }
+
+    fn count(&self, piece: u8) -> usize {
+        self.board.iter().filter(|other| **other == piece).count()
+    }
+}
+
+// Loads a FEN the way init_game_from_fen does, but gives an error for text that isn't a FEN and
+// for positions that fail GAME::validate instead of playing on from them. The variant is used when the
+// FEN doesn't tell it (three-check counters and crazyhouse pockets do). Castling rights the FEN gives
+// without their king and rook are an error too, the loader would drop them.
+pub fn game_from_fen(fen: &str, variant: Variant) -> Result<GAME, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let (placement, pocket) = match fields.first().map(|placement| placement.split_once('[')) {
+        Some(Some((placement, pocket))) => (placement, Some(pocket)),
+        Some(None) => (fields[0], None),
+        None => ("", None),
+    };
+    let ranks: Vec<&str> = placement.split('/').collect();
+    let rank_is_valid = |rank: &&str| {
+        let width: u32 = rank.chars().filter(|c| *c != '~').map(|c| c.to_digit(10).unwrap_or(1)).sum();
+        width == 8 && rank.chars().all(|c| c.is_ascii_digit() || "pnbrqkPNBRQK~".contains(c))
+    };
+    let castling = fields.get(2).copied().unwrap_or("-");
+    let castling_is_valid = castling == "-" || castling.chars().all(|c| "KQkqABCDEFGHabcdefgh".contains(c));
+    let en_passant_is_valid = fields.get(3).into_iter().all(|square| *square == "-" || parse_square(square).is_some());
+    // pieces in hand like [QNpp], and check counts where at most one side has given all its checks
+    let pocket_is_valid = pocket.into_iter().all(|pocket| {
+        pocket.strip_suffix(']').is_some_and(|pieces| pieces.chars().all(|c| "PNBRQpnbrq".contains(c)))
+    });
+    let checks_are_valid = fields.iter().skip(2).filter(|field| field.contains('+')).all(|field| {
+        variant::parse_checks_field(field).is_some_and(|checks| checks.iter().any(|count| *count < variant::CHECKS_TO_WIN))
+    });
+    if fields.len() < 2 || ranks.len() != 8 || !ranks.iter().all(rank_is_valid) || (fields[1] != "w" && fields[1] != "b")
+        || !castling_is_valid || !en_passant_is_valid || !pocket_is_valid || !checks_are_valid {
+        return Err(format!("{} is not a valid FEN", fen));
+    }
+
+    let mut game = init_game_from_fen(fen);
+    if game.variant == Variant::Standard {
+        game.set_variant(variant);
+    }
+    let mut problems: Vec<PositionProblem> = castling.chars()
+        .filter(|symbol| *symbol != '-' && !castling_loaded(&game, *symbol))
+        .map(PositionProblem::ImpossibleCastling)
+        .collect();
+    if let Err(found) = game.validate() {
+        problems.extend(found);
+    }
+    if problems.is_empty() {
+        return Ok(game);
+    }
+    let descriptions: Vec<String> = problems.iter().map(|problem| problem.describe()).collect();
+    Err(format!("illegal position: {}", descriptions.join(", ")))
+}
+
+// Whether the castling right written as symbol made it into the game.
+pub(crate) fn castling_loaded(game: &GAME, symbol: char) -> bool {
+    let color = if symbol.is_ascii_uppercase() { COLORS::WHITE } else { COLORS::BLACK };
+    let back_rank = if color == COLORS::WHITE { 56 } else { 0 };
+    let king_tile = match (back_rank..back_rank + 8).find(|tile| game.board[*tile] == TYPES::KING + color) {
+        Some(king_tile) => king_tile,
+        None => return false,
+    };
+    let first_right = if color == COLORS::WHITE { 0 } else { 2 };
+    match symbol.to_ascii_lowercase() {
+        'k' => game.chastling_ability[first_right],
+        'q' => game.chastling_ability[first_right + 1],
+        file @ 'a'..='h' => {
+            let rook_tile = back_rank + (file as usize - 'a' as usize);
+            let right = first_right + if rook_tile > king_tile { 0 } else { 1 };
+            game.chastling_ability[right] && game.chastling_rooks[right] == rook_tile
+        }
+        _ => false,
+    }
+}
+
+fn color_name(color: u8) -> &'static str {
+    if color == COLORS::WHITE { "white" } else { "black" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, make_move, Move};
+
+    #[test]
+    fn broken_fens_are_errors() {
+        for fen in ["xyz", "8/8/8/8/8/8/8 w - - 0 1", "4k3/8/8/8/8/8/8/4K3 x - - 0 1", "4k3/8/8/8/8/8/8/4K3 w - z9 0 1"] {
+            assert_eq!(game_from_fen(fen, Variant::Standard).err(), Some(format!("{} is not a valid FEN", fen)));
+        }
+    }
+
+    #[test]
+    fn check_counts_and_pockets_are_checked() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR";
+        for fen in [format!("{} w KQkq - 0 1 +9+9", start), format!("{} w KQkq - 0 1 +3+3", start), format!("{} w KQkq - 0 1 +1+x", start),
+                    format!("{}[Xz] w KQkq - 0 1", start), format!("{}[Q w KQkq - 0 1", start), format!("{}[K] w KQkq - 0 1", start)] {
+            assert!(game_from_fen(&fen, Variant::Standard).is_err(), "{}", fen);
+        }
+        let three_check = game_from_fen(&format!("{} w KQkq - 0 1 +2+1", start), Variant::Standard).unwrap();
+        assert_eq!(three_check.get_checks_given(), [2, 1]);
+        let crazyhouse = game_from_fen(&format!("{}[QNpp] w KQkq - 0 1", start), Variant::Standard).unwrap();
+        assert_eq!(crazyhouse.get_pocket(COLORS::BLACK), [2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn illegal_positions_are_described() {
+        assert_eq!(game_from_fen("4k3/8/8/8/8/8/8/8 w - - 0 1", Variant::Standard).err().as_deref(), Some("illegal position: white has no king"));
+        assert_eq!(game_from_fen("4k3/8/8/8/8/8/8/R3K3 w K - 0 1", Variant::Standard).err().as_deref(),
+                   Some("illegal position: castling right K without the king and rook for it"));
+        assert_eq!(game_from_fen("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", Variant::Standard).err().as_deref(),
+                   Some("illegal position: the black king is in check with white to move"));
+        assert!(game_from_fen("8/8/8/8/8/8/8/8 w - - 0 1", Variant::Antichess).is_ok());
+    }
+
+    #[test]
+    fn horde_first_rank_pawns_are_not_taken_en_passant() {
+        assert_eq!(game_from_fen("rnbqkbnr/pppppppp/8/8/8/3pP3/8/8 b kq e2 0 1", Variant::Horde).err().as_deref(),
+                   Some("illegal position: en passant on e2 without a pawn that just moved past it"));
+        assert!(game_from_fen("rnbqkbnr/pppppppp/8/8/3pP3/8/8/8 b kq e3 0 1", Variant::Horde).is_ok());
+
+        // the double step from the first rank leaves no en passant tile behind
+        let mut game = game_from_fen("rnbqkbnr/pppppppp/8/8/8/3p4/8/4P3 w kq - 0 1", Variant::Horde).unwrap();
+        let e1 = algebraic_notation_to_memory_location("e1");
+        let (e2, e3) = (e1 - 8, e1 - 16);
+        assert!(make_move(&Move::new(e1, e3, TYPES::NONE), &mut game));
+        assert_eq!(game.get_fen(), "rnbqkbnr/pppppppp/8/8/8/3pP3/8/8 b kq - 0 1");
+        assert!(game.generate_legal_moves().iter().all(|chess_move| chess_move.to != e2));
+    }
+}
@@ -0,0 +1,542 @@
+use crate::analysis;
+use crate::movement::available_moves_for_piece;
+use crate::Move;
+use crate::COLORS;
+use crate::TYPES;
+use crate::GAME;
+
+pub const HILL_TILES: [usize; 4] = [27, 28, 35, 36];           // d5, e5, d4, e4
+pub const CHECKS_TO_WIN: u8 = 3;
+pub const POCKET_PIECES: [u8; 5] = [TYPES::PAWN, TYPES::KNIGHT, TYPES::BISHOP, TYPES::ROOK, TYPES::QUEEN];
+
+// Every variant is standard chess with the rules below changed. Chess960 isn't one of them,
+// the starting position is separate from the rules so the variants can be played from 960 positions too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    KingOfTheHill,                                              // the king reaching the centre wins
+    ThreeCheck,                                                 // the third check wins
+    Atomic,                                                     // captures explode, blowing up the enemy king wins
+    Crazyhouse,                                                 // captured pieces can be dropped back on the board
+    Antichess,                                                  // captures are forced, losing every piece wins
+    Horde,                                                      // 36 white pawns without a king against the normal black army
+    RacingKings,                                                // no checks, the first king on the 8th rank wins
+}
+
+impl Variant {
+    // The name used in the PGN Variant tag.
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Atomic => "Atomic",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Antichess => "Antichess",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+        }
+    }
+
+    pub fn get_starting_fen(&self) -> &'static str {
+        match self {
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +0+0",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Horde => "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            _ => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+
+    // Accepts the PGN tag names and the usual short forms like koth and 3check.
+    pub fn from_name(name: &str) -> Option<Variant> {
+        let name: String = name.chars().filter(|character| character.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+        match name.as_str() {
+            "standard" | "chess" | "normal" | "chess960" | "fischerandom" | "fischerrandom" => Some(Variant::Standard),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "threecheck" | "3check" => Some(Variant::ThreeCheck),
+            "atomic" => Some(Variant::Atomic),
+            "crazyhouse" | "zh" => Some(Variant::Crazyhouse),
+            "antichess" | "giveaway" | "suicide" => Some(Variant::Antichess),
+            "horde" => Some(Variant::Horde),
+            "racingkings" | "racing" => Some(Variant::RacingKings),
+            _ => None,
+        }
+    }
+}
+
+// The color that has won by a rule of the variant, checkmate is left to the normal rules.
+pub fn variant_winner(game: &GAME) -> Option<u8> {
+    if let Some(winner) = winner_by_position(game) {
+        return Some(winner);
+    }
+    // being stalemated wins antichess
+    if game.variant == Variant::Antichess && game.generate_legal_moves().is_empty() {
+        return Some(game.turn);
+    }
+    if white_won_the_race(game) {
+        return Some(COLORS::WHITE);
+    }
+    None
+}
+
+// Both kings on the 8th rank in racing kings, black having equalised.
+pub fn is_variant_draw(game: &GAME) -> bool {
+    game.variant == Variant::RacingKings && king_on_last_rank(game, COLORS::WHITE) && king_on_last_rank(game, COLORS::BLACK)
+}
+
+// White reached the 8th rank first and black loses, its king can't follow right away. Worked out on the board
+// rather than with the legal moves since allows_move asks it before every move.
+fn white_won_the_race(game: &GAME) -> bool {
+    if game.variant != Variant::RacingKings || game.turn != COLORS::BLACK || !king_on_last_rank(game, COLORS::WHITE) {
+        return false;
+    }
+    let (black_king, white_king) = match (king_tile(game, COLORS::BLACK), king_tile(game, COLORS::WHITE)) {
+        (Some(black_king), Some(white_king)) => (black_king, white_king),
+        _ => return false,
+    };
+    let king_moves = available_moves_for_piece(game.board[black_king], black_king, &mut game.clone());
+    let black_can_follow = (0..8).filter(|to_tile| king_moves[*to_tile]).any(|to_tile| {
+        let mut board = game.board;
+        board[to_tile] = board[black_king];
+        board[black_king] = TYPES::NONE;
+        // neither king may be in check afterwards
+        !analysis::is_attacked(&board, to_tile, COLORS::WHITE) && !analysis::is_attacked(&board, white_king, COLORS::BLACK)
+    });
+    !black_can_follow
+}
+
+// The wins that can be seen on the board without looking at the moves.
+fn winner_by_position(game: &GAME) -> Option<u8> {
+    match game.variant {
+        Variant::Standard => None,
+        Variant::KingOfTheHill => {
+            HILL_TILES.iter().map(|tile| game.board[*tile]).find(|piece| piece & TYPES::KING > 0).map(|king| king & (COLORS::WHITE | COLORS::BLACK))
+        }
+        Variant::ThreeCheck => {
+            if game.checks_given[0] >= CHECKS_TO_WIN {
+                Some(COLORS::WHITE)
+            } else if game.checks_given[1] >= CHECKS_TO_WIN {
+                Some(COLORS::BLACK)
+            } else {
+                None
+            }
+        }
+        Variant::Crazyhouse => None,
+        Variant::Antichess => {
+            // only the side to move can have lost all its pieces
+            if game.board.iter().any(|piece| piece & game.turn > 0) { None } else { Some(game.turn) }
+        }
+        Variant::Horde => {
+            // the horde loses when all its pieces are taken, black only by checkmate
+            if game.board.iter().any(|piece| piece & COLORS::WHITE > 0) { None } else { Some(COLORS::BLACK) }
+        }
+        Variant::RacingKings => {
+            match (king_on_last_rank(game, COLORS::WHITE), king_on_last_rank(game, COLORS::BLACK)) {
+                (false, true) => Some(COLORS::BLACK),
+                (true, false) if game.turn == COLORS::WHITE => Some(COLORS::WHITE),      // black had its move to equalise
+                _ => None,
+            }
+        }
+        Variant::Atomic => {
+            if king_tile(game, COLORS::WHITE).is_none() {
+                Some(COLORS::BLACK)
+            } else if king_tile(game, COLORS::BLACK).is_none() {
+                Some(COLORS::WHITE)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+// Asked before every move, nothing can be played once a variant rule has ended the game.
+pub(crate) fn allows_move(game: &GAME, from_tile: usize, to_tile: usize) -> bool {
+    if winner_by_position(game).is_some() || is_variant_draw(game) || white_won_the_race(game) {
+        return false;
+    }
+    match game.variant {
+        // a king capturing would blow itself up
+        Variant::Atomic => !(game.board[from_tile] & TYPES::KING > 0 && game.board[to_tile] != TYPES::NONE && game.board[to_tile] & game.turn == 0),
+        // no castling, and a capture has to be played when there is one
+        Variant::Antichess => {
+            let chess_move = Move::new(from_tile, to_tile, TYPES::NONE);
+            !game.is_castling(&chess_move) && (game.is_capture(&chess_move) || !has_capture(game))
+        }
+        _ => true,
+    }
+}
+
+fn has_capture(game: &GAME) -> bool {
+    let mut game_copy = game.clone();
+    game.board.iter().enumerate().filter(|(_, piece)| *piece & game.turn > 0).any(|(from_tile, piece)| {
+        let moves = available_moves_for_piece(*piece, from_tile, &mut game_copy);
+        moves.iter().enumerate().any(|(to_tile, possible)| *possible && game.is_capture(&Move::new(from_tile, to_tile, TYPES::NONE)))
+    })
+}
+
+// Asked after every move with check of the side that didn't move known, in racing kings giving check isn't allowed.
+pub(crate) fn allows_position(game_after: &GAME) -> bool {
+    !(game_after.variant == Variant::RacingKings && game_after.check)
+}
+
+// In atomic the capturing piece and every piece but pawns next to the capture tile are blown up,
+// in crazyhouse the captured piece goes to the pocket of the capturer, as a pawn if it was promoted.
+pub(crate) fn after_capture(game: &mut GAME, to_tile: usize, captured_piece: u8, was_promoted: bool) {
+    if game.variant == Variant::Crazyhouse {
+        let piece_type = if was_promoted { TYPES::PAWN } else { captured_piece & !(COLORS::WHITE | COLORS::BLACK) };
+        if let Some(index) = POCKET_PIECES.iter().position(|pocket_piece| *pocket_piece == piece_type) {
+            let side = if game.turn == COLORS::WHITE { 0 } else { 1 };
+            game.pockets[side][index] += 1;
+        }
+        return;
+    }
+    if game.variant != Variant::Atomic {
+        return;
+    }
+    game.board[to_tile] = TYPES::NONE;
+    let (rank, file) = ((to_tile / 8) as i32, (to_tile % 8) as i32);
+    for rank_offset in -1..=1 {
+        for file_offset in -1..=1 {
+            let (neighbour_rank, neighbour_file) = (rank + rank_offset, file + file_offset);
+            if !(0..8).contains(&neighbour_rank) || !(0..8).contains(&neighbour_file) {
+                continue;
+            }
+            let tile = (neighbour_rank * 8 + neighbour_file) as usize;
+            if game.board[tile] & TYPES::PAWN == 0 {
+                game.board[tile] = TYPES::NONE;
+            }
+        }
+    }
+    for right in 0..4 {
+        let color = if right < 2 { COLORS::WHITE } else { COLORS::BLACK };
+        if game.board[game.chastling_rooks[right]] & TYPES::ROOK == 0 || king_tile(game, color).is_none() {
+            game.chastling_ability[right] = false;
+        }
+    }
+}
+
+// Positions where check doesn't apply, antichess has no check at all. In atomic a missing king ends the game and
+// kings standing next to each other can't be checked since neither can capture.
+pub(crate) fn ignores_check(game: &GAME) -> bool {
+    match game.variant {
+        Variant::Antichess => true,
+        Variant::Atomic => match (king_tile(game, COLORS::WHITE), king_tile(game, COLORS::BLACK)) {
+            (Some(white_king), Some(black_king)) => {
+                let file_distance = (white_king % 8) as i32 - (black_king % 8) as i32;
+                let rank_distance = (white_king / 8) as i32 - (black_king / 8) as i32;
+                file_distance.abs() <= 1 && rank_distance.abs() <= 1
+            }
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+// A move losing your own king is never allowed, even when it blows up the enemy king too.
+pub(crate) fn royal_king_lost(game: &GAME, color: u8) -> bool {
+    match game.variant {
+        Variant::Atomic => king_tile(game, color).is_none(),
+        _ => false,
+    }
+}
+
+fn king_on_last_rank(game: &GAME, color: u8) -> bool {
+    matches!(king_tile(game, color), Some(tile) if tile < 8)
+}
+
+fn king_tile(game: &GAME, color: u8) -> Option<usize> {
+    analysis::king_tile(&game.board, color)
+}
+
+// Updates the variant state once a move is on the board and check is known.
+pub(crate) fn after_move(game: &mut GAME, mover: u8) {
+    if game.variant == Variant::ThreeCheck && game.check {
+        let side = if mover == COLORS::WHITE { 0 } else { 1 };
+        game.checks_given[side] += 1;
+    }
+}
+
+// The +N+M field of three-check FENs with the checks given by white and black.
+pub(crate) fn checks_field(game: &GAME) -> String {
+    format!("+{}+{}", game.checks_given[0], game.checks_given[1])
+}
+
+// Reads +N+M (checks given) or the older N+M (checks remaining), None if it's neither or a count is above three.
+pub(crate) fn parse_checks_field(field: &str) -> Option<[u8; 2]> {
+    let given = field.starts_with('+');
+    let mut counts = field.trim_start_matches('+').split('+');
+    let white: u8 = counts.next()?.parse().ok()?;
+    let black: u8 = counts.next()?.parse().ok()?;
+    if counts.next().is_some() || white > CHECKS_TO_WIN || black > CHECKS_TO_WIN {
+        return None;
+    }
+    if given {
+        Some([white, black])
+    } else {
+        Some([CHECKS_TO_WIN.saturating_sub(white), CHECKS_TO_WIN.saturating_sub(black)])
+    }
+}
+
+// The [pocket] after the pieces in crazyhouse FENs, white pieces in upper case first, like [QNpp].
+pub(crate) fn pocket_field(game: &GAME) -> String {
+    let mut field = String::from("[");
+    for (side, symbols) in ["PNBRQ", "pnbrq"].iter().enumerate() {
+        for (index, symbol) in symbols.chars().enumerate() {
+            for _ in 0..game.pockets[side][index] {
+                field.push(symbol);
+            }
+        }
+    }
+    field.push(']');
+    field
+}
+
+pub(crate) fn parse_pocket_field(field: &str) -> [[u8; 5]; 2] {
+    let mut pockets = [[0; 5]; 2];
+    for character in field.chars() {
+        let side = if character.is_ascii_uppercase() { 0 } else { 1 };
+        if let Some(index) = "pnbrq".find(character.to_ascii_lowercase()) {
+            pockets[side][index] += 1;
+        }
+    }
+    pockets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algebraic_notation_to_memory_location, drop_piece, init_game_from_fen, make_move};
+
+    fn tile(name: &str) -> usize {
+        algebraic_notation_to_memory_location(name)
+    }
+
+    fn play(game: &mut GAME, from: &str, to: &str) {
+        assert!(make_move(&Move::new(tile(from), tile(to), TYPES::NONE), game), "{}{} in {}", from, to, game.get_fen());
+    }
+
+    #[test]
+    fn crazyhouse_capture_goes_to_the_pocket_of_the_capturer() {
+        let mut game = init_game_from_fen("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR[] w KQkq - 0 2");
+        play(&mut game, "e4", "d5");
+        assert_eq!(game.get_pocket(COLORS::WHITE), [1, 0, 0, 0, 0]);
+        assert_eq!(game.get_pocket(COLORS::BLACK), [0; 5]);
+        play(&mut game, "d8", "d5");
+        assert_eq!(game.get_pocket(COLORS::BLACK), [1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn crazyhouse_promoted_piece_goes_back_as_a_pawn() {
+        let mut game = init_game_from_fen("4k2r/8/8/8/8/8/8/4K2Q~[] b - - 0 1");
+        assert!(game.is_promoted(tile("h1")));
+        play(&mut game, "h8", "h1");
+        assert_eq!(game.get_pocket(COLORS::BLACK), [1, 0, 0, 0, 0]);
+        assert!(!game.is_promoted(tile("h1")));
+
+        let mut game = init_game_from_fen("4k2r/8/8/8/8/8/8/4K2Q[] b - - 0 1");
+        play(&mut game, "h8", "h1");
+        assert_eq!(game.get_pocket(COLORS::BLACK), [0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn crazyhouse_promotion_is_marked() {
+        let mut game = init_game_from_fen("4k3/P7/8/8/8/8/8/4K3[] w - - 0 1");
+        assert!(make_move(&Move::new(tile("a7"), tile("a8"), TYPES::QUEEN), &mut game));
+        assert!(game.is_promoted(tile("a8")));
+        assert_eq!(game.get_fen(), "Q~3k3/8/8/8/8/8/8/4K3[] b - - 0 1");
+    }
+
+    #[test]
+    fn crazyhouse_illegal_drops() {
+        let game = init_game_from_fen("4k3/8/8/8/8/8/8/4K3[PN] w - - 0 1");
+        for back_rank_tile in [tile("a1"), tile("d8")] {
+            assert!(!drop_piece(TYPES::PAWN, back_rank_tile, &mut game.clone()));
+        }
+        assert!(!drop_piece(TYPES::KNIGHT, tile("e1"), &mut game.clone()));
+        assert!(!drop_piece(TYPES::QUEEN, tile("d4"), &mut game.clone()));
+        assert!(!game.generate_legal_moves().iter().any(|chess_move| chess_move.drop == TYPES::PAWN && (chess_move.to < 8 || chess_move.to > 55)));
+
+        let mut dropped = game.clone();
+        assert!(drop_piece(TYPES::PAWN, tile("d4"), &mut dropped));
+        assert_eq!(dropped.get_pocket(COLORS::WHITE), [0, 1, 0, 0, 0]);
+        assert_eq!(dropped.get_fen(), "4k3/8/8/8/3P4/8/8/4K3[N] b - - 1 1");
+
+        let mut standard = init_game_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert!(!drop_piece(TYPES::PAWN, tile("d4"), &mut standard));
+    }
+
+    #[test]
+    fn crazyhouse_fen_round_trip() {
+        let fen = "r1bqk2r/pppp1ppp/2n5/2b1p3/2B1P3/5Q~2/PPPP1PPP/RNB1K2R[NPb] w KQkq - 3 7";
+        let game = init_game_from_fen(fen);
+        assert_eq!(game.get_variant(), Variant::Crazyhouse);
+        assert!(game.is_promoted(tile("f3")));
+        assert_eq!(game.get_pocket(COLORS::WHITE), [1, 1, 0, 0, 0]);
+        assert_eq!(game.get_pocket(COLORS::BLACK), [0, 0, 1, 0, 0]);
+        assert_eq!(game.get_fen(), "r1bqk2r/pppp1ppp/2n5/2b1p3/2B1P3/5Q~2/PPPP1PPP/RNB1K2R[PNb] w KQkq - 3 7");
+        assert_eq!(init_game_from_fen(&game.get_fen()).get_fen(), game.get_fen());
+    }
+
+    fn racing_kings(fen: &str) -> GAME {
+        let mut game = init_game_from_fen(fen);
+        game.set_variant(Variant::RacingKings);
+        game
+    }
+
+    #[test]
+    fn racing_kings_no_moves_once_black_cannot_follow() {
+        let game = racing_kings("K7/8/7k/8/8/8/8/8 b - - 0 1");
+        assert_eq!(game.get_variant_winner(), Some(COLORS::WHITE));
+        assert!(game.generate_legal_moves().is_empty());
+        assert!(!make_move(&Move::new(tile("h6"), tile("h7"), TYPES::NONE), &mut game.clone()));
+    }
+
+    #[test]
+    fn racing_kings_black_can_still_equalise() {
+        let mut game = racing_kings("K7/7k/8/8/8/8/8/8 b - - 0 1");
+        assert_eq!(game.get_variant_winner(), None);
+        assert!(!game.generate_legal_moves().is_empty());
+        play(&mut game, "h7", "h8");
+        assert!(game.is_variant_draw());
+    }
+
+    fn variant_game(fen: &str, variant: Variant) -> GAME {
+        let mut game = init_game_from_fen(fen);
+        game.set_variant(variant);
+        game
+    }
+
+    #[test]
+    fn king_of_the_hill_king_on_the_hill_wins() {
+        let mut game = variant_game("4k3/8/8/8/8/4K3/8/8 w - - 0 1", Variant::KingOfTheHill);
+        play(&mut game, "e3", "d3");
+        assert_eq!(game.get_variant_winner(), None);
+        play(&mut game, "e8", "d7");
+        play(&mut game, "d3", "e4");
+        assert_eq!(game.get_variant_winner(), Some(COLORS::WHITE));
+        assert!(game.generate_legal_moves().is_empty());
+        assert_eq!(game.get_outcome().unwrap().describe(), "white wins by variant rules");
+
+        // any of d4, d5, e4 and e5 counts, a king next to them doesn't
+        assert_eq!(variant_game("8/8/8/3k4/8/8/8/K7 w - - 0 1", Variant::KingOfTheHill).get_variant_winner(), Some(COLORS::BLACK));
+        assert_eq!(variant_game("8/8/4k3/8/8/8/8/K7 w - - 0 1", Variant::KingOfTheHill).get_variant_winner(), None);
+        assert_eq!(variant_game("8/8/8/3k4/8/8/8/K7 w - - 0 1", Variant::Standard).get_variant_winner(), None);
+    }
+
+    #[test]
+    fn three_check_fields() {
+        assert_eq!(parse_checks_field("+1+2"), Some([1, 2]));                  // checks given
+        assert_eq!(parse_checks_field("2+1"), Some([1, 2]));                   // checks remaining
+        assert_eq!(parse_checks_field("+3+0"), Some([3, 0]));
+        assert_eq!(parse_checks_field("+4+0"), None);
+        assert_eq!(parse_checks_field("+1+1+1"), None);
+        assert_eq!(parse_checks_field("+x+1"), None);
+        assert_eq!(parse_checks_field("+1"), None);
+
+        let game = init_game_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +2+0");
+        assert_eq!(game.get_variant(), Variant::ThreeCheck);
+        assert_eq!(game.get_checks_given(), [2, 0]);
+        assert_eq!(game.get_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 +2+0");
+        let game = init_game_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 1+3 0 1");
+        assert_eq!(game.get_checks_given(), [2, 0]);
+    }
+
+    #[test]
+    fn three_check_third_check_wins() {
+        let mut game = init_game_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 +2+0");
+        play(&mut game, "a1", "a7");
+        assert_eq!(game.get_checks_given(), [2, 0]);
+        play(&mut game, "e8", "f8");
+        play(&mut game, "a7", "a8");
+        assert_eq!(game.get_checks_given(), [3, 0]);
+        assert_eq!(game.get_variant_winner(), Some(COLORS::WHITE));
+        assert!(game.generate_legal_moves().is_empty());
+        assert!(game.get_fen().ends_with("+3+0"));
+
+        // a check in standard chess isn't counted
+        let mut game = init_game_from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        play(&mut game, "a1", "a8");
+        assert_eq!(game.get_checks_given(), [0, 0]);
+    }
+
+    #[test]
+    fn atomic_explosion_spares_pawns() {
+        let mut game = variant_game("4k3/8/3p1b2/4n3/4P3/5N2/8/4K3 w - - 0 1", Variant::Atomic);
+        play(&mut game, "f3", "e5");
+        assert_eq!(game.get_fen(), "4k3/8/3p4/8/4P3/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn atomic_king_may_not_capture() {
+        let game = variant_game("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1", Variant::Atomic);
+        let capture = Move::new(tile("e1"), tile("e2"), TYPES::NONE);
+        assert!(!game.generate_legal_moves().contains(&capture));
+        assert!(!make_move(&capture, &mut game.clone()));
+
+        // nor may a capture blow up the own king
+        let game = variant_game("4k3/8/8/8/8/8/3r4/3QK3 w - - 0 1", Variant::Atomic);
+        assert!(!game.generate_legal_moves().contains(&Move::new(tile("d1"), tile("d2"), TYPES::NONE)));
+    }
+
+    #[test]
+    fn atomic_connected_kings_ignore_checks() {
+        let game = variant_game("8/8/8/8/3k4/3K3r/8/8 w - - 0 1", Variant::Atomic);
+        assert!(!game.is_check());
+        let legal_moves = game.generate_legal_moves();
+        assert!(legal_moves.contains(&Move::new(tile("d3"), tile("e3"), TYPES::NONE)));        // still next to the black king
+        // apart the rook checks again
+        let game = variant_game("8/8/8/3k4/8/3K3r/8/8 w - - 0 1", Variant::Atomic);
+        assert!(game.is_check());
+    }
+
+    #[test]
+    fn atomic_exploded_king_ends_the_game() {
+        let mut game = variant_game("4k3/4p3/8/8/8/8/8/4QK2 w - - 0 1", Variant::Atomic);
+        play(&mut game, "e1", "e7");
+        assert_eq!(game.get_fen(), "8/8/8/8/8/8/8/5K2 b - - 0 1");
+        assert_eq!(game.get_variant_winner(), Some(COLORS::WHITE));
+        assert!(game.generate_legal_moves().is_empty());
+        assert_eq!(game.get_outcome().unwrap().describe(), "white wins by variant rules");
+    }
+
+    #[test]
+    fn antichess_captures_are_forced() {
+        let game = variant_game("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", Variant::Antichess);
+        assert!(has_capture(&game));
+        assert_eq!(game.generate_legal_moves(), [Move::new(tile("e4"), tile("d5"), TYPES::NONE)]);
+        assert!(!make_move(&Move::new(tile("e4"), tile("e5"), TYPES::NONE), &mut game.clone()));
+
+        let game = variant_game("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1", Variant::Antichess);
+        assert!(!has_capture(&game));
+        assert!(game.generate_legal_moves().len() > 2);
+    }
+
+    #[test]
+    fn antichess_pawns_promote_to_kings() {
+        let mut game = variant_game("8/P7/8/8/8/8/8/7k w - - 0 1", Variant::Antichess);
+        let mut promotions = game.pending_promotion(tile("a7"), tile("a8"));
+        promotions.sort();
+        assert_eq!(promotions, [TYPES::KNIGHT, TYPES::BISHOP, TYPES::ROOK, TYPES::QUEEN, TYPES::KING]);
+        assert!(make_move(&Move::new(tile("a7"), tile("a8"), TYPES::KING), &mut game));
+        assert_eq!(game.get_fen(), "K7/8/8/8/8/8/8/7k b - - 0 1");
+    }
+
+    #[test]
+    fn antichess_losing_every_piece_wins() {
+        let mut game = variant_game("8/8/8/8/8/8/8/r6R w - - 0 1", Variant::Antichess);
+        assert_eq!(game.get_variant_winner(), None);
+        play(&mut game, "h1", "a1");
+        assert_eq!(game.get_variant_winner(), Some(COLORS::BLACK));
+        assert_eq!(game.get_outcome().unwrap().describe(), "black wins by variant rules");
+    }
+
+    #[test]
+    fn antichess_being_stalemated_wins() {
+        let game = variant_game("8/8/8/8/8/p7/P7/8 w - - 0 1", Variant::Antichess);
+        assert!(game.generate_legal_moves().is_empty());
+        assert_eq!(game.get_variant_winner(), Some(COLORS::WHITE));
+        assert_eq!(game.get_outcome().unwrap().describe(), "white wins by variant rules");
+    }
+}
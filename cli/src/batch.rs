@@ -0,0 +1,195 @@
+use chess_logic::*;
+use chess_logic::pgn::move_to_san;
+use chess_logic::validation::game_from_fen;
+use chess_logic::variant::Variant;
+
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use crate::commands::parse_move;
+
+// cli --batch reads one command per line and answers each with one line of JSON, without drawing:
+//   position startpos|<fen> [moves <move>...]   {"fen":"..."}
+//   move <move>...                               {"fen":"..."}
+//   legal                                        {"legal":["e2e4",...],"san":["e4",...]}
+//   fen                                          {"fen":"..."}
+//   status                                       {"turn":"white","check":false,"result":null,"reason":null,...}
+// Moves are SAN or UCI, none once the game is over. Anything that fails gets {"error":"..."}, with --strict
+// the cli then exits with 1. An unknown --variant is an error before any command is read.
+pub fn run(args: &[String]) {
+    let strict = args.iter().any(|arg| arg == "--strict");
+    let variant = match args.iter().position(|arg| arg == "--variant") {
+        Some(index) => match args.get(index + 1).and_then(|name| Variant::from_name(name)) {
+            Some(variant) => variant,
+            None => {
+                let message = format!("unknown variant {}, the variants are standard, koth, 3check, atomic, crazyhouse, antichess, horde and racingkings",
+                                      args.get(index + 1).map(String::as_str).unwrap_or(""));
+                println!("{{\"error\":{}}}", json_string(&message));
+                process::exit(2);
+            }
+        },
+        None => Variant::Standard,
+    };
+    let mut game = init_variant_game(variant);
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        let response = execute(line, &mut game, variant);
+        let failed = response.is_err();
+        let json = response.unwrap_or_else(|message| format!("{{\"error\":{}}}", json_string(&message)));
+        if writeln!(output, "{}", json).and_then(|_| output.flush()).is_err() {
+            break;
+        }
+        if failed && strict {
+            process::exit(1);
+        }
+    }
+}
+
+fn execute(line: &str, game: &mut GAME, variant: Variant) -> Result<String, String> {
+    let (command, argument) = match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+    match command {
+        "position" => {
+            let (position, moves) = match argument.find("moves") {
+                Some(index) => (argument[..index].trim(), argument[index + "moves".len()..].trim()),
+                None => (argument, ""),
+            };
+            let mut new_game = match position.strip_prefix("fen").unwrap_or(position).trim() {
+                "" => return Err("position needs startpos or a FEN".to_string()),
+                "startpos" => init_variant_game(variant),
+                fen => game_from_fen(fen, variant)?,
+            };
+            play_moves(moves, &mut new_game)?;
+            *game = new_game;
+            Ok(fen_response(game))
+        }
+        "move" | "moves" => {
+            let mut new_game = game.clone();
+            play_moves(argument, &mut new_game)?;
+            *game = new_game;
+            Ok(fen_response(game))
+        }
+        "legal" => {
+            let legal_moves = game.generate_legal_moves();
+            let uci: Vec<String> = legal_moves.iter().map(|chess_move| json_string(&chess_move.to_uci())).collect();
+            let san: Vec<String> = legal_moves.iter().map(|chess_move| json_string(&move_to_san(chess_move, game))).collect();
+            Ok(format!("{{\"legal\":[{}],\"san\":[{}]}}", uci.join(","), san.join(",")))
+        }
+        "fen" => Ok(fen_response(game)),
+        "status" => Ok(status_response(game)),
+        _ => Err(format!("unknown command {}, the commands are position, move, legal, fen and status", command)),
+    }
+}
+
+fn play_moves(moves: &str, game: &mut GAME) -> Result<(), String> {
+    for input in moves.split_whitespace() {
+        if let Some(outcome) = game.get_outcome() {
+            return Err(format!("no move {}, the game is over {} by {}", input, outcome.result.to_pgn(), outcome.reason.get_name()));
+        }
+        let chess_move = parse_move(input, game).map_err(|_| format!("illegal move {} in {}", input, game.get_fen()))?;
+        if !make_move(&chess_move, game) {
+            return Err(format!("illegal move {} in {}", input, game.get_fen()));
+        }
+    }
+    Ok(())
+}
+
+fn fen_response(game: &GAME) -> String {
+    format!("{{\"fen\":{}}}", json_string(&game.get_fen()))
+}
+
+fn status_response(game: &GAME) -> String {
+    let legal_moves = game.generate_legal_moves().len();
+    let outcome = game.get_outcome();
+    let result = outcome.map(|outcome| outcome.result.to_pgn());
+    let reason = outcome.map(|outcome| outcome.reason.get_name());
+    let optional = |value: Option<&str>| value.map_or("null".to_string(), json_string);
+    format!("{{\"turn\":\"{}\",\"check\":{},\"legal_moves\":{},\"halfmove_clock\":{},\"fullmove_number\":{},\"result\":{},\"reason\":{}}}",
+            if game.is_whites_turn() { "white" } else { "black" }, game.is_check(), legal_moves,
+            game.get_halfmove_clock(), game.get_fullmove_number(), optional(result), optional(reason))
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_lines(lines: &[&str]) -> Vec<Result<String, String>> {
+        let mut game = init_game();
+        lines.iter().map(|line| execute(line, &mut game, Variant::Standard)).collect()
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("e4"), "\"e4\"");
+        assert_eq!(json_string("say \"hi\" \\ bye\n"), r#""say \"hi\" \\ bye\n""#);
+        assert_eq!(json_string("\u{1}\t"), r#""\u0001\u0009""#);
+    }
+
+    #[test]
+    fn positions_and_moves() {
+        let responses = run_lines(&["position startpos moves e2e4 e5", "move Nf3", "fen"]);
+        assert_eq!(responses[0], Ok("{\"fen\":\"rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2\"}".to_string()));
+        assert_eq!(responses[2], Ok("{\"fen\":\"rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2\"}".to_string()));
+
+        let responses = run_lines(&["position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1", "legal"]);
+        assert!(responses[1].as_ref().unwrap().starts_with("{\"legal\":[\""));
+        assert!(responses[1].as_ref().unwrap().contains("\"e1g1\""));
+        assert!(responses[1].as_ref().unwrap().contains("\"O-O\""));
+    }
+
+    #[test]
+    fn errors_leave_the_game_alone() {
+        let responses = run_lines(&["move e4 e5 Ke3", "fen", "position fen 8/8/8/8/8/8/8/8 w - - 0 1", "jump", "position"]);
+        assert_eq!(responses[0], Err("illegal move Ke3 in rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2".to_string()));
+        assert_eq!(responses[1], Ok(fen_response(&init_game())));
+        assert!(responses[2].as_ref().unwrap_err().starts_with("illegal position"));
+        assert!(responses[3].as_ref().unwrap_err().starts_with("unknown command jump"));
+        assert_eq!(responses[4], Err("position needs startpos or a FEN".to_string()));
+    }
+
+    #[test]
+    fn status_fields() {
+        let responses = run_lines(&["status", "position startpos moves f3 e5 g4 Qh4", "status"]);
+        assert_eq!(responses[0], Ok("{\"turn\":\"white\",\"check\":false,\"legal_moves\":20,\"halfmove_clock\":0,\"fullmove_number\":1,\"result\":null,\"reason\":null}".to_string()));
+        assert_eq!(responses[2], Ok("{\"turn\":\"white\",\"check\":true,\"legal_moves\":0,\"halfmove_clock\":1,\"fullmove_number\":3,\"result\":\"0-1\",\"reason\":\"checkmate\"}".to_string()));
+    }
+
+    #[test]
+    fn no_moves_after_the_game_is_over() {
+        let responses = run_lines(&["position startpos moves f3 e5 g4 Qh4", "move a3"]);
+        assert_eq!(responses[1], Err("no move a3, the game is over 0-1 by checkmate".to_string()));
+        let responses = run_lines(&["position startpos moves f3 e5 g4 Qh4 a3"]);
+        assert!(responses[0].is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use chess_logic::book::BookBuilder;
+use chess_logic::pgn::read_pgn_file;
+
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: build_book [--depth PLIES] [--min OCCURRENCES] [--win W] [--draw D] [--loss L] -o OUT.bin GAMES.pgn...";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut builder = BookBuilder::new();
+    let mut output_path = String::new();
+    let mut pgn_paths = Vec::new();
+
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        if arg.starts_with('-') {
+            let value = match args.get(index + 1) {
+                Some(value) => value.clone(),
+                None => exit_with_usage(&format!("missing value for {}", arg)),
+            };
+            match arg {
+                "-o" | "--output" => output_path = value,
+                "--depth" => builder.max_ply = parse_number(arg, &value) as usize,
+                "--min" => builder.min_occurrences = parse_number(arg, &value),
+                "--win" => builder.win_weight = parse_number(arg, &value),
+                "--draw" => builder.draw_weight = parse_number(arg, &value),
+                "--loss" => builder.loss_weight = parse_number(arg, &value),
+                _ => exit_with_usage(&format!("unknown option {}", arg)),
+            }
+            index += 2;
+        } else {
+            pgn_paths.push(args[index].clone());
+            index += 1;
+        }
+    }
+
+    if output_path.is_empty() || pgn_paths.is_empty() {
+        exit_with_usage("an output file and at least one pgn file are needed");
+    }
+
+    let mut added_games = 0;
+    let mut skipped_games = 0;
+    for path in &pgn_paths {
+        let games = match read_pgn_file(path) {
+            Ok(games) => games,
+            Err(error) => {
+                eprintln!("could not read {}: {}", path, error);
+                process::exit(1);
+            }
+        };
+        for game in &games {
+            if builder.add_game(game) {
+                added_games += 1;
+            } else {
+                skipped_games += 1;
+            }
+        }
+    }
+
+    match builder.write(&output_path) {
+        Ok(entry_count) => println!("{} games read, {} skipped, {} entries written to {}", added_games, skipped_games, entry_count, output_path),
+        Err(error) => {
+            eprintln!("could not write {}: {}", output_path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> u32 {
+    match value.parse::<u32>() {
+        Ok(number) => number,
+        Err(_) => exit_with_usage(&format!("{} expects a number, got {}", option, value)),
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
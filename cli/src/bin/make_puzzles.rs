@@ -0,0 +1,92 @@
+use chess_logic::engine::MATE_SCORE;
+use chess_logic::pgn::read_pgn_file;
+use chess_logic::puzzle::{write_puzzle_file, PuzzleGenerator};
+
+use std::env;
+use std::ops::RangeInclusive;
+use std::process;
+
+const USAGE: &str = "usage: make_puzzles [--depth N] [--blunder CENTIPAWNS] [--winning CENTIPAWNS] [--moves N] -o OUT.txt GAMES.pgn...";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut generator = PuzzleGenerator::new();
+    let mut output_path = String::new();
+    let mut pgn_paths = Vec::new();
+
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+        if arg.starts_with('-') {
+            let value = match args.get(index + 1) {
+                Some(value) => value.clone(),
+                None => exit_with_usage(&format!("missing value for {}", arg)),
+            };
+            match arg {
+                "-o" | "--output" => output_path = value,
+                "--depth" => generator.depth = parse_in_range(arg, &value, 1..=u8::MAX as u32) as u8,
+                "--blunder" => generator.blunder_threshold = parse_in_range(arg, &value, 1..=MATE_SCORE as u32) as i32,
+                "--winning" => generator.winning_score = parse_in_range(arg, &value, 1..=MATE_SCORE as u32) as i32,
+                "--moves" => generator.max_solution_moves = parse_in_range(arg, &value, 1..=u32::MAX) as usize,
+                _ => exit_with_usage(&format!("unknown option {}", arg)),
+            }
+            index += 2;
+        } else {
+            pgn_paths.push(args[index].clone());
+            index += 1;
+        }
+    }
+
+    if output_path.is_empty() || pgn_paths.is_empty() {
+        exit_with_usage("an output file and at least one pgn file are needed");
+    }
+
+    let mut puzzles = Vec::new();
+    let mut game_count = 0;
+    for path in &pgn_paths {
+        let games = match read_pgn_file(path) {
+            Ok(games) => games,
+            Err(error) => {
+                eprintln!("could not read {}: {}", path, error);
+                process::exit(1);
+            }
+        };
+        for game in &games {
+            game_count += 1;
+            for puzzle in generator.puzzles_from_game(game) {
+                println!("{}", puzzle.to_line());
+                puzzles.push(puzzle);
+            }
+        }
+    }
+
+    match write_puzzle_file(&output_path, &puzzles) {
+        Ok(()) => println!("{} games read, {} puzzles written to {}", game_count, puzzles.len(), output_path),
+        Err(error) => {
+            eprintln!("could not write {}: {}", output_path, error);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_number(option: &str, value: &str) -> u32 {
+    match value.parse::<u32>() {
+        Ok(number) => number,
+        Err(_) => exit_with_usage(&format!("{} expects a number, got {}", option, value)),
+    }
+}
+
+// The casts after it can't wrap, the ranges fit the fields.
+fn parse_in_range(option: &str, value: &str, range: RangeInclusive<u32>) -> u32 {
+    let number = parse_number(option, value);
+    if !range.contains(&number) {
+        exit_with_usage(&format!("{} goes from {} to {}", option, range.start(), range.end()));
+    }
+    number
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
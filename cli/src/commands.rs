@@ -0,0 +1,578 @@
+use chess_logic::*;
+use chess_logic::book::Book;
+use chess_logic::clock::{format_time, Clock};
+use chess_logic::engine::{Engine, MATE_SCORE, TABLEBASE_WIN_SCORE};
+use chess_logic::outcome::{GameEvent, GameOutcome, GameRecord};
+use chess_logic::pgn::{self, move_to_san, san_to_move};
+use chess_logic::syzygy::Tablebase;
+use chess_logic::validation::game_from_fen;
+use chess_logic::variant::Variant;
+
+use std::fs;
+
+use crate::{print_book_moves, print_tablebase_verdict, print_tactics, TACTICS_DEPTH};
+
+const HELP: &str = "commands:
+  <move>          play a move in SAN (Nf3, exd5, e8=Q, O-O, N@f3) or UCI (g1f3, e7e8q)
+  moves [square]  list the legal moves, or those of the piece on the square
+  undo, redo      take back a move or play it again
+  new [fen]       start a new game, from the FEN if one is given
+  fen, pgn        print the position as FEN or the game as PGN
+  save <file>     write the game to a PGN file
+  load <file>     read the first game of a PGN file
+  flip            turn the board around
+  resign          resign for the side to move
+  draw            offer a draw for the side to move, moving on declines it
+  accept, decline answer a draw offer
+  book, tb        show the opening book moves or the tablebase verdict
+  tactics         show the engine's move and the tactics behind it
+  hint            ask the engine for a move
+  eval            turn the evaluation after the engine's moves on or off
+  help            show this list
+  quit, exit      leave";
+
+// A game played in the cli with what's needed to take moves back and write it as PGN.
+pub struct Session {
+    pub flipped: bool,
+    record: GameRecord,                                         // the moves, draw offers and how the game ended
+    redo: Vec<Move>,
+    book: Option<Book>,
+    tablebase: Option<Tablebase>,
+    engine: Option<Engine>,                                     // the opponent with cli --play
+    engine_color: u8,
+    clock: Option<Clock>,                                       // the time control with cli --clock
+    pub show_eval: bool,
+}
+
+impl Session {
+    pub fn new(game: GAME, book: Option<Book>, tablebase: Option<Tablebase>) -> Session {
+        Session {
+            flipped: false,
+            record: GameRecord::new(game),
+            redo: Vec::new(),
+            book,
+            tablebase,
+            engine: None,
+            engine_color: COLORS::BLACK,
+            clock: None,
+            show_eval: false,
+        }
+    }
+
+    // The engine plays the pieces of engine_color, undo and redo then go back and forth a move of each side.
+    pub fn with_engine(mut self, engine: Engine, engine_color: u8) -> Session {
+        self.engine = Some(engine);
+        self.engine_color = engine_color;
+        self
+    }
+
+    // Plays with a clock, it starts for the side to move right away.
+    pub fn with_clock(mut self, clock: Clock) -> Session {
+        self.clock = Some(clock);
+        self.sync_clock();
+        self
+    }
+
+    pub fn get_game(&self) -> &GAME {
+        self.record.get_game()
+    }
+
+    // Lets the engine move when it's its turn and the game isn't over.
+    pub fn engine_turn(&mut self) {
+        self.check_flag();
+        let engine = match &self.engine {
+            Some(engine) if self.is_engine_turn() && self.get_outcome().is_none() => engine,
+            _ => return,
+        };
+        let result = engine.search(self.get_game());
+        let chess_move = match result.best_move {
+            Some(chess_move) => chess_move,
+            None => return,
+        };
+        println!("engine plays {}", move_to_san(&chess_move, self.get_game()));
+        if self.show_eval && !result.from_book {
+            let white_score = if self.engine_color == COLORS::WHITE { result.score } else { -result.score };
+            println!("eval {} (depth {})", describe_score(white_score), result.depth);
+        }
+        self.play(chess_move);
+    }
+
+    fn is_engine_turn(&self) -> bool {
+        self.engine.is_some() && self.get_game().is_whites_turn() == (self.engine_color == COLORS::WHITE)
+    }
+
+    // Runs one line of input, false when the cli should stop.
+    pub fn execute(&mut self, line: &str) -> bool {
+        self.check_flag();
+        let line = line.trim();
+        let (command, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => {}
+            "quit" | "exit" => return false,
+            "help" => println!("{}", HELP),
+            "moves" => self.print_moves(argument),
+            "undo" => self.undo(),
+            "redo" => self.redo(),
+            "new" => self.new_game(argument),
+            "fen" => println!("{}", self.get_game().get_fen()),
+            "pgn" => print!("{}", pgn::write_pgn(&self.to_pgn())),
+            "save" => self.save(argument),
+            "load" => self.load(argument),
+            "flip" => self.flipped = !self.flipped,
+            "resign" => self.resign(),
+            "draw" => self.offer_draw(),
+            "accept" | "decline" => self.answer_draw(command == "accept"),
+            "book" => print_book_moves(&self.book, self.get_game()),
+            "tb" => print_tablebase_verdict(&self.tablebase, self.get_game()),
+            "tactics" => print_tactics(self.get_game()),
+            "hint" => self.print_hint(),
+            "eval" => {
+                self.show_eval = !self.show_eval;
+                println!("evaluation {}", if self.show_eval { "on" } else { "off" });
+            }
+            _ => match (parse_move(line, self.get_game()), self.get_outcome()) {
+                (_, Some(outcome)) => println!("the game is over ({}), type new or undo", outcome.result.to_pgn()),
+                (Ok(chess_move), None) => self.play(chess_move),
+                (Err(message), None) => println!("{}", message),
+            },
+        }
+        true
+    }
+
+    pub fn play(&mut self, chess_move: Move) {
+        if let Err(message) = self.record.play(chess_move) {
+            println!("{}", message);
+            return;
+        }
+        self.redo.clear();
+        if let Some(clock) = &mut self.clock {
+            clock.press();
+        }
+        self.sync_clock();
+    }
+
+    // A fallen flag ends the game the next time the cli looks, between the lines typed.
+    fn check_flag(&mut self) {
+        if let Some(flagged) = self.clock.as_ref().and_then(|clock| clock.flagged()) {
+            if self.get_outcome().is_none() {
+                let _ = self.record.lose_on_time(flagged);
+            }
+        }
+    }
+
+    // The clock runs for the side to move while the game goes on and stands still once it's over.
+    fn sync_clock(&mut self) {
+        let running = self.get_outcome().is_none();
+        let color = if self.get_game().is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+        if let Some(clock) = &mut self.clock {
+            if !running {
+                clock.stop();
+            } else if clock.running() != Some(color) {
+                clock.start(color);
+            }
+        }
+    }
+
+    pub fn last_move(&self) -> Option<Move> {
+        self.record.last_move()
+    }
+
+    // Set once the game is over, by the rules, by the players or by the clock.
+    pub fn get_outcome(&self) -> Option<GameOutcome> {
+        self.record.get_outcome()
+    }
+
+    pub fn print_status(&self) {
+        let game = self.get_game();
+        let no_moves = game.generate_legal_moves().is_empty();
+        if game.is_check() && no_moves {
+            println!("Checkmate!");
+        } else if game.is_check() {
+            println!("Check!!!");
+        } else if no_moves && game.get_variant_winner().is_none() {
+            println!("Stalemate");
+        }
+        if game.get_variant() == Variant::Crazyhouse {
+            println!("pockets: white {:?}, black {:?} (pawns, knights, bishops, rooks, queens)", game.get_pocket(COLORS::WHITE), game.get_pocket(COLORS::BLACK));
+        }
+        if game.get_variant() == Variant::ThreeCheck {
+            let checks_given = game.get_checks_given();
+            println!("checks given: white {}, black {}", checks_given[0], checks_given[1]);
+        }
+        if let (Some(offering), None) = (self.record.get_draw_offer(), self.get_outcome()) {
+            println!("{} offers a draw, accept or decline", if offering == COLORS::WHITE { "white" } else { "black" });
+        }
+        if let Some(clock) = &self.clock {
+            println!("white {}  black {}", format_time(clock.remaining(COLORS::WHITE)), format_time(clock.remaining(COLORS::BLACK)));
+        }
+        if let Some(outcome) = self.get_outcome() {
+            println!("game over, {} ({})", outcome.result.to_pgn(), outcome.describe());
+        }
+    }
+
+    fn print_hint(&self) {
+        let hint = match &self.engine {
+            Some(engine) => engine.best_move(self.get_game()),
+            None => Engine::new(TACTICS_DEPTH).best_move(self.get_game()),
+        };
+        match hint {
+            Some(hint) => println!("hint: {}", move_to_san(&hint, self.get_game())),
+            None => println!("no moves to hint"),
+        }
+    }
+
+    fn print_moves(&self, square: &str) {
+        match self.legal_moves(square) {
+            Ok(sans) if sans.is_empty() => println!("no legal moves"),
+            Ok(sans) => println!("{}", sans.join(" ")),
+            Err(message) => println!("{}", message),
+        }
+    }
+
+    // The legal moves in SAN, only those of the piece on the square if one is given.
+    fn legal_moves(&self, square: &str) -> Result<Vec<String>, String> {
+        let from_tile = if square.is_empty() {
+            None
+        } else {
+            match pgn::parse_square(square) {
+                Some(tile) => Some(tile),
+                None => return Err(format!("{} is not a square, squares go from a1 to h8", square)),
+            }
+        };
+        Ok(self.get_game().generate_legal_moves().iter()
+            .filter(|chess_move| match from_tile {
+                Some(tile) => chess_move.from == tile && !chess_move.is_drop(),
+                None => true,
+            })
+            .map(|chess_move| move_to_san(chess_move, self.get_game()))
+            .collect())
+    }
+
+    // A resignation or draw agreement is taken back before any move, a fallen flag stays.
+    fn undo(&mut self) {
+        if self.clock.as_ref().is_some_and(|clock| clock.flagged().is_some()) {
+            println!("the time ran out, type new for another game");
+            return;
+        }
+        match self.record.undo() {
+            Some(GameEvent::Move(chess_move)) => self.redo.push(chess_move),
+            Some(_) => {
+                println!("the game goes on");
+                self.sync_clock();
+                return;
+            }
+            None => println!("nothing to undo"),
+        }
+        if self.is_engine_turn() {
+            if let Some(GameEvent::Move(chess_move)) = self.record.undo() {
+                self.redo.push(chess_move);
+            }
+        }
+        self.sync_clock();
+    }
+
+    // Moves played again don't press the clock, it goes on for the side to move.
+    fn redo(&mut self) {
+        let chess_move = match self.redo.pop() {
+            Some(chess_move) => chess_move,
+            None => {
+                println!("nothing to redo");
+                return;
+            }
+        };
+        let mut redo = std::mem::take(&mut self.redo);
+        let clock = self.clock.take();
+        self.play(chess_move);
+        if self.is_engine_turn() {
+            if let Some(chess_move) = redo.pop() {
+                self.play(chess_move);
+            }
+        }
+        self.redo = redo;
+        self.clock = clock;
+        self.sync_clock();
+    }
+
+    fn new_game(&mut self, fen: &str) {
+        let variant = self.get_game().get_variant();
+        let game = if fen.is_empty() {
+            init_variant_game(variant)
+        } else {
+            match game_from_fen(fen, variant) {
+                Ok(game) => game,
+                Err(error) => {
+                    println!("{}", error);
+                    return;
+                }
+            }
+        };
+        self.reset(game);
+    }
+
+    fn reset(&mut self, game: GAME) {
+        self.record = GameRecord::new(game);
+        self.redo.clear();
+        if let Some(clock) = &mut self.clock {
+            clock.reset();
+        }
+        self.sync_clock();
+    }
+
+    fn side_to_move(&self) -> u8 {
+        if self.get_game().is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK }
+    }
+
+    fn resign(&mut self) {
+        match self.record.resign(self.side_to_move()) {
+            Ok(()) => println!("{} resigns, {}", color_name(self.side_to_move()), self.get_outcome().map_or("", |outcome| outcome.result.to_pgn())),
+            Err(message) => println!("{}", message),
+        }
+        self.sync_clock();
+    }
+
+    // The engine takes a draw when it isn't better, between players the other side answers.
+    fn offer_draw(&mut self) {
+        let offering = self.side_to_move();
+        if let Err(message) = self.record.offer_draw(offering) {
+            println!("{}", message);
+            return;
+        }
+        if self.record.get_draw_offer().is_none() {
+            println!("draw agreed, 1/2-1/2");
+        } else if self.engine.is_some() && offering != self.engine_color {
+            // the offer comes on the player's turn, the engine's score is the other way around
+            let engine_score = -self.engine.as_ref().map_or(0, |engine| engine.search(self.get_game()).score);
+            let accepted = engine_score <= 0;
+            let _ = if accepted { self.record.accept_draw(self.engine_color) } else { self.record.decline_draw(self.engine_color) };
+            println!("{}", if accepted { "the engine accepts, 1/2-1/2" } else { "the engine declines the draw" });
+        } else {
+            println!("{} offers a draw, {} can accept, decline or move on", color_name(offering), color_name(opposite_color(offering)));
+        }
+        self.sync_clock();
+    }
+
+    fn answer_draw(&mut self, accept: bool) {
+        let answering = match self.record.get_draw_offer() {
+            Some(offering) => opposite_color(offering),
+            None => {
+                println!("there is no draw offer to answer");
+                return;
+            }
+        };
+        let answer = if accept { self.record.accept_draw(answering) } else { self.record.decline_draw(answering) };
+        match answer {
+            Ok(()) if accept => println!("draw agreed, 1/2-1/2"),
+            Ok(()) => println!("{} declines the draw", color_name(answering)),
+            Err(message) => println!("{}", message),
+        }
+        self.sync_clock();
+    }
+
+    fn to_pgn(&self) -> pgn::PgnGame {
+        let mut pgn_game = self.record.to_pgn();
+        if let Some(clock) = &self.clock {
+            pgn_game.tags.push(("TimeControl".to_string(), clock.get_control().to_pgn()));
+        }
+        pgn_game
+    }
+
+    fn save(&self, path: &str) {
+        if path.is_empty() {
+            println!("save needs a file name, like save game.pgn");
+            return;
+        }
+        match fs::write(path, pgn::write_pgn(&self.to_pgn())) {
+            Ok(()) => println!("saved to {}", path),
+            Err(error) => println!("could not write {}: {}", path, error),
+        }
+    }
+
+    fn load(&mut self, path: &str) {
+        if path.is_empty() {
+            println!("load needs a file name, like load game.pgn");
+            return;
+        }
+        let pgn_game = match pgn::read_pgn_file(path) {
+            Ok(games) if !games.is_empty() => games[0].clone(),
+            Ok(_) => {
+                println!("no game in {}", path);
+                return;
+            }
+            Err(error) => {
+                println!("could not read {}: {}", path, error);
+                return;
+            }
+        };
+        let start = match pgn_game.get_starting_game() {
+            Ok(start) => start,
+            Err(error) => {
+                println!("could not load {}: {}", path, error);
+                return;
+            }
+        };
+        self.reset(start);
+        let clock = self.clock.take();
+        for (index, san) in pgn_game.moves.iter().enumerate() {
+            match san_to_move(san, self.get_game()) {
+                Some(chess_move) => self.play(chess_move),
+                None => {
+                    println!("move {} ({}) can't be played, the game stops before it", index + 1, san);
+                    break;
+                }
+            }
+        }
+        if let Some(outcome) = GameOutcome::from_pgn(pgn_game.get_result(), pgn_game.tag("Termination")) {
+            if self.get_outcome().is_none() {
+                if let Err(error) = self.record.finish(outcome) {
+                    println!("the result is left out, {}", error);
+                }
+            }
+        }
+        self.clock = clock;
+        self.sync_clock();
+        println!("loaded {}", path);
+    }
+}
+
+fn color_name(color: u8) -> &'static str {
+    if color == COLORS::WHITE { "white" } else { "black" }
+}
+
+fn opposite_color(color: u8) -> u8 {
+    if color == COLORS::WHITE { COLORS::BLACK } else { COLORS::WHITE }
+}
+
+// Pawns from white's side, mates as the number of moves until mate.
+fn describe_score(white_score: i32) -> String {
+    if white_score.abs() >= MATE_SCORE - 1000 {
+        let moves = (MATE_SCORE - white_score.abs() + 1) / 2;
+        return format!("{}mate in {}", if white_score > 0 { "" } else { "-" }, moves);
+    }
+    if white_score.abs() >= TABLEBASE_WIN_SCORE - 1000 {
+        return format!("{} wins (tablebase)", if white_score > 0 { "white" } else { "black" });
+    }
+    format!("{:+.2}", white_score as f32 / 100.0)
+}
+
+// A move in SAN, in UCI or as two squares like "e2 e4". Promotions need the piece.
+pub fn parse_move(input: &str, game: &GAME) -> Result<Move, String> {
+    let input = input.trim();
+    if let Some(chess_move) = san_to_move(input, game) {
+        return Ok(chess_move);
+    }
+    if san_to_move(&format!("{}=Q", input), game).is_some() {
+        return Err(format!("{} promotes, add the piece like {}=Q", input, input));
+    }
+    let legal_moves = game.generate_legal_moves();
+    if let Some(chess_move) = legal_moves.iter().find(|chess_move| chess_move.to_uci() == input) {
+        return Ok(*chess_move);
+    }
+
+    let squares: String = input.split_whitespace().collect();
+    if squares.len() == 4 && squares.is_ascii() {
+        if let (Some(from), Some(to)) = (pgn::parse_square(&squares[..2]), pgn::parse_square(&squares[2..])) {
+            let matching: Vec<&Move> = legal_moves.iter().filter(|chess_move| chess_move.from == from && chess_move.to == to && !chess_move.is_drop()).collect();
+            match matching.first() {
+                Some(chess_move) if chess_move.promotion != TYPES::NONE => {
+                    return Err(format!("{} promotes, add the piece like {}q or {}=Q", squares, squares, &squares[2..]));
+                }
+                Some(chess_move) => return Ok(**chess_move),
+                None => {}
+            }
+        }
+    }
+    Err(format!("{} is not a legal move or command, type help for the commands", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uci(input: &str, game: &GAME) -> Result<String, String> {
+        parse_move(input, game).map(|chess_move| chess_move.to_uci())
+    }
+
+    #[test]
+    fn moves_in_san_uci_and_squares() {
+        let game = init_game();
+        assert_eq!(uci("Nf3", &game), Ok("g1f3".to_string()));
+        assert_eq!(uci("g1f3", &game), Ok("g1f3".to_string()));
+        assert_eq!(uci("e2 e4", &game), Ok("e2e4".to_string()));
+        assert_eq!(uci(" e4 ", &game), Ok("e2e4".to_string()));
+        assert!(uci("e5", &game).unwrap_err().ends_with("type help for the commands"));
+        assert!(uci("e2 e5", &game).is_err());
+    }
+
+    #[test]
+    fn promotions_need_the_piece() {
+        let game = init_game_from_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1");
+        assert_eq!(uci("a8", &game), Err("a8 promotes, add the piece like a8=Q".to_string()));
+        assert_eq!(uci("a7a8", &game), Err("a7a8 promotes, add the piece like a7a8=Q".to_string()));
+        assert_eq!(uci("a7 a8", &game), Err("a7a8 promotes, add the piece like a7a8q or a8=Q".to_string()));
+        assert_eq!(uci("a8=N", &game), Ok("a7a8n".to_string()));
+        assert_eq!(uci("a7a8r", &game), Ok("a7a8r".to_string()));
+    }
+
+    #[test]
+    fn legal_moves_of_a_square() {
+        let session = Session::new(init_game(), None, None);
+        assert_eq!(session.legal_moves("").unwrap().len(), 20);
+        let sorted = |square: &str| {
+            let mut sans = session.legal_moves(square).unwrap();
+            sans.sort();
+            sans
+        };
+        assert_eq!(sorted("e2"), ["e3", "e4"]);
+        assert_eq!(sorted("g1"), ["Nf3", "Nh3"]);
+        assert_eq!(session.legal_moves("e4"), Ok(Vec::new()));
+        assert!(session.legal_moves("z9").is_err());
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut session = Session::new(init_game(), None, None);
+        assert!(session.execute("e4"));
+        assert!(session.execute("e7e5"));
+        let after_e5 = session.get_game().get_fen();
+        session.execute("undo");
+        assert_eq!(session.get_game().get_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        session.execute("redo");
+        assert_eq!(session.get_game().get_fen(), after_e5);
+        // a new move forgets the moves to redo
+        session.execute("undo");
+        session.execute("c5");
+        session.execute("redo");
+        assert_eq!(session.get_game().get_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+        assert!(!session.execute("quit"));
+    }
+
+    #[test]
+    fn undo_and_redo_with_an_engine_take_a_move_of_each_side() {
+        let mut session = Session::new(init_game(), None, None).with_engine(Engine::new(1), COLORS::BLACK);
+        session.execute("e4");
+        session.engine_turn();
+        let after_reply = session.get_game().get_fen();
+        assert!(session.get_game().is_whites_turn());
+        session.execute("undo");
+        assert_eq!(session.get_game().get_fen(), init_game().get_fen());
+        session.execute("redo");
+        assert_eq!(session.get_game().get_fen(), after_reply);
+    }
+
+    #[test]
+    fn no_moves_once_the_game_is_over() {
+        let mut session = Session::new(init_game(), None, None);
+        session.execute("resign");
+        assert!(session.get_outcome().is_some());
+        session.execute("e4");
+        assert_eq!(session.get_game().get_fen(), init_game().get_fen());
+        // undo takes the resignation back
+        session.execute("undo");
+        assert!(session.get_outcome().is_none());
+        session.execute("e4");
+        assert_eq!(session.get_game().get_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+    }
+}
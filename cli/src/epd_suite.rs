@@ -0,0 +1,136 @@
+use chess_logic::engine::Engine;
+use chess_logic::epd::read_epd_file;
+use chess_logic::pgn::move_to_san;
+
+use std::convert::TryFrom;
+use std::process;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: cli epd FILE.epd [--depth N] [--movetime MS]";
+const DEFAULT_DEPTH: u8 = 3;
+
+// cli epd runs the engine on every position of an EPD file and checks its move against bm and am.
+pub fn run(args: &[String]) {
+    let mut path = None;
+    let mut depth = None;
+    let mut movetime = None;
+
+    let mut index = 0;
+    while index < args.len() {
+        match args[index].as_str() {
+            "--depth" => depth = Some(parse_depth(args.get(index + 1))),
+            "--movetime" => movetime = Some(Duration::from_millis(parse_number(args.get(index + 1)))),
+            arg if arg.starts_with("--") => exit_with_usage(&format!("unknown option {}", arg)),
+            arg => {
+                path = Some(arg.to_string());
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => exit_with_usage("an epd file is needed"),
+    };
+    let records = match read_epd_file(&path) {
+        Ok(records) => records,
+        Err(error) => {
+            eprintln!("could not read {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    // with only a movetime the depth is just a safety limit
+    let mut engine = match (depth, movetime) {
+        (Some(depth), _) => Engine::new(depth),
+        (None, Some(_)) => Engine::new(u8::MAX),
+        (None, None) => Engine::new(DEFAULT_DEPTH),
+    };
+    if let Some(movetime) = movetime {
+        engine = engine.with_movetime(movetime);
+    }
+
+    let started = Instant::now();
+    let mut solved = 0;
+    let mut tested = 0;
+    let mut skipped = 0;
+
+    for (number, record) in &records {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                println!("line {}: skipped, {}", number, error);
+                skipped += 1;
+                continue;
+            }
+        };
+        let id = record.get_id().map(|id| id.to_string()).unwrap_or_else(|| format!("line {}", number));
+        let game = record.get_game();
+        let (best_moves, avoid_moves) = match (record.get_best_moves(), record.get_avoid_moves()) {
+            (Ok(best_moves), Ok(avoid_moves)) => (best_moves, avoid_moves),
+            (Err(error), _) | (_, Err(error)) => {
+                println!("{}: skipped, {}", id, error);
+                skipped += 1;
+                continue;
+            }
+        };
+        if best_moves.is_empty() && avoid_moves.is_empty() {
+            println!("{}: skipped, no bm or am", id);
+            skipped += 1;
+            continue;
+        }
+
+        let result = engine.search(&game);
+        let played = match result.best_move {
+            Some(chess_move) => chess_move,
+            None => {
+                println!("{}: skipped, no legal moves", id);
+                skipped += 1;
+                continue;
+            }
+        };
+        tested += 1;
+
+        let is_solved = (best_moves.is_empty() || best_moves.contains(&played)) && !avoid_moves.contains(&played);
+        if is_solved {
+            solved += 1;
+        }
+
+        let mut expected = String::new();
+        if !best_moves.is_empty() {
+            let sans: Vec<String> = best_moves.iter().map(|chess_move| move_to_san(chess_move, &game)).collect();
+            expected.push_str(&format!(" bm {}", sans.join(" ")));
+        }
+        if !avoid_moves.is_empty() {
+            let sans: Vec<String> = avoid_moves.iter().map(|chess_move| move_to_san(chess_move, &game)).collect();
+            expected.push_str(&format!(" am {}", sans.join(" ")));
+        }
+        println!("{}: {} {} ({}) score {} depth {}",
+                 id, if is_solved { "solved" } else { "failed" }, move_to_san(&played, &game), expected.trim(), result.score, result.depth);
+    }
+
+    let percentage = if tested > 0 { solved as f32 * 100.0 / tested as f32 } else { 0.0 };
+    println!("solved {} of {} ({:.1}%), skipped {}, in {:.1}s", solved, tested, percentage, skipped, started.elapsed().as_secs_f32());
+}
+
+fn parse_number(value: Option<&String>) -> u64 {
+    match value.map(|value| value.parse::<u64>()) {
+        Some(Ok(number)) => number,
+        _ => exit_with_usage("--depth and --movetime expect a number"),
+    }
+}
+
+fn parse_depth(value: Option<&String>) -> u8 {
+    match u8::try_from(parse_number(value)) {
+        Ok(depth) if depth > 0 => depth,
+        _ => exit_with_usage("--depth goes from 1 to 255"),
+    }
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
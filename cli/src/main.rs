@@ -1,115 +1,245 @@
 
 use chess_logic::*;
+use chess_logic::book::{self, Book};
+use chess_logic::clock::{Clock, TimeControl};
+use chess_logic::engine::Engine;
+use chess_logic::syzygy::{self, Tablebase};
+use chess_logic::variant::Variant;
 
-use std::{collections::HashMap};
+use commands::Session;
+use render::Renderer;
+
+use std::env;
 use std::io::{self, BufRead};
+use std::time::Duration;
+
+mod batch;
+mod commands;
+mod epd_suite;
+mod puzzle_trainer;
+mod render;
+
+const TACTICS_DEPTH: u8 = 3;
+const PLAY_DEPTH: u8 = 3;
 
 fn main() {
-    let mut symbol_to_piece = HashMap::new();
-    symbol_to_piece.insert("bk", "\u{2654}");
-    symbol_to_piece.insert("bq", "\u{2655}");
-    symbol_to_piece.insert("br", "\u{2656}");
-    symbol_to_piece.insert("bb", "\u{2657}");
-    symbol_to_piece.insert("bn", "\u{2658}");
-    symbol_to_piece.insert("bp", "\u{2659}");
-
-    symbol_to_piece.insert("wk", "\u{265A}");
-    symbol_to_piece.insert("wq", "\u{265B}");
-    symbol_to_piece.insert("wr", "\u{265C}");
-    symbol_to_piece.insert("wb", "\u{265D}");
-    symbol_to_piece.insert("wn", "\u{265E}");
-    symbol_to_piece.insert("wp", "\u{265F}");
-
-    let mut game = init_game();                             // todo - half/fullmove, add moves played to list
-                                                            // check mate, draw, generate all possible moves
-                                                            // King doesnt know where it can go
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("epd") {
+        epd_suite::run(&args[2..]);
+        return;
+    }
+
+    if args.iter().any(|arg| arg == "--batch") {
+        batch::run(&args);
+        return;
+    }
+
+    if args.get(1).map(|arg| arg.as_str()) == Some("puzzle") {
+        puzzle_trainer::run(&args[2..]);
+        return;
+    }
+
+    let renderer = Renderer::from_args(&args);
+    let mut session = Session::new(start_game(), open_book(), open_tablebase());
+    if let Some((engine, engine_color)) = open_engine(&args) {
+        session = session.with_engine(engine, engine_color);
+        session.flipped = engine_color == COLORS::WHITE;
+        session.show_eval = args.iter().any(|arg| arg == "--eval");
+    }
+    if let Some(clock) = open_clock(&args) {
+        session = session.with_clock(clock);
+    }
+    println!("type a move like e4 or e2e4, or help for the commands");
+    session.engine_turn();
+    renderer.draw(session.get_game(), session.flipped, session.last_move());
 
     let stdin = io::stdin();
-    let mut iterator = stdin.lock().lines();
-
-    let mut running = true;
-    let mut command: String;
-
-    while running {
-        let board = game.get_board();
-        draw_chess_board(board, &mut symbol_to_piece);
-
-        command = iterator.next().unwrap().unwrap();
-        
-        if command == "quit" || command == "exit" {
-            running = false;
-        } else if command.chars().count() == 1 {                        // q = queen, r = rook, b = bishop, k = knight
-            let is_valid = promote_pawn(&command, &mut game);
-            println!("{}", "promoting");
-            if !is_valid {
-                println!("{}", "not a valid move");
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if !session.execute(&line) {
+            break;
+        }
+        session.engine_turn();
+        session.print_status();
+        renderer.draw(session.get_game(), session.flipped, session.last_move());
+    }
+}
+
+// cli --play white|black plays against the engine, searching --depth N plies or for --movetime MS milliseconds.
+// Returns the engine and the colour it plays.
+fn open_engine(args: &[String]) -> Option<(Engine, u8)> {
+    let index = args.iter().position(|arg| arg == "--play")?;
+    let engine_color = match args.get(index + 1).map(|side| side.as_str()) {
+        Some("white") => COLORS::BLACK,
+        Some("black") => COLORS::WHITE,
+        _ => {
+            println!("--play needs white or black, the side you play, playing both sides");
+            return None;
+        }
+    };
+    let option = |name: &str| {
+        let index = args.iter().position(|arg| arg == name)?;
+        match args.get(index + 1).map(|value| value.parse::<u64>()) {
+            Some(Ok(number)) => Some(number),
+            _ => {
+                println!("{} expects a number", name);
+                None
             }
-        } else if (command.chars().count() == 5) & (command.find(" ") == Some(2)) {
-            let from_to: Vec<&str> = command.split_whitespace().collect();
-            let is_valid = move_piece_from_to(from_to[0], from_to[1], &mut game);
-            if !is_valid {
-                println!("{}", "not a valid move");
+        }
+    };
+    let engine = match (option("--depth"), option("--movetime")) {
+        (depth, Some(movetime)) => Engine::new(depth.unwrap_or(u8::MAX as u64).min(u8::MAX as u64) as u8).with_movetime(Duration::from_millis(movetime)),
+        (Some(depth), None) => Engine::new(depth.min(u8::MAX as u64) as u8),
+        (None, None) => Engine::new(PLAY_DEPTH),
+    };
+    Some((engine, engine_color))
+}
+
+// cli --clock 5+3 plays with a clock: minutes, then seconds of increment. Delays are written 15d5 (simple)
+// and 15b5 (Bronstein), stages like 40/90+30:30+30 for 90 minutes for 40 moves, then 30 minutes.
+fn open_clock(args: &[String]) -> Option<Clock> {
+    let index = args.iter().position(|arg| arg == "--clock")?;
+    let text = args.get(index + 1).map(|text| text.as_str()).unwrap_or("");
+    match TimeControl::from_minutes(text) {
+        Some(control) => {
+            println!("playing {} ({} in PGN)", text, control.to_pgn());
+            Some(Clock::new(control))
+        }
+        None => {
+            println!("--clock needs a time control like 5+3, 15d5 or 40/90+30:30+30, playing without a clock");
+            None
+        }
+    }
+}
+
+// cli --variant <name> plays king of the hill (koth), three-check (3check), atomic, crazyhouse, antichess, horde or racingkings
+fn start_game() -> GAME {
+    let args: Vec<String> = env::args().collect();
+    let mut game = start_position(&args);
+    if let Some(index) = args.iter().position(|arg| arg == "--variant") {
+        match args.get(index + 1).and_then(|name| Variant::from_name(name)) {
+            Some(variant) => {
+                println!("playing {}", variant.get_name());
+                if !game.is_chess960() {
+                    game = init_variant_game(variant);
+                }
+                game.set_variant(variant);
             }
-        } else {
-            println!("{}", "not a valid input");
+            None => println!("variants are standard, koth, 3check, atomic, crazyhouse, antichess, horde and racingkings, playing standard chess"),
+        }
+    }
+    game
+}
+
+// cli --chess960 <index|random> starts from one of the 960 chess960 positions
+fn start_position(args: &[String]) -> GAME {
+    let index = match args.iter().position(|arg| arg == "--chess960") {
+        Some(index) if index + 1 < args.len() => args[index + 1].clone(),
+        _ => return init_game(),
+    };
+    let index = if index == "random" { chess960::random_start_position_index() } else { index.parse().unwrap_or(usize::MAX) };
+    match init_chess960_game(index) {
+        Some(game) => {
+            println!("chess960 position {}", index);
+            game
         }
-        
-        if game.is_check() {
-            println!("{}", "Check!!!");
+        None => {
+            println!("chess960 positions are numbered 0 to 959, starting a normal game");
+            init_game()
         }
+    }
+}
+
+// cli --book <path>, otherwise the CHESS_BOOK variable or book.bin
+fn open_book() -> Option<Book> {
+    let args: Vec<String> = env::args().collect();
+    let path = match args.iter().position(|arg| arg == "--book") {
+        Some(index) if index + 1 < args.len() => args[index + 1].clone(),
+        _ => book::book_path(),
+    };
+    match Book::open(&path) {
+        Ok(book) => Some(book),
+        Err(_) => {
+            println!("no opening book found at {}", path);
+            None
+        }
+    }
+}
 
-        // let available_moves = game.get_valid_moves(d2);  //list of valid moves
+// cli --syzygy <directory>, otherwise the SYZYGY_PATH variable
+fn open_tablebase() -> Option<Tablebase> {
+    let args: Vec<String> = env::args().collect();
+    let directory = match args.iter().position(|arg| arg == "--syzygy") {
+        Some(index) if index + 1 < args.len() => args[index + 1].clone(),
+        _ => syzygy::syzygy_path()?,
+    };
+    match Tablebase::open(&directory) {
+        Ok(tablebase) => Some(tablebase),
+        Err(_) => {
+            println!("no tablebase found at {}", directory);
+            None
+        }
+    }
+}
 
-        // let played_moves = game.get_played_moves(); // Vec<String>
-        // let is_check = game.is_check();  // bool
-        // let is_draw = game.is_draw(); // bool
-        // let is_check_mate = game.is_check_mate(); // bool
-        // let is_whites_turn = game.is_whites_turn(); // bool
-        // let (is_whites_turn, is_check, is_draw, is_check_mate) = game.get_game_status(); // bools
-        // println!("played_moves: {:?}, is in check: {}, is draw: {}, is checkmate: {}, is whites turn: {}", played_moves, is_check, is_draw, is_check_mate, is_whites_turn);
+fn print_tablebase_verdict(tablebase: &Option<Tablebase>, game: &GAME) {
+    let tablebase = match tablebase {
+        Some(tablebase) => tablebase,
+        None => {
+            println!("no tablebase loaded");
+            return;
+        }
+    };
+    let wdl = match tablebase.probe_wdl(game) {
+        Some(wdl) => wdl,
+        None => {
+            println!("position not in tablebase");
+            return;
+        }
+    };
+    match tablebase.probe_dtz(game) {
+        Some(dtz) if dtz != 0 => println!("{} for the side to move, dtz {}", wdl.describe(), dtz),
+        _ => println!("{} for the side to move", wdl.describe()),
     }
 }
 
-pub fn draw_chess_board(board: [u8; 64], symbol_to_piece: &mut HashMap<&str, &str>) {
-    let mut rank  = 1;
-    let mut file = 8;
-    println!("{}", "   A  B  C  D  E  F  G  H");
-    print!(" {}", file);
-    for piece in board {
-        if piece == 0 {
-            print!("|{} ", " ");
-        } else if is_black_king(piece) {
-            print!("|{} ", symbol_to_piece.get("bk").unwrap());
-        } else if is_black_queen(piece) {
-            print!("|{} ", symbol_to_piece.get("bq").unwrap());
-        } else if is_black_rook(piece) {
-            print!("|{} ", symbol_to_piece.get("br").unwrap());
-        } else if is_black_bishop(piece) {
-            print!("|{} ", symbol_to_piece.get("bb").unwrap());
-        } else if is_black_knight(piece) {
-            print!("|{} ", symbol_to_piece.get("bn").unwrap());
-        } else if is_black_pawn(piece) {
-            print!("|{} ", symbol_to_piece.get("bp").unwrap());
-        } else if is_white_king(piece) {
-            print!("|{} ", symbol_to_piece.get("wk").unwrap());
-        } else if is_white_queen(piece) {
-            print!("|{} ", symbol_to_piece.get("wq").unwrap());
-        } else if is_white_rook(piece) {
-            print!("|{} ", symbol_to_piece.get("wr").unwrap());
-        } else if is_white_bishop(piece) {
-            print!("|{} ", symbol_to_piece.get("wb").unwrap());
-        } else if is_white_knight(piece) {
-            print!("|{} ", symbol_to_piece.get("wn").unwrap());
-        } else if is_white_pawn(piece) {
-            print!("|{} ", symbol_to_piece.get("wp").unwrap());
+// The engine's move with the motifs behind it, or those of the position when there is no move.
+fn print_tactics(game: &GAME) {
+    let best_move = Engine::new(TACTICS_DEPTH).best_move(game);
+    let tags = match &best_move {
+        Some(best_move) => {
+            println!("best move {}", pgn::move_to_san(best_move, game));
+            tactics::find_tactics(game, Some(&[*best_move]))
         }
-        if rank % 8 == 0 {
-            file -= 1;
-            print!("|{}", "\n");
-            if file > 0 {
-            print!(" {}", file);
-            }
+        None => tactics::find_tactics(game, None),
+    };
+    if tags.is_empty() {
+        println!("no tactics found");
+    }
+    for tag in tags {
+        println!("{}", tag.describe());
+    }
+}
+
+fn print_book_moves(book: &Option<Book>, game: &GAME) {
+    let book = match book {
+        Some(book) => book,
+        None => {
+            println!("no opening book loaded");
+            return;
         }
-        rank += 1;
+    };
+    let book_moves = book.get_book_moves(game);
+    if book_moves.is_empty() {
+        println!("no book moves for this position");
+        return;
+    }
+    let total_weight: u32 = book_moves.iter().map(|(_, weight)| *weight as u32).sum();
+    for (book_move, weight) in book_moves {
+        let percentage = if total_weight > 0 { weight as f32 * 100.0 / total_weight as f32 } else { 0.0 };
+        println!("{} weight: {} ({:.1}%)", book_move.to_uci(), weight, percentage);
     }
 }
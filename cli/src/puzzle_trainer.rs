@@ -0,0 +1,228 @@
+use chess_logic::pgn::move_to_san;
+use chess_logic::puzzle::{read_puzzle_file, Puzzle};
+use chess_logic::tactics::is_checkmate;
+use chess_logic::*;
+
+use std::fs;
+use std::io::{self, BufRead};
+use std::process;
+
+use crate::commands::parse_move;
+use crate::render::{self, Renderer};
+
+const USAGE: &str = "usage: cli puzzle FILE.txt [--stats FILE] [--start N]";
+const DEFAULT_STATS_PATH: &str = "puzzle_stats.txt";
+
+// The solved and tried puzzles of every theme, the current streak and the best one so far.
+// Saved as lines like "streak 3", "best 7" and "theme fork 4 5".
+#[derive(Default)]
+struct Statistics {
+    streak: u32,
+    best_streak: u32,
+    themes: Vec<(String, u32, u32)>,                            // theme, solved, tried
+}
+
+impl Statistics {
+    fn load(path: &str) -> Statistics {
+        let mut statistics = Statistics::default();
+        let text = fs::read_to_string(path).unwrap_or_default();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["streak", streak] => statistics.streak = streak.parse().unwrap_or(0),
+                ["best", best] => statistics.best_streak = best.parse().unwrap_or(0),
+                ["theme", theme, solved, tried] => {
+                    statistics.themes.push((theme.to_string(), solved.parse().unwrap_or(0), tried.parse().unwrap_or(0)));
+                }
+                _ => {}
+            }
+        }
+        statistics
+    }
+
+    fn save(&self, path: &str) {
+        let mut text = format!("streak {}\nbest {}\n", self.streak, self.best_streak);
+        for (theme, solved, tried) in &self.themes {
+            text.push_str(&format!("theme {} {} {}\n", theme, solved, tried));
+        }
+        if let Err(error) = fs::write(path, text) {
+            println!("could not save the statistics to {}: {}", path, error);
+        }
+    }
+
+    fn record(&mut self, puzzle: &Puzzle, solved: bool) {
+        self.streak = if solved { self.streak + 1 } else { 0 };
+        self.best_streak = self.best_streak.max(self.streak);
+        for theme in &puzzle.themes {
+            let index = match self.themes.iter().position(|(name, _, _)| name == theme) {
+                Some(index) => index,
+                None => {
+                    self.themes.push((theme.clone(), 0, 0));
+                    self.themes.len() - 1
+                }
+            };
+            self.themes[index].1 += solved as u32;
+            self.themes[index].2 += 1;
+        }
+    }
+
+    fn print(&self) {
+        println!("streak {}, best streak {}", self.streak, self.best_streak);
+        for (theme, solved, tried) in &self.themes {
+            println!("  {}: {} of {} solved", theme, solved, tried);
+        }
+    }
+}
+
+enum Outcome {
+    Solved,
+    Failed,
+    Skipped,
+    Quit,
+}
+
+// cli puzzle plays the puzzles of a file one after the other. Moves are typed in SAN or UCI, the
+// opponent's replies are played for you and any mate counts, even one that isn't the solution.
+pub fn run(args: &[String]) {
+    let mut path = None;
+    let mut stats_path = DEFAULT_STATS_PATH.to_string();
+    let mut start = 1;
+
+    let mut index = 0;
+    while index < args.len() {
+        match args[index].as_str() {
+            "--stats" => match args.get(index + 1) {
+                Some(value) => stats_path = value.clone(),
+                None => exit_with_usage("--stats expects a file"),
+            },
+            "--start" => match args.get(index + 1).map(|value| value.parse::<usize>()) {
+                Some(Ok(number)) if number > 0 => start = number,
+                _ => exit_with_usage("--start expects a puzzle number from 1"),
+            },
+            arg if render::FLAGS.contains(&arg) => {
+                index += 1;
+                continue;
+            }
+            arg if arg.starts_with("--") => exit_with_usage(&format!("unknown option {}", arg)),
+            arg => {
+                path = Some(arg.to_string());
+                index += 1;
+                continue;
+            }
+        }
+        index += 2;
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => exit_with_usage("a puzzle file is needed"),
+    };
+    let puzzles = match read_puzzle_file(&path) {
+        Ok(puzzles) => puzzles,
+        Err(error) => {
+            eprintln!("could not read {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+
+    let renderer = Renderer::from_args(args);
+    let mut statistics = Statistics::load(&stats_path);
+    println!("{} puzzles, type a move, skip, stats or quit", puzzles.len());
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    for (number, puzzle) in puzzles.iter().enumerate().skip(start - 1) {
+        println!("puzzle {}", number + 1);
+        match play_puzzle(puzzle, &mut lines, &statistics, &renderer) {
+            Outcome::Solved => {
+                println!("solved!");
+                statistics.record(puzzle, true);
+            }
+            Outcome::Failed => statistics.record(puzzle, false),
+            Outcome::Skipped => continue,
+            Outcome::Quit => break,
+        }
+        statistics.save(&stats_path);
+        println!("streak {}", statistics.streak);
+    }
+    statistics.print();
+}
+
+fn play_puzzle(puzzle: &Puzzle, lines: &mut impl Iterator<Item = io::Result<String>>, statistics: &Statistics, renderer: &Renderer) -> Outcome {
+    let solution = match puzzle.get_solution_moves() {
+        Some(solution) => solution,
+        None => {
+            println!("the solution doesn't fit the position, skipping");
+            return Outcome::Skipped;
+        }
+    };
+    let mut game = puzzle.get_game();
+    let mut last_move = None;
+    let flipped = !game.is_whites_turn();                       // the solver's pieces at the bottom
+    println!("{} to move", if flipped { "black" } else { "white" });
+
+    let mut ply = 0;
+    while ply < solution.len() {
+        renderer.draw(&game, flipped, last_move);
+        let input = match lines.next() {
+            Some(Ok(line)) => line.trim().to_string(),
+            _ => return Outcome::Quit,
+        };
+        match input.as_str() {
+            "quit" | "exit" => return Outcome::Quit,
+            "skip" => {
+                print_solution(puzzle, &solution);
+                return Outcome::Skipped;
+            }
+            "stats" => {
+                statistics.print();
+                continue;
+            }
+            _ => {}
+        }
+
+        let chess_move = match parse_move(&input, &game) {
+            Ok(chess_move) => chess_move,
+            Err(message) => {
+                println!("{}", message);
+                continue;
+            }
+        };
+        let mut after = game.clone();
+        make_move(&chess_move, &mut after);
+        if is_checkmate(&after) {
+            return Outcome::Solved;
+        }
+        if chess_move != solution[ply] {
+            println!("{} is not it", move_to_san(&chess_move, &game));
+            print_solution(puzzle, &solution);
+            return Outcome::Failed;
+        }
+        game = after;
+        ply += 1;
+
+        if let Some(reply) = solution.get(ply) {
+            println!("{}", move_to_san(reply, &game));
+            make_move(reply, &mut game);
+            last_move = Some(*reply);
+            ply += 1;
+        }
+    }
+    Outcome::Solved
+}
+
+fn print_solution(puzzle: &Puzzle, solution: &[Move]) {
+    let mut game = puzzle.get_game();
+    let mut sans = Vec::new();
+    for chess_move in solution {
+        sans.push(move_to_san(chess_move, &game));
+        make_move(chess_move, &mut game);
+    }
+    println!("the solution was {} ({})", sans.join(" "), puzzle.themes.join(", "));
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,190 @@
+use chess_logic::*;
+
+use std::env;
+
+pub const FLAGS: [&str; 4] = ["--ascii", "--unicode", "--color", "--no-color"];
+const STYLE_VARIABLE: &str = "CHESS_BOARD";
+
+const RESET: &str = "\x1b[0m";
+const LIGHT_SQUARE: u8 = 223;                                   // 256 colour palette
+const DARK_SQUARE: u8 = 137;
+const LIGHT_LAST_MOVE: u8 = 187;
+const DARK_LAST_MOVE: u8 = 143;
+const CHECK_SQUARE: u8 = 167;
+const WHITE_PIECE: u8 = 231;
+const BLACK_PIECE: u8 = 16;
+
+// How the cli draws the board: letters or chess glyphs, with or without ANSI colours.
+// Set with --ascii, --unicode, --color and --no-color, or with the CHESS_BOARD environment variable,
+// like CHESS_BOARD=ascii for terminals without unicode or CHESS_BOARD=unicode,color. Flags win.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Renderer {
+    pub unicode: bool,
+    pub colors: bool,
+}
+
+impl Renderer {
+    pub fn from_args(args: &[String]) -> Renderer {
+        let mut renderer = Renderer { unicode: true, colors: false };
+        if let Ok(style) = env::var(STYLE_VARIABLE) {
+            for word in style.split(|c: char| c == ',' || c.is_whitespace()) {
+                renderer.apply(&word.to_ascii_lowercase());
+            }
+        }
+        for arg in args.iter().filter(|arg| FLAGS.contains(&arg.as_str())) {
+            renderer.apply(arg.trim_start_matches("--"));
+        }
+        renderer
+    }
+
+    fn apply(&mut self, word: &str) {
+        match word {
+            "ascii" => self.unicode = false,
+            "unicode" => self.unicode = true,
+            "color" | "colour" => self.colors = true,
+            "no-color" | "no-colour" | "plain" => self.colors = false,
+            _ => {}
+        }
+    }
+
+    // The board with coordinates on every side, seen from black when flipped. The squares of the
+    // last move are marked, and so is a king in check.
+    pub fn draw(&self, game: &GAME, flipped: bool, last_move: Option<Move>) {
+        print!("{}", self.render(game, flipped, last_move));
+    }
+
+    pub fn render(&self, game: &GAME, flipped: bool, last_move: Option<Move>) -> String {
+        let board = game.get_board();
+        let checked_king = if game.is_check() {
+            let color = if game.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+            board.iter().position(|piece| *piece == TYPES::KING + color)
+        } else {
+            None
+        };
+        let files: Vec<String> = (0..8).map(|column| {
+            let file = if flipped { 7 - column } else { column };
+            ((b'a' + file as u8) as char).to_string()
+        }).collect();
+        let files = files.join("  ");
+        let border = format!("  +{}+\n", "-".repeat(24));
+
+        let mut text = format!("    {}\n", files);
+        if !self.colors {
+            text.push_str(&border);
+        }
+        for row in 0..8 {
+            let rank = if flipped { 7 - row } else { row };     // rank 0 is the 8th rank, like the board
+            text.push_str(&format!("{} {}", 8 - rank, if self.colors { " " } else { "|" }));
+            for column in 0..8 {
+                let file = if flipped { 7 - column } else { column };
+                let tile = rank * 8 + file;
+                let in_last_move = last_move.is_some_and(|chess_move| chess_move.to == tile || (chess_move.from == tile && !chess_move.is_drop()));
+                text.push_str(&self.square(board[tile], (rank + file) % 2 == 1, in_last_move, checked_king == Some(tile)));
+            }
+            text.push_str(&format!("{} {}\n", if self.colors { " " } else { "|" }, 8 - rank));
+        }
+        if !self.colors {
+            text.push_str(&border);
+        }
+        text.push_str(&format!("    {}\n", files));
+        text
+    }
+
+    fn square(&self, piece: u8, dark: bool, in_last_move: bool, checked: bool) -> String {
+        if !self.colors {
+            let symbol = if piece == TYPES::NONE { if dark { ":".to_string() } else { ".".to_string() } } else { self.glyph(piece) };
+            return match (checked, in_last_move) {
+                (true, _) => format!("({})", symbol),
+                (false, true) => format!("[{}]", symbol),
+                (false, false) => format!(" {} ", symbol),
+            };
+        }
+        let background = match (checked, in_last_move, dark) {
+            (true, _, _) => CHECK_SQUARE,
+            (false, true, true) => DARK_LAST_MOVE,
+            (false, true, false) => LIGHT_LAST_MOVE,
+            (false, false, true) => DARK_SQUARE,
+            (false, false, false) => LIGHT_SQUARE,
+        };
+        let foreground = if piece & COLORS::WHITE > 0 { WHITE_PIECE } else { BLACK_PIECE };
+        let symbol = if piece == TYPES::NONE { " ".to_string() } else { self.glyph(piece) };
+        format!("\x1b[48;5;{};38;5;{};1m {} {}", background, foreground, symbol, RESET)
+    }
+
+    // With colours the filled glyphs are used for both sides, the foreground tells them apart.
+    fn glyph(&self, piece: u8) -> String {
+        let index = [TYPES::KING, TYPES::QUEEN, TYPES::ROOK, TYPES::BISHOP, TYPES::KNIGHT, TYPES::PAWN]
+            .iter().position(|piece_type| piece & piece_type > 0).unwrap_or(5);
+        if !self.unicode {
+            let letter = ['k', 'q', 'r', 'b', 'n', 'p'][index];
+            return if piece & COLORS::WHITE > 0 { letter.to_ascii_uppercase() } else { letter }.to_string();
+        }
+        // U+2654 is the white king and U+265A the black one, each followed by queen, rook, bishop, knight and pawn
+        let first = if piece & COLORS::BLACK > 0 || self.colors { 0x265A } else { 0x2654 };
+        std::char::from_u32(first + index as u32).unwrap_or('?').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASCII: Renderer = Renderer { unicode: false, colors: false };
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().collect()
+    }
+
+    #[test]
+    fn ascii_and_unicode_glyphs() {
+        let game = init_game();
+        let text = ASCII.render(&game, false, None);
+        let rows = lines(&text);
+        assert_eq!(rows[0], "    a  b  c  d  e  f  g  h");
+        assert_eq!(rows[2], "8 | r  n  b  q  k  b  n  r | 8");
+        assert_eq!(rows[5], "5 | :  .  :  .  :  .  :  . | 5");
+        assert_eq!(rows[9], "1 | R  N  B  Q  K  B  N  R | 1");
+
+        let text = Renderer { unicode: true, colors: false }.render(&game, false, None);
+        assert_eq!(lines(&text)[9], "1 | \u{2656}  \u{2658}  \u{2657}  \u{2655}  \u{2654}  \u{2657}  \u{2658}  \u{2656} | 1");
+        assert!(lines(&text)[2].contains('\u{265A}'));
+
+        // with colours both sides get the filled glyphs
+        let text = Renderer { unicode: true, colors: true }.render(&game, false, None);
+        assert!(!text.contains('\u{2654}') && text.contains('\u{265A}'));
+        assert!(text.contains(RESET));
+    }
+
+    #[test]
+    fn flipped_coordinates() {
+        let text = ASCII.render(&init_game(), true, None);
+        let rows = lines(&text);
+        assert_eq!(rows[0], "    h  g  f  e  d  c  b  a");
+        assert_eq!(rows[2], "1 | R  N  B  K  Q  B  N  R | 1");
+        assert_eq!(rows[9], "8 | r  n  b  k  q  b  n  r | 8");
+        assert_eq!(rows[11], rows[0]);
+    }
+
+    #[test]
+    fn last_move_and_check_markers() {
+        let mut game = init_game();
+        let chess_move = pgn::san_to_move("e4", &game).unwrap();
+        make_move(&chess_move, &mut game);
+        let text = ASCII.render(&game, false, Some(chess_move));
+        assert_eq!(lines(&text)[6], "4 | .  :  .  : [P] :  .  : | 4");
+        assert_eq!(lines(&text)[8], "2 | P  P  P  P [.] P  P  P | 2");
+
+        let game = init_game_from_fen("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1");
+        assert_eq!(lines(&ASCII.render(&game, false, None))[2], "8 | .  :  .  : (k) :  .  : | 8");
+    }
+
+    #[test]
+    fn flags_override_the_environment() {
+        env::set_var(STYLE_VARIABLE, "ascii,color");
+        assert_eq!(Renderer::from_args(&[]), Renderer { unicode: false, colors: true });
+        let args = ["--unicode".to_string(), "--no-color".to_string(), "--depth".to_string()];
+        assert_eq!(Renderer::from_args(&args), Renderer { unicode: true, colors: false });
+        env::remove_var(STYLE_VARIABLE);
+        assert_eq!(Renderer::from_args(&[]), Renderer { unicode: true, colors: false });
+    }
+}
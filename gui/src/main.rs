@@ -1,5 +1,9 @@
 use std::path;
 use chess_logic::*;
+use chess_logic::book::{Book, BookSelection};
+use chess_logic::clock::{format_time, Clock, TimeControl};
+use chess_logic::engine::Engine;
+use chess_logic::outcome::GameOutcome;
 use ggez;
 use ggez::event;
 use ggez::graphics;
@@ -7,10 +11,12 @@ use ggez::graphics::DrawParam;
 use ggez::graphics::Image;
 use ggez::{Context, GameResult};
 use ggez::input::mouse::MouseButton;
+use ggez::input::keyboard::{KeyCode, KeyMods};
 // use ggez::mint::Point2;
 use glam::Vec2;
 
 const WINDOWSIZE: f32 = 1000.0;
+const HINT_DEPTH: u8 = 3;
 
 struct SpriteSheet {
     pawn_white: graphics::Image,
@@ -27,6 +33,12 @@ struct SpriteSheet {
     king_black: graphics::Image,
 }
 
+// Setting up a position: S starts it from the current one, the clicked tiles get piece.
+struct Setup {
+    piece: u8,
+    before: chess_logic::GAME,                                  // put back with escape
+}
+
 struct MouseInfo {
     mouse_down: bool,
     mouse_button: MouseButton,
@@ -39,6 +51,10 @@ pub struct GAME {
     move_from: usize,
     move_to: usize,
     make_move: bool,
+    hint: Option<Move>,
+    show_analysis: bool,                                // pinned and hanging pieces of the side to move, toggled with A
+    promotion: Option<(usize, usize)>,                  // a pawn move waiting for Q, R, B or N
+    setup: Option<Setup>,
 }
 
 struct MainState {
@@ -46,6 +62,8 @@ struct MainState {
     pub current_cursor_position: Vec2,
     mouse_info: MouseInfo,
     game: GAME,
+    engine: Engine,
+    clock: Option<Clock>,                                       // set with --clock, like 5+3
 }
 
 impl MainState {
@@ -77,7 +95,13 @@ impl MainState {
                 move_from: 100,
                 move_to: 100,
                 make_move: false,
+                hint: None,
+                show_analysis: true,
+                promotion: None,
+                setup: None,
             },
+            engine: create_hint_engine(),
+            clock: create_clock(),
         }
     }
 }
@@ -97,6 +121,8 @@ impl event::EventHandler for MainState {
         let _err = render_graphical_board(ctx, self);
 
         let _err = render_pieces(ctx, self);
+
+        let _err = render_clocks(ctx, self);
         
         graphics::present(ctx)?;
         Ok(())
@@ -117,6 +143,161 @@ impl event::EventHandler for MainState {
         self.mouse_info.mouse_button = _button;
         // println!("Mouse button released: {:?}, x: {}, y: {}", _button, _x, _y);
     }
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        if self.game.setup.is_some() {
+            setup_key(self, keycode);
+        } else if let Some((from, to)) = self.game.promotion {
+            let piece_type = match keycode {
+                KeyCode::Q => TYPES::QUEEN,
+                KeyCode::R => TYPES::ROOK,
+                KeyCode::B => TYPES::BISHOP,
+                KeyCode::N => TYPES::KNIGHT,
+                KeyCode::Escape => {
+                    self.game.promotion = None;
+                    return;
+                }
+                _ => return,
+            };
+            self.game.promotion = None;
+            if make_move(&Move::new(from, to, piece_type), &mut self.game.logic) {
+                after_move(self);
+            }
+        } else if keycode == KeyCode::H {
+            self.game.hint = self.engine.best_move(&self.game.logic);
+            if let Some(hint) = &self.game.hint {
+                for tag in tactics::find_tactics(&self.game.logic, Some(&[*hint])) {
+                    println!("{}", tag.describe());
+                }
+            }
+        } else if keycode == KeyCode::A {
+            self.game.show_analysis = !self.game.show_analysis;
+        } else if keycode == KeyCode::S {
+            start_setup(self);
+        } else if keycode == KeyCode::Escape {
+            event::quit(ctx);
+        }
+    }
+}
+
+fn start_setup(state: &mut MainState) {
+    state.game.setup = Some(Setup { piece: TYPES::PAWN + COLORS::WHITE, before: state.game.logic.clone() });
+    state.game.move_from = 100;
+    state.game.move_to = 100;
+    state.game.hint = None;
+    if let Some(clock) = &mut state.clock {
+        clock.stop();
+    }
+    println!("{}", "setup: left click puts a piece and right click takes it away, P, N, B, R, Q and K choose the piece, C its colour,");
+    println!("{}", "T changes the side to move, X clears the board, enter plays from the position and escape goes back");
+}
+
+fn setup_key(state: &mut MainState, keycode: KeyCode) {
+    let setup = match &mut state.game.setup {
+        Some(setup) => setup,
+        None => return,
+    };
+    let color = setup.piece & (COLORS::WHITE | COLORS::BLACK);
+    let piece_type = match keycode {
+        KeyCode::P => TYPES::PAWN,
+        KeyCode::N => TYPES::KNIGHT,
+        KeyCode::B => TYPES::BISHOP,
+        KeyCode::R => TYPES::ROOK,
+        KeyCode::Q => TYPES::QUEEN,
+        KeyCode::K => TYPES::KING,
+        KeyCode::C => {
+            setup.piece ^= COLORS::WHITE | COLORS::BLACK;
+            return;
+        }
+        KeyCode::T => {
+            let side = if state.game.logic.is_whites_turn() { COLORS::BLACK } else { COLORS::WHITE };
+            state.game.logic.set_side_to_move(side);
+            println!("{} to move", if side == COLORS::WHITE { "white" } else { "black" });
+            return;
+        }
+        KeyCode::X => {
+            state.game.logic = chess_logic::GAME::empty();
+            return;
+        }
+        KeyCode::Return => {
+            match state.game.logic.finish_setup() {
+                Ok(()) => {
+                    println!("{}", state.game.logic.get_fen());
+                    state.game.setup = None;
+                    if let Some(clock) = &mut state.clock {
+                        clock.reset();
+                    }
+                    start_clock(state);
+                }
+                Err(problems) => {
+                    for problem in problems {
+                        println!("{}", problem.describe());
+                    }
+                }
+            }
+            return;
+        }
+        KeyCode::Escape => {
+            state.game.logic = setup.before.clone();
+            state.game.setup = None;
+            start_clock(state);
+            return;
+        }
+        _ => return,
+    };
+    setup.piece = piece_type + color;
+}
+
+// The clock runs for the side to move unless the game is over.
+fn start_clock(state: &mut MainState) {
+    let side = if state.game.logic.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+    if let Some(clock) = &mut state.clock {
+        if state.game.logic.get_outcome().is_none() {
+            clock.start(side);
+        }
+    }
+}
+
+// The hint plays from the opening book (CHESS_BOOK or book.bin) before it starts searching.
+fn create_hint_engine() -> Engine {
+    let engine = Engine::new(HINT_DEPTH);
+    match Book::open_default() {
+        Ok(book) => engine.with_book(book, BookSelection::WeightedRandom),
+        Err(_) => engine,
+    }
+}
+
+// gui --clock 5+3 plays with a clock, minutes and seconds of increment like the cli.
+fn create_clock() -> Option<Clock> {
+    let args: Vec<String> = std::env::args().collect();
+    let index = args.iter().position(|arg| arg == "--clock")?;
+    let control = args.get(index + 1).and_then(|text| TimeControl::from_minutes(text));
+    if control.is_none() {
+        println!("{}", "--clock needs a time control like 5+3, 15d5 or 40/90+30:30+30, playing without a clock");
+    }
+    let mut clock = Clock::new(control?);
+    clock.start(COLORS::WHITE);
+    Some(clock)
+}
+
+// Black's time above the board and white's below it, red for the side whose flag fell.
+fn render_clocks(ctx: &mut Context, state: &mut MainState) -> GameResult {
+    let clock = match &state.clock {
+        Some(clock) => clock,
+        None => return Ok(()),
+    };
+    for (color, y) in [(COLORS::BLACK, 20.0), (COLORS::WHITE, 940.0)] {
+        let text_color = if clock.flagged() == Some(color) {
+            graphics::Color::from_rgb(179, 57, 57)
+        } else if clock.running() == Some(color) {
+            graphics::Color::from_rgb(0, 0, 0)
+        } else {
+            graphics::Color::from_rgb(112, 111, 111)
+        };
+        let text = graphics::Text::new((format_time(clock.remaining(color)), graphics::Font::default(), 36.0));
+        let dst = ggez::mint::Point2 {x: 70.0, y};
+        graphics::draw(ctx, &text, DrawParam::new().dest(dst).color(text_color))?;
+    }
+    Ok(())
 }
 
 fn draw_piece(ctx: &mut Context, piece: &ggez::graphics::Image, file: usize, rank: usize) -> GameResult {
@@ -190,12 +371,43 @@ fn make_a_move(_ctx: &mut Context, state: & mut MainState) -> GameResult {
 
         let (from_tile, to_tile) = convert_move_to_logic_understandable(state, move_from, move_to);
 
+        // no more moves once a flag has fallen
+        if let Some(flagged) = state.clock.as_ref().and_then(|clock| clock.flagged()) {
+            let outcome = GameOutcome::timeout(&state.game.logic, flagged);
+            println!("{} {}", outcome.result.to_pgn(), outcome.describe());
+            return Ok(());
+        }
+
+        // the piece is chosen with a key before the move is played
+        if !state.game.logic.pending_promotion(move_from, move_to).is_empty() {
+            state.game.promotion = Some((move_from, move_to));
+            println!("{}", "promote to a queen, rook, bishop or knight: press Q, R, B or N (escape to take it back)");
+            return Ok(());
+        }
+
         let is_valid_move = move_piece_from_to(from_tile.as_str(), to_tile.as_str(), &mut state.game.logic);
+        if is_valid_move {
+            after_move(state);
+        }
     }
 
     Ok(())
 }
 
+fn after_move(state: &mut MainState) {
+    state.game.hint = None;
+    let outcome = state.game.logic.get_outcome();
+    if let Some(outcome) = outcome {
+        println!("{} {}", outcome.result.to_pgn(), outcome.describe());
+    }
+    if let Some(clock) = &mut state.clock {
+        clock.press();
+        if outcome.is_some() {
+            clock.stop();
+        }
+    }
+}
+
 fn convert_move_to_logic_understandable(state: & mut MainState, move_from: usize, move_to: usize) -> (String, String) {
     let index_to_alphabet = ['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H'];
 
@@ -224,6 +436,17 @@ fn handle_tile_selection(_ctx: &mut Context, state: & mut MainState) -> GameResu
 
     let pressed_tile_index = convert_coors_to_tile_index(pressed_position, state);
 
+    if let Some(setup) = &state.game.setup {
+        if mouse_down && pressed_tile_index < 64 {
+            if mouse_button == MouseButton::Left {
+                state.game.logic.put_piece(pressed_tile_index, setup.piece);
+            } else if mouse_button == MouseButton::Right {
+                state.game.logic.remove_piece(pressed_tile_index);
+            }
+        }
+        return Ok(());
+    }
+
     if mouse_down {
         if mouse_button == MouseButton::Left {
             if pressed_tile_index != state.game.move_from && pressed_tile_index != state.game.move_to {
@@ -271,13 +494,20 @@ fn render_graphical_board(ctx: &mut Context, state: &mut MainState) -> GameResul
 
     state.game.board_boundary = graphics::Rect::new(offset, offset, tile_size * 8.0, tile_size * 8.0);
 
+    let side_to_move = if state.game.logic.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+    let (pinned, hanging) = if state.game.show_analysis && state.game.setup.is_none() {
+        (state.game.logic.pinned_pieces(side_to_move), state.game.logic.hanging_pieces(side_to_move))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
     for file in 0..8 {
         for rank in 0..8 {
             let tile_index = (rank * 8 + file) as usize;
             let is_light_square = (file + rank) % 2 != 0;
 
             let bound= graphics::Rect { x: (tile_size * file as f32 + offset), y: (tile_size * rank as f32 + offset), w: tile_size, h: tile_size };
-            let square_color = manage_tile_color(state, bound, tile_index, is_light_square);
+            let square_color = manage_tile_color(state, bound, tile_index, is_light_square, &pinned, &hanging);
 
             let rect_mesh = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), bound, graphics::Color::from_rgb(square_color.0, square_color.1, square_color.2))?;
             let draw_param = graphics::DrawParam::default();
@@ -287,15 +517,33 @@ fn render_graphical_board(ctx: &mut Context, state: &mut MainState) -> GameResul
     Ok(())
 }
 
-fn manage_tile_color(state: &mut MainState, bound: graphics::Rect, tile_index: usize, is_light_square: bool) -> (u8, u8, u8) {
+fn manage_tile_color(state: &mut MainState, bound: graphics::Rect, tile_index: usize, is_light_square: bool, pinned: &[usize], hanging: &[usize]) -> (u8, u8, u8) {
     let hovering_tile_color = (179, 57, 57);
     let move_from_tile_color = (255, 82, 82);
     let move_to_tile_color = (179, 57, 57);
+    let hint_tile_color = (51, 217, 178);
+    let pinned_tile_color = (112, 111, 211);
+    let hanging_tile_color = (255, 121, 63);
 
     let light_color_tile = (255, 218, 121);
     let dark_color_tile = (204, 142, 53);
 
     let mut square_color = if is_light_square { light_color_tile }  else { dark_color_tile};
+
+    // Pinned and hanging tile colors
+    if pinned.contains(&tile_index) {
+        square_color = pinned_tile_color;
+    }
+    if hanging.contains(&tile_index) {
+        square_color = hanging_tile_color;
+    }
+
+    // Hint tile color
+    if let Some(hint) = state.game.hint {
+        if hint.from == tile_index || hint.to == tile_index {
+            square_color = hint_tile_color;
+        }
+    }
     let (x, y) = (state.current_cursor_position[0], state.current_cursor_position[1]);
 
     // Hovering tile color
@@ -3,8 +3,15 @@
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
 
+use chess_logic::*;
+use chess_logic::clock::{format_time, Clock, TimeControl};
+use chess_logic::outcome::GameRecord;
+use chess_logic::validation::game_from_fen;
+use chess_logic::variant::Variant;
+
 use std::env;
 use std::error::Error;
+use std::sync::{Arc, Mutex};
 
 use std::{num::ParseIntError};
 
@@ -15,17 +22,33 @@ pub fn decode_binary(s: &str) -> Result<Vec<u8>, ParseIntError> {
         .collect()
 }
 
+// The game both players connect to, with its clock when the server was started with a time control.
+struct Table {
+    record: GameRecord,
+    clock: Option<Clock>,
+}
+
+// server [address] [time control like 5+3 or 40/90+30:30+30]
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let addr = env::args()
         .nth(1)
         .unwrap_or_else(|| "127.0.0.1:1337".to_string());
+    let clock = env::args().nth(2).map(|text| {
+        TimeControl::from_minutes(&text).map(Clock::new)
+            .ok_or_else(|| format!("{} is not a time control, write it like 5+3 or 40/90+30:30+30", text))
+    }).transpose()?;
 
     let listener = TcpListener::bind(&addr).await?;
     println!("Listening on: {}", addr);
+    if let Some(clock) = &clock {
+        println!("Time control: {}", clock.get_control().to_pgn());
+    }
+    let table = Arc::new(Mutex::new(Table { record: GameRecord::new(init_game()), clock }));
 
     loop {
         let (mut socket, _) = listener.accept().await?;
+        let table = Arc::clone(&table);
 
         tokio::spawn(async move {
             let mut buf = vec![0; 1024];
@@ -44,7 +67,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
                 println!("{}", message);
 
-                let message = encode_message("rnbqkbnr/pppppppp/8/6P/6p/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string());
+                let reply = handle_message(&mut table.lock().unwrap(), message.trim_matches(|c: char| c.is_whitespace() || c == '\0'));
+                let message = encode_message(reply);
 
 
                 socket
@@ -56,6 +80,62 @@ async fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
+// "fen <FEN>" starts the game over from that position, anything else is a move.
+fn handle_message(table: &mut Table, message: &str) -> String {
+    match message.strip_prefix("fen ") {
+        Some(fen) => set_position(table, fen),
+        None => play_move(table, message),
+    }
+}
+
+// Positions that can't come up in a game are refused, the game goes on as it was.
+fn set_position(table: &mut Table, fen: &str) -> String {
+    match game_from_fen(fen.trim(), Variant::Standard) {
+        Ok(game) => {
+            table.record = GameRecord::new(game);
+            if let Some(clock) = &mut table.clock {
+                clock.reset();
+            }
+            table.record.get_game().get_fen()
+        }
+        Err(error) => error,
+    }
+}
+
+// Plays a move in UCI like e2e4 and answers with the position, and the clocks below it.
+// The clock starts with the first move, for the side that played it, a fallen flag ends the game.
+fn play_move(table: &mut Table, uci: &str) -> String {
+    if let Some(flagged) = table.clock.as_ref().and_then(|clock| clock.flagged()) {
+        let _ = table.record.lose_on_time(flagged);
+    }
+    let game = table.record.get_game();
+    let mover = if game.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+    let chess_move = game.generate_legal_moves().into_iter().find(|chess_move| chess_move.to_uci() == uci);
+    if let Some(chess_move) = chess_move {
+        if table.record.play(chess_move).is_ok() {
+            if let Some(clock) = &mut table.clock {
+                if clock.running().is_none() {
+                    clock.start(mover);                         // black after a fen with black to move
+                }
+                clock.press();
+            }
+        }
+    }
+    let outcome = table.record.get_outcome();
+    if let (Some(clock), Some(_)) = (&mut table.clock, outcome) {
+        clock.stop();
+    }
+
+    let mut reply = table.record.get_game().get_fen();
+    if let Some(clock) = &table.clock {
+        reply.push_str(&format!("\nwhite {} black {}", format_time(clock.remaining(COLORS::WHITE)), format_time(clock.remaining(COLORS::BLACK))));
+    }
+    if let Some(outcome) = outcome {
+        reply.push_str(&format!("\n{} {}", outcome.result.to_pgn(), outcome.describe()));
+    }
+    reply
+}
+
 fn decode_message(buf:  &Vec<u8>) -> String {
     let message: String = buf.iter().map(|x| char::from(*x)).collect();
     let message = message.lines().next().unwrap();
@@ -0,0 +1,416 @@
+use chess_logic::*;
+use chess_logic::engine::{Engine, MATE_SCORE};
+use chess_logic::outcome::GameOutcome;
+use chess_logic::pgn::move_to_san;
+
+use std::time::Duration;
+
+const EVAL_DEPTH: u8 = 2;
+const PROMOTION_PIECES: [(char, u8); 5] = [('q', TYPES::QUEEN), ('r', TYPES::ROOK), ('b', TYPES::BISHOP), ('n', TYPES::KNIGHT), ('k', TYPES::KING)];
+
+// The keys the app understands, the terminal's key events are turned into these so the
+// app can be driven without a terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Escape,
+    Char(char),
+}
+
+// What a square shows besides its piece, the first that applies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SquareState {
+    Cursor,
+    Selected,
+    Destination,                                                // a legal move of the selected piece goes there
+    Check,
+    LastMove,
+    Normal,
+}
+
+// The whole tui without the terminal: keys and passing time go in, the squares and panes come out.
+pub struct App {
+    pub game: GAME,
+    pub cursor: usize,
+    pub selected: Option<usize>,
+    pub promotion: Option<(usize, usize)>,                      // a pawn move waiting for the piece to promote to
+    pub flipped: bool,
+    pub show_eval: bool,
+    pub message: String,
+    pub quit: bool,
+    history: Vec<(GAME, Move)>,                                 // the position before every move played
+    clocks: [Duration; 2],                                      // thinking time of white and black
+    eval: Option<i32>,                                          // from white's side
+}
+
+impl App {
+    pub fn new(game: GAME) -> App {
+        let mut app = App {
+            game,
+            cursor: 52,                                         // e2
+            selected: None,
+            promotion: None,
+            flipped: false,
+            show_eval: true,
+            message: String::new(),
+            quit: false,
+            history: Vec::new(),
+            clocks: [Duration::ZERO; 2],
+            eval: None,
+        };
+        app.update_eval();
+        app
+    }
+
+    pub fn handle_key(&mut self, key: Key) {
+        self.message.clear();
+        if let Some((from, to)) = self.promotion {
+            match key {
+                Key::Char(symbol) => match self.promotion_choices().iter().find(|(piece_symbol, _)| *piece_symbol == symbol) {
+                    Some((_, piece_type)) => {
+                        self.promotion = None;
+                        self.play(Move::new(from, to, *piece_type));
+                    }
+                    None => self.message = self.promotion_prompt(),
+                },
+                Key::Escape => self.promotion = None,
+                _ => self.message = format!("{}, escape to take it back", self.promotion_prompt()),
+            }
+            return;
+        }
+
+        match key {
+            Key::Up => self.move_cursor(0, if self.flipped { 1 } else { -1 }),
+            Key::Down => self.move_cursor(0, if self.flipped { -1 } else { 1 }),
+            Key::Left => self.move_cursor(if self.flipped { 1 } else { -1 }, 0),
+            Key::Right => self.move_cursor(if self.flipped { -1 } else { 1 }, 0),
+            Key::Enter | Key::Char(' ') => self.choose_square(),
+            Key::Escape => self.selected = None,
+            Key::Char('u') => self.undo(),
+            Key::Char('f') => self.flipped = !self.flipped,
+            Key::Char('e') => {
+                self.show_eval = !self.show_eval;
+                self.update_eval();
+            }
+            Key::Char('n') => *self = App::new(init_variant_game(self.game.get_variant())),
+            Key::Char('q') => self.quit = true,
+            _ => self.message = "arrows move, enter picks, u undo, f flip, e eval, n new game, q quit".to_string(),
+        }
+    }
+
+    // Time passing for the side to move, until the game is over.
+    pub fn tick(&mut self, elapsed: Duration) {
+        if self.get_outcome().is_none() {
+            let side = if self.game.is_whites_turn() { 0 } else { 1 };
+            self.clocks[side] += elapsed;
+        }
+    }
+
+    pub fn square_state(&self, tile: usize) -> SquareState {
+        let checked_king = self.game.is_check() && self.game.get_board()[tile] == TYPES::KING + if self.game.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK };
+        let last_move = self.history.last().map(|(_, chess_move)| *chess_move);
+        if tile == self.cursor {
+            SquareState::Cursor
+        } else if self.selected == Some(tile) {
+            SquareState::Selected
+        } else if self.legal_destinations().contains(&tile) {
+            SquareState::Destination
+        } else if checked_king {
+            SquareState::Check
+        } else if last_move.is_some_and(|chess_move| chess_move.to == tile || (chess_move.from == tile && !chess_move.is_drop())) {
+            SquareState::LastMove
+        } else {
+            SquareState::Normal
+        }
+    }
+
+    pub fn legal_destinations(&self) -> Vec<usize> {
+        let from = match self.selected {
+            Some(from) => from,
+            None => return Vec::new(),
+        };
+        let mut destinations: Vec<usize> = self.game.generate_legal_moves().iter()
+            .filter(|chess_move| chess_move.from == from && !chess_move.is_drop())
+            .map(|chess_move| chess_move.to)
+            .collect();
+        destinations.dedup();
+        destinations
+    }
+
+    // Numbered move pairs like "1. e4 e5".
+    pub fn move_list(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (before, chess_move) in &self.history {
+            let san = move_to_san(chess_move, before);
+            if before.is_whites_turn() || lines.is_empty() {
+                let dots = if before.is_whites_turn() { "." } else { "..." };
+                lines.push(format!("{}{} {}", before.get_fullmove_number(), dots, san));
+            } else if let Some(line) = lines.last_mut() {
+                line.push(' ');
+                line.push_str(&san);
+            }
+        }
+        lines
+    }
+
+    // The pieces of color taken so far, en passant captures included.
+    pub fn captured(&self, color: u8) -> Vec<u8> {
+        let mut captured = Vec::new();
+        for (before, chess_move) in &self.history {
+            let board = before.get_board();
+            let piece = if chess_move.is_drop() {
+                TYPES::NONE
+            } else if board[chess_move.to] != TYPES::NONE {
+                board[chess_move.to]
+            } else if board[chess_move.from] & TYPES::PAWN > 0 && chess_move.from % 8 != chess_move.to % 8 {
+                board[if chess_move.to < chess_move.from { chess_move.to + 8 } else { chess_move.to - 8 }]
+            } else {
+                TYPES::NONE
+            };
+            if piece & color > 0 && !before.is_castling(chess_move) {
+                captured.push(piece);
+            }
+        }
+        captured
+    }
+
+    pub fn clock(&self, color: u8) -> Duration {
+        self.clocks[if color == COLORS::WHITE { 0 } else { 1 }]
+    }
+
+    pub fn eval_text(&self) -> String {
+        match (self.show_eval, self.eval) {
+            (false, _) => "eval off".to_string(),
+            (true, _) if self.get_outcome().is_some() => format!("eval {}", self.get_outcome().map_or("", |outcome| outcome.result.to_pgn())),
+            (true, None) => "eval -".to_string(),
+            (true, Some(score)) if score.abs() >= MATE_SCORE - 1000 => {
+                format!("eval {}mate in {}", if score > 0 { "" } else { "-" }, (MATE_SCORE - score.abs() + 1) / 2)
+            }
+            (true, Some(score)) => format!("eval {:+.2}", score as f32 / 100.0),
+        }
+    }
+
+    pub fn get_outcome(&self) -> Option<GameOutcome> {
+        self.game.get_outcome()
+    }
+
+    pub fn status(&self) -> String {
+        match self.get_outcome() {
+            Some(outcome) => format!("{}, {}", outcome.result.to_pgn(), outcome.describe()),
+            None if self.promotion.is_some() => self.promotion_prompt(),
+            None => format!("{} to move{}", if self.game.is_whites_turn() { "white" } else { "black" }, if self.game.is_check() { ", check" } else { "" }),
+        }
+    }
+
+    // The pieces the waiting pawn may become, kings too in antichess.
+    fn promotion_choices(&self) -> Vec<(char, u8)> {
+        let pieces = match self.promotion {
+            Some((from, to)) => self.game.pending_promotion(from, to),
+            None => Vec::new(),
+        };
+        PROMOTION_PIECES.iter().copied().filter(|(_, piece_type)| pieces.contains(piece_type)).collect()
+    }
+
+    // Like "promote to q, r, b or n".
+    fn promotion_prompt(&self) -> String {
+        let symbols: Vec<String> = self.promotion_choices().iter().map(|(symbol, _)| symbol.to_string()).collect();
+        match symbols.split_last() {
+            Some((last, rest)) if !rest.is_empty() => format!("promote to {} or {}", rest.join(", "), last),
+            _ => format!("promote to {}", symbols.join("")),
+        }
+    }
+
+    fn move_cursor(&mut self, file_step: i32, rank_step: i32) {
+        let file = ((self.cursor % 8) as i32 + file_step).clamp(0, 7);
+        let rank = ((self.cursor / 8) as i32 + rank_step).clamp(0, 7);
+        self.cursor = (rank * 8 + file) as usize;
+    }
+
+    fn choose_square(&mut self) {
+        if self.get_outcome().is_some() {
+            self.message = "the game is over, n starts a new one".to_string();
+            return;
+        }
+        let own_piece = self.game.get_board()[self.cursor] & if self.game.is_whites_turn() { COLORS::WHITE } else { COLORS::BLACK } > 0;
+        let from = match self.selected {
+            Some(from) if from == self.cursor => {
+                self.selected = None;
+                return;
+            }
+            Some(from) if !own_piece || self.game.is_castling(&Move::new(from, self.cursor, TYPES::NONE)) => from,
+            _ => {
+                if !own_piece {
+                    self.message = "pick one of your pieces".to_string();
+                } else {
+                    self.selected = Some(self.cursor);
+                    if self.legal_destinations().is_empty() {
+                        self.message = "that piece can't move".to_string();
+                    }
+                }
+                return;
+            }
+        };
+        let moves: Vec<Move> = self.game.generate_legal_moves().into_iter()
+            .filter(|chess_move| chess_move.from == from && chess_move.to == self.cursor && !chess_move.is_drop())
+            .collect();
+        match moves.first() {
+            None => self.message = "not a legal move".to_string(),
+            Some(chess_move) if chess_move.promotion != TYPES::NONE => self.promotion = Some((from, self.cursor)),
+            Some(chess_move) => self.play(*chess_move),
+        }
+    }
+
+    fn play(&mut self, chess_move: Move) {
+        let before = self.game.clone();
+        if make_move(&chess_move, &mut self.game) {
+            self.history.push((before, chess_move));
+            self.selected = None;
+            self.update_eval();
+        } else {
+            self.message = "not a legal move".to_string();
+        }
+    }
+
+    fn undo(&mut self) {
+        match self.history.pop() {
+            Some((before, _)) => {
+                self.game = before;
+                self.selected = None;
+                self.update_eval();
+            }
+            None => self.message = "nothing to undo".to_string(),
+        }
+    }
+
+    fn update_eval(&mut self) {
+        if !self.show_eval {
+            return;
+        }
+        let score = Engine::new(EVAL_DEPTH).search(&self.game).score;
+        self.eval = Some(if self.game.is_whites_turn() { score } else { -score });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chess_logic::variant::Variant;
+
+    // Without the engine evaluation, it isn't what these tests look at.
+    fn app(fen: &str) -> App {
+        let mut app = App::new(init_game_from_fen(fen));
+        app.show_eval = false;
+        app
+    }
+
+    fn pick(app: &mut App, square: &str) {
+        app.cursor = algebraic_notation_to_memory_location(square);
+        app.handle_key(Key::Enter);
+    }
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn cursor_follows_the_board_when_flipped() {
+        let mut app = app(START);
+        app.handle_key(Key::Up);
+        app.handle_key(Key::Right);
+        assert_eq!(app.cursor, algebraic_notation_to_memory_location("f3"));
+
+        app.handle_key(Key::Char('f'));
+        app.handle_key(Key::Up);
+        app.handle_key(Key::Right);
+        assert_eq!(app.cursor, algebraic_notation_to_memory_location("e2"));
+        app.handle_key(Key::Up);
+        app.handle_key(Key::Up);
+        assert_eq!(app.cursor, algebraic_notation_to_memory_location("e1"));
+    }
+
+    #[test]
+    fn select_shows_destinations_then_plays() {
+        let mut app = app(START);
+        pick(&mut app, "e2");
+        assert_eq!(app.selected, Some(algebraic_notation_to_memory_location("e2")));
+        let mut destinations = app.legal_destinations();
+        destinations.sort_unstable();
+        assert_eq!(destinations, vec![algebraic_notation_to_memory_location("e4"), algebraic_notation_to_memory_location("e3")]);
+        assert_eq!(app.square_state(algebraic_notation_to_memory_location("e3")), SquareState::Destination);
+
+        pick(&mut app, "e4");
+        assert_eq!(app.selected, None);
+        assert_eq!(app.game.get_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        assert_eq!(app.move_list(), vec!["1. e4"]);
+        assert_eq!(app.square_state(algebraic_notation_to_memory_location("e2")), SquareState::LastMove);
+
+        pick(&mut app, "e5");
+        assert_eq!(app.message, "pick one of your pieces");
+    }
+
+    #[test]
+    fn promotion_waits_for_the_piece() {
+        let mut app = app("8/P6k/8/8/8/8/8/K7 w - - 0 1");
+        pick(&mut app, "a7");
+        pick(&mut app, "a8");
+        let (a7, a8) = (algebraic_notation_to_memory_location("a7"), algebraic_notation_to_memory_location("a8"));
+        assert_eq!(app.promotion, Some((a7, a8)));
+        assert_eq!(app.status(), "promote to q, r, b or n");
+
+        app.handle_key(Key::Char('x'));
+        assert_eq!(app.promotion, Some((a7, a8)));
+        app.handle_key(Key::Escape);
+        assert_eq!(app.promotion, None);
+        assert_eq!(app.game.get_board()[a7], TYPES::PAWN + COLORS::WHITE);
+
+        pick(&mut app, "a8");
+        app.handle_key(Key::Char('n'));
+        assert_eq!(app.promotion, None);
+        assert_eq!(app.game.get_board()[a8], TYPES::KNIGHT + COLORS::WHITE);
+        assert_eq!(app.move_list(), vec!["1. a8=N"]);
+    }
+
+    #[test]
+    fn antichess_promotion_offers_the_king() {
+        let mut antichess = app("8/P7/8/8/8/8/8/7k w - - 0 1");
+        antichess.game.set_variant(Variant::Antichess);
+        pick(&mut antichess, "a7");
+        pick(&mut antichess, "a8");
+        assert_eq!(antichess.status(), "promote to q, r, b, n or k");
+        antichess.handle_key(Key::Char('k'));
+        assert_eq!(antichess.promotion, None);
+        assert_eq!(antichess.game.get_board()[algebraic_notation_to_memory_location("a8")], TYPES::KING + COLORS::WHITE);
+
+        // a king isn't offered in standard chess
+        let mut standard = app("8/P6k/8/8/8/8/8/K7 w - - 0 1");
+        pick(&mut standard, "a7");
+        pick(&mut standard, "a8");
+        standard.handle_key(Key::Char('k'));
+        assert_eq!(standard.message, "promote to q, r, b or n");
+        assert!(standard.promotion.is_some());
+    }
+
+    #[test]
+    fn undo_takes_back_the_last_move() {
+        let mut app = app(START);
+        pick(&mut app, "g1");
+        pick(&mut app, "f3");
+        app.handle_key(Key::Char('u'));
+        assert_eq!(app.game.get_fen(), START);
+        assert!(app.move_list().is_empty());
+        app.handle_key(Key::Char('u'));
+        assert_eq!(app.message, "nothing to undo");
+    }
+
+    #[test]
+    fn en_passant_counts_as_a_capture() {
+        let mut app = app("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2");
+        pick(&mut app, "e5");
+        pick(&mut app, "d6");
+        assert_eq!(app.captured(COLORS::BLACK), vec![TYPES::PAWN + COLORS::BLACK]);
+        assert!(app.captured(COLORS::WHITE).is_empty());
+        assert_eq!(app.game.get_board()[algebraic_notation_to_memory_location("d5")], TYPES::NONE);
+    }
+}
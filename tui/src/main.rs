@@ -0,0 +1,147 @@
+use chess_logic::*;
+use chess_logic::validation::game_from_fen;
+use chess_logic::variant::Variant;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use std::time::{Duration, Instant};
+
+mod app;
+
+use app::{App, Key, SquareState};
+
+const FRAME: Duration = Duration::from_millis(100);
+const PANE_COLUMN: u16 = 30;
+const MOVE_LIST_ROWS: usize = 10;
+
+// tui [--variant <name>] [--fen <fen>]
+fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1));
+    let variant = option("--variant").and_then(|name| Variant::from_name(name)).unwrap_or(Variant::Standard);
+    let game = match option("--fen") {
+        Some(fen) => match game_from_fen(fen, variant) {
+            Ok(game) => game,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        },
+        None => init_variant_game(variant),
+    };
+    let mut app = App::new(game);
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+    let result = run(&mut app, &mut stdout);
+    execute!(stdout, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn run(app: &mut App, stdout: &mut io::Stdout) -> io::Result<()> {
+    let mut last_frame = Instant::now();
+    while !app.quit {
+        draw(app, stdout)?;
+        if event::poll(FRAME)? {
+            if let Event::Key(key_event) = event::read()? {
+                if key_event.kind == KeyEventKind::Release {
+                    continue;
+                }
+                if key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL) {
+                    break;
+                }
+                if let Some(key) = to_key(key_event.code) {
+                    app.handle_key(key);
+                }
+            }
+        }
+        app.tick(last_frame.elapsed());
+        last_frame = Instant::now();
+    }
+    Ok(())
+}
+
+fn to_key(code: KeyCode) -> Option<Key> {
+    match code {
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        KeyCode::Left => Some(Key::Left),
+        KeyCode::Right => Some(Key::Right),
+        KeyCode::Enter => Some(Key::Enter),
+        KeyCode::Esc => Some(Key::Escape),
+        KeyCode::Char(character) => Some(Key::Char(character.to_ascii_lowercase())),
+        _ => None,
+    }
+}
+
+fn draw(app: &App, stdout: &mut io::Stdout) -> io::Result<()> {
+    queue!(stdout, Clear(ClearType::All))?;
+    let files: String = (0..8).map(|column| {
+        let file = if app.flipped { 7 - column } else { column };
+        format!(" {} ", (b'a' + file as u8) as char)
+    }).collect();
+    queue!(stdout, MoveTo(2, 0), Print(&files), MoveTo(2, 9), Print(&files))?;
+
+    let board = app.game.get_board();
+    for row in 0..8 {
+        let rank = if app.flipped { 7 - row } else { row };
+        queue!(stdout, MoveTo(0, row as u16 + 1), Print(8 - rank), MoveTo(27, row as u16 + 1), Print(8 - rank))?;
+        for column in 0..8 {
+            let file = if app.flipped { 7 - column } else { column };
+            let tile = rank * 8 + file;
+            let dark = (rank + file) % 2 == 1;
+            let background = match app.square_state(tile) {
+                SquareState::Cursor => Color::AnsiValue(74),
+                SquareState::Selected => Color::AnsiValue(71),
+                SquareState::Destination => if dark { Color::AnsiValue(107) } else { Color::AnsiValue(150) },
+                SquareState::Check => Color::AnsiValue(167),
+                SquareState::LastMove => if dark { Color::AnsiValue(143) } else { Color::AnsiValue(187) },
+                SquareState::Normal => if dark { Color::AnsiValue(137) } else { Color::AnsiValue(223) },
+            };
+            let piece = board[tile];
+            let foreground = if piece & COLORS::WHITE > 0 { Color::AnsiValue(231) } else { Color::AnsiValue(16) };
+            queue!(stdout, MoveTo(2 + column as u16 * 3, row as u16 + 1), SetBackgroundColor(background),
+                   SetForegroundColor(foreground), Print(format!(" {} ", glyph(piece))), ResetColor)?;
+        }
+    }
+
+    let mut pane = vec![
+        app.status(),
+        format!("white {}  black {}", clock_text(app.clock(COLORS::WHITE)), clock_text(app.clock(COLORS::BLACK))),
+        app.eval_text(),
+        format!("taken from white: {}", app.captured(COLORS::WHITE).iter().map(|piece| glyph(*piece)).collect::<String>()),
+        format!("taken from black: {}", app.captured(COLORS::BLACK).iter().map(|piece| glyph(*piece)).collect::<String>()),
+        String::new(),
+    ];
+    let moves = app.move_list();
+    pane.extend(moves.iter().skip(moves.len().saturating_sub(MOVE_LIST_ROWS)).cloned());
+    for (row, line) in pane.iter().enumerate() {
+        queue!(stdout, MoveTo(PANE_COLUMN, row as u16 + 1), Print(line))?;
+    }
+    queue!(stdout, MoveTo(0, 11), Print(&app.message))?;
+    queue!(stdout, MoveTo(0, 12), Print("arrows move, enter picks, u undo, f flip, e eval, n new game, q quit"))?;
+    stdout.flush()
+}
+
+fn glyph(piece: u8) -> char {
+    let index = match [TYPES::KING, TYPES::QUEEN, TYPES::ROOK, TYPES::BISHOP, TYPES::KNIGHT, TYPES::PAWN].iter().position(|piece_type| piece & piece_type > 0) {
+        Some(index) => index,
+        None => return ' ',
+    };
+    // the filled glyphs for both sides, the colour tells them apart
+    std::char::from_u32(0x265A + index as u32).unwrap_or('?')
+}
+
+fn clock_text(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}